@@ -1,10 +1,96 @@
-use rkyv::{Archive, Deserialize, Serialize};
+use rkyv::{Archive, Deserialize, Serialize, rancor};
 
 #[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy)]
+// Copy, so archived vertices can be uploaded without deserializing them first.
+#[rkyv(derive(Clone, Copy))]
 #[repr(C)]
 pub struct Vertex {
     pub position: [f32; 3],
     pub normal: [f32; 3],
+    pub tex_coord: [f32; 2],
+    // xyz is the tangent direction, w is the bitangent sign.
+    pub tangent: [f32; 4],
+    pub color: [f32; 4],
+}
+
+// An axis aligned box and a sphere that both enclose some vertices, in model space.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Bounds {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
+// A range of a model's index buffer drawn in one call, e.g. one glTF primitive.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+pub struct Submesh {
+    pub name: String,
+    pub first_index: u32,
+    pub index_count: u32,
+    // Added to each index before fetching from the model's vertices.
+    pub vertex_offset: u32,
+    // Index into the model's materials.
+    pub material: u32,
+    // Covers every vertex the submesh's indices refer to.
+    pub bounds: Bounds,
+}
+
+// A glTF metallic-roughness material. Texture fields index into the model's textures,
+// and each factor multiplies its texture, or stands alone if there isn't one.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+pub struct Material {
+    pub name: String,
+
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<u32>,
+
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    // Roughness in green, metallic in blue.
+    pub metallic_roughness_texture: Option<u32>,
+
+    pub normal_texture: Option<u32>,
+    // Scales the tangent space X and Y of the normal map.
+    pub normal_scale: f32,
+
+    // Ambient occlusion in red.
+    pub occlusion_texture: Option<u32>,
+    pub occlusion_strength: f32,
+
+    pub emissive_factor: [f32; 3],
+    pub emissive_texture: Option<u32>,
+}
+
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    // Infinitely far away, so only its direction matters.
+    Directional,
+    Point,
+    // Full intensity inside the inner cone, fading out to nothing at the outer cone.
+    // Angles are in radians from the light's direction.
+    Spot {
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+// A glTF KHR_lights_punctual light.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+pub struct Light {
+    pub name: String,
+    pub kind: LightKind,
+    // Linear RGB.
+    pub color: [f32; 3],
+    // Candela for point and spot lights, lux for directional ones.
+    pub intensity: f32,
+    // Distance where the light fades out completely. Infinite if unset.
+    pub range: Option<f32>,
+
+    // Both in model space. Directional lights ignore position.
+    pub position: [f32; 3],
+    // The way the light shines, not the way to the light.
+    pub direction: [f32; 3],
 }
 
 #[derive(Archive, Serialize, Deserialize)]
@@ -12,4 +98,239 @@ pub struct Model {
     pub name: String,
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
+    pub submeshes: Vec<Submesh>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<Texture>,
+    pub lights: Vec<Light>,
+}
+
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFormat {
+    // Color data, like base color. Converted to linear when sampled.
+    Rgba8Srgb,
+    // Everything else, like normal maps.
+    Rgba8Unorm,
+}
+
+// One level of a texture's mip chain, as a range of the texture's data.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+pub struct TextureMip {
+    pub width: u32,
+    pub height: u32,
+    pub offset: u32,
+    pub size: u32,
+}
+
+#[derive(Archive, Serialize, Deserialize)]
+pub struct Texture {
+    pub name: String,
+    pub format: TextureFormat,
+    // Largest first, down to 1x1.
+    pub mips: Vec<TextureMip>,
+    // Every mip level back to back, as tightly packed RGBA8 rows.
+    pub data: Vec<u8>,
+}
+
+// Where rsrc writes the manifest, relative to its output dir.
+pub const MANIFEST_FILE_NAME: &str = "manifest.bin";
+
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetKind {
+    VertexShader,
+    FragmentShader,
+    Model,
+    Texture,
+    // Copied over as is.
+    File,
+}
+
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestEntry {
+    // What the asset is looked up by, e.g. "models/jerma" or "shader/a.vert".
+    pub name: String,
+    pub kind: AssetKind,
+    // Relative to the output dir, with forward slashes.
+    pub path: String,
+    pub size: u64,
+    // xxh3-128 of the output file.
+    pub hash: u128,
+}
+
+// Everything rsrc put in an output dir.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Manifest {
+    // Sorted by name.
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn get(&self, name: &str) -> Option<&ManifestEntry> {
+        self.entries
+            .binary_search_by(|entry| entry.name.as_str().cmp(name))
+            .ok()
+            .map(|idx| &self.entries[idx])
+    }
+}
+
+// Pack files start with a `PackHeader`, followed by every entry and then the archived `PackToc`.
+pub const PACK_MAGIC: [u8; 8] = *b"URBRSPAK";
+// Bump this whenever the layout of a pack changes.
+pub const PACK_VERSION: u32 = 1;
+// Entries and the table of contents start on a multiple of this, so archived data in a
+// memory-mapped pack can be accessed in place.
+pub const PACK_ALIGNMENT: u64 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackHeader {
+    pub version: u32,
+    // Where the archived `PackToc` is, from the start of the file.
+    pub toc_offset: u64,
+    pub toc_size: u64,
+}
+
+impl PackHeader {
+    // Magic, version, 4 bytes of padding, then the table of contents' offset and size.
+    pub const SIZE: usize = 32;
+
+    pub fn new(toc_offset: u64, toc_size: u64) -> Self {
+        Self {
+            version: PACK_VERSION,
+            toc_offset,
+            toc_size,
+        }
+    }
+
+    // Little endian, no matter where it was written.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..8].copy_from_slice(&PACK_MAGIC);
+        bytes[8..12].copy_from_slice(&self.version.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.toc_offset.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.toc_size.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        let bytes: &[u8; Self::SIZE] = bytes
+            .get(..Self::SIZE)
+            .and_then(|header| header.try_into().ok())
+            .ok_or("file is too small to be a pack")?;
+
+        if bytes[0..8] != PACK_MAGIC {
+            return Err("not a pack file");
+        }
+
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+
+        let header = Self {
+            version: u32_at(8),
+            toc_offset: u64_at(16),
+            toc_size: u64_at(24),
+        };
+        if header.version != PACK_VERSION {
+            return Err("pack was written by an incompatible version of rsrc");
+        }
+
+        Ok(header)
+    }
+}
+
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    // Raw LZ4 block, without a frame around it.
+    Lz4,
+}
+
+#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+pub struct PackEntry {
+    // Same as in the manifest the pack was made from.
+    pub name: String,
+    pub kind: AssetKind,
+    // From the start of the file. Always a multiple of `PACK_ALIGNMENT`.
+    pub offset: u64,
+    // What's stored in the pack, which is smaller than `uncompressed_size` if it's compressed.
+    pub size: u64,
+    pub uncompressed_size: u64,
+    pub compression: Compression,
+    // xxh3-128 of the uncompressed data.
+    pub hash: u128,
+}
+
+// Everything in a pack file.
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PackToc {
+    // Sorted by name.
+    pub entries: Vec<PackEntry>,
+}
+
+impl PackToc {
+    pub fn get(&self, name: &str) -> Option<&PackEntry> {
+        self.entries
+            .binary_search_by(|entry| entry.name.as_str().cmp(name))
+            .ok()
+            .map(|idx| &self.entries[idx])
+    }
+}
+
+// Lays a pack out in memory. Entries have to be added in order of name.
+pub struct PackWriter {
+    bytes: Vec<u8>,
+    toc: PackToc,
+}
+
+impl Default for PackWriter {
+    fn default() -> Self {
+        // The header goes in last, once we know where the table of contents ends up.
+        Self {
+            bytes: vec![0; PackHeader::SIZE],
+            toc: PackToc::default(),
+        }
+    }
+}
+
+impl PackWriter {
+    fn pad_to_alignment(&mut self) {
+        let aligned = (self.bytes.len() as u64).next_multiple_of(PACK_ALIGNMENT);
+        self.bytes.resize(aligned as usize, 0);
+    }
+
+    // `compressed` is `data` after LZ4, if that's what should be stored.
+    pub fn add(
+        &mut self,
+        name: String,
+        kind: AssetKind,
+        data: &[u8],
+        compressed: Option<&[u8]>,
+        hash: u128,
+    ) {
+        let stored = compressed.unwrap_or(data);
+
+        self.pad_to_alignment();
+        self.toc.entries.push(PackEntry {
+            name,
+            kind,
+            offset: self.bytes.len() as u64,
+            size: stored.len() as u64,
+            uncompressed_size: data.len() as u64,
+            compression: match compressed {
+                Some(_) => Compression::Lz4,
+                None => Compression::None,
+            },
+            hash,
+        });
+        self.bytes.extend_from_slice(stored);
+    }
+
+    pub fn finish(mut self) -> Result<Vec<u8>, rancor::Error> {
+        let toc = rkyv::to_bytes::<rancor::Error>(&self.toc)?;
+
+        self.pad_to_alignment();
+        let header = PackHeader::new(self.bytes.len() as u64, toc.len() as u64);
+        self.bytes.extend_from_slice(&toc);
+        self.bytes[..PackHeader::SIZE].copy_from_slice(&header.to_bytes());
+
+        Ok(self.bytes)
+    }
 }
@@ -2,29 +2,219 @@
 
 layout (location = 0) in vec3 ssPosition;
 layout (location = 1) in vec3 ssNormal;
+layout (location = 2) in vec2 texCoord;
+layout (location = 3) in vec4 ssTangent;
+layout (location = 4) in vec4 color;
 
 layout(set = 0, binding = 0) uniform GlobalSceneData {
 	mat4 view;
 	mat4 proj;
 	mat4 vp;
+	uint lightCount;
+	// Index of the light the shadow map is for, or -1 if there isn't one.
+	int shadowLight;
+	// View space to each cascade's clip space.
+	mat4 shadowMatrices[4];
+	// How far from the camera each cascade reaches.
+	vec4 cascadeSplits;
 } globalSceneData;
 
+const uint LIGHT_DIRECTIONAL = 0;
+const uint LIGHT_POINT = 1;
+const uint LIGHT_SPOT = 2;
+
+// Positions and directions are in world space.
+struct Light {
+	vec3 position;
+	// 0 if infinite.
+	float range;
+	// The way the light shines.
+	vec3 direction;
+	uint kind;
+	// Color premultiplied by intensity.
+	vec3 radiance;
+	float spotScale;
+	float spotOffset;
+};
+
+layout(std430, set = 0, binding = 1) readonly buffer LightData {
+	Light lights[];
+} lightData;
+
+layout(set = 0, binding = 2) uniform sampler2DArrayShadow shadowMap;
+
+layout(set = 1, binding = 0) uniform MaterialData {
+	vec4 baseColorFactor;
+	vec4 emissiveFactor;
+	float metallicFactor;
+	float roughnessFactor;
+	float normalScale;
+	float occlusionStrength;
+} material;
+
+layout(set = 1, binding = 1) uniform sampler2D baseColorTexture;
+// Roughness in green, metallic in blue.
+layout(set = 1, binding = 2) uniform sampler2D metallicRoughnessTexture;
+layout(set = 1, binding = 3) uniform sampler2D normalTexture;
+layout(set = 1, binding = 4) uniform sampler2D occlusionTexture;
+layout(set = 1, binding = 5) uniform sampler2D emissiveTexture;
+
 layout (location = 0) out vec4 outFragColor;
 
-const vec3 LIGHT_DIR = normalize(vec3(-1, -1, -1));
+const float PI = 3.14159265359;
 
-const vec3 ALBEDO = vec3(1.0);
 const float AMBIENT = 0.01;
 
-void main() 
+// Below this, highlights get small enough to alias badly.
+const float MIN_ROUGHNESS = 0.04;
+
+// GGX normal distribution.
+float distributionGGX(float nDotH, float alpha)
+{
+	float alpha2 = alpha * alpha;
+	float denom = nDotH * nDotH * (alpha2 - 1.0) + 1.0;
+
+	return alpha2 / (PI * denom * denom);
+}
+
+// Height correlated Smith visibility, with the 4 * nDotL * nDotV denominator folded in.
+float visibilitySmithGGX(float nDotV, float nDotL, float alpha)
+{
+	float alpha2 = alpha * alpha;
+	float ggxV = nDotL * sqrt(nDotV * nDotV * (1.0 - alpha2) + alpha2);
+	float ggxL = nDotV * sqrt(nDotL * nDotL * (1.0 - alpha2) + alpha2);
+
+	return 0.5 / max(ggxV + ggxL, 1e-5);
+}
+
+vec3 fresnelSchlick(float vDotH, vec3 f0)
 {
-	vec3 ssLightDir = normalize((vec4(LIGHT_DIR, 0.0) * globalSceneData.view).xyz);
+	return f0 + (1.0 - f0) * pow(1.0 - vDotH, 5.0);
+}
 
+vec3 getNormal()
+{
 	vec3 normal = normalize(ssNormal);
-	float diffuseFac = clamp(dot(normal, ssLightDir), 0.0, 1.0);
-	vec3 diffuse = ALBEDO * diffuseFac;
 
-	vec3 ambient = AMBIENT * ALBEDO;
+	// Re-orthogonalize, interpolation doesn't keep the tangent frame square.
+	vec3 tangent = normalize(ssTangent.xyz - normal * dot(normal, ssTangent.xyz));
+	vec3 bitangent = cross(normal, tangent) * ssTangent.w;
+
+	vec3 tsNormal = texture(normalTexture, texCoord).xyz * 2.0 - 1.0;
+	tsNormal.xy *= material.normalScale;
+
+	return normalize(mat3(tangent, bitangent, normal) * tsNormal);
+}
+
+// How many texels out to sample in each direction for percentage closer filtering. Each lookup
+// filters a 2x2 block on its own, so this covers a 4x4 texel area.
+const int PCF_RADIUS = 1;
+
+// Fraction of light reaching a view space position from the shadowed light.
+float shadowFactor(vec3 position)
+{
+	float dist = -position.z;
+
+	int cascade = 0;
+	while (cascade < 4 && dist > globalSceneData.cascadeSplits[cascade]) {
+		cascade++;
+	}
+
+	// Past the last cascade, so nothing shadows it.
+	if (cascade == 4) {
+		return 1.0;
+	}
+
+	vec4 shadowPosition = globalSceneData.shadowMatrices[cascade] * vec4(position, 1.0);
+	vec3 projected = shadowPosition.xyz / shadowPosition.w;
+	vec2 uv = projected.xy * 0.5 + 0.5;
+
+	vec2 texelSize = 1.0 / vec2(textureSize(shadowMap, 0).xy);
+
+	float lit = 0.0;
+	for (int x = -PCF_RADIUS; x <= PCF_RADIUS; x++) {
+		for (int y = -PCF_RADIUS; y <= PCF_RADIUS; y++) {
+			vec2 offset = vec2(x, y) * texelSize;
+			lit += texture(shadowMap, vec4(uv + offset, cascade, projected.z));
+		}
+	}
+
+	float samples = float((2 * PCF_RADIUS + 1) * (2 * PCF_RADIUS + 1));
+	return lit / samples;
+}
+
+// The radiance arriving at a view space position, and the view space direction towards its source.
+vec3 incomingLight(Light light, vec3 position, out vec3 lightDir)
+{
+	mat4 view = globalSceneData.view;
+
+	if (light.kind == LIGHT_DIRECTIONAL) {
+		lightDir = normalize(-(view * vec4(light.direction, 0.0)).xyz);
+		return light.radiance;
+	}
+
+	vec3 toLight = (view * vec4(light.position, 1.0)).xyz - position;
+	float dist2 = max(dot(toLight, toLight), 1e-4);
+	lightDir = toLight * inversesqrt(dist2);
+
+	// Inverse square falloff, smoothly windowed to zero at the range like the glTF spec suggests.
+	float attenuation = 1.0 / dist2;
+	if (light.range > 0.0) {
+		float ratio = dist2 / (light.range * light.range);
+		attenuation *= clamp(1.0 - ratio * ratio, 0.0, 1.0);
+	}
+
+	if (light.kind == LIGHT_SPOT) {
+		vec3 spotDir = normalize((view * vec4(light.direction, 0.0)).xyz);
+		float cone = clamp(dot(spotDir, -lightDir) * light.spotScale + light.spotOffset, 0.0, 1.0);
+		attenuation *= cone * cone;
+	}
+
+	return light.radiance * attenuation;
+}
+
+void main() 
+{
+	vec4 baseColor = material.baseColorFactor * color * texture(baseColorTexture, texCoord);
+
+	vec4 metallicRoughness = texture(metallicRoughnessTexture, texCoord);
+	float metallic = clamp(material.metallicFactor * metallicRoughness.b, 0.0, 1.0);
+	float roughness = clamp(material.roughnessFactor * metallicRoughness.g, MIN_ROUGHNESS, 1.0);
+	float alpha = roughness * roughness;
+
+	float occlusion = 1.0 + material.occlusionStrength * (texture(occlusionTexture, texCoord).r - 1.0);
+	vec3 emissive = material.emissiveFactor.rgb * texture(emissiveTexture, texCoord).rgb;
+
+	vec3 normal = getNormal();
+	vec3 viewDir = normalize(-ssPosition);
+	float nDotV = clamp(abs(dot(normal, viewDir)), 1e-5, 1.0);
+
+	// Dielectrics reflect about 4% head on, metals tint their reflections with the base color.
+	vec3 f0 = mix(vec3(0.04), baseColor.rgb, metallic);
+
+	vec3 direct = vec3(0.0);
+	for (uint i = 0; i < globalSceneData.lightCount; i++) {
+		vec3 lightDir;
+		vec3 radiance = incomingLight(lightData.lights[i], ssPosition, lightDir);
+		if (int(i) == globalSceneData.shadowLight) {
+			radiance *= shadowFactor(ssPosition);
+		}
+
+		vec3 halfway = normalize(viewDir + lightDir);
+
+		float nDotL = clamp(dot(normal, lightDir), 0.0, 1.0);
+		float nDotH = clamp(dot(normal, halfway), 0.0, 1.0);
+		float vDotH = clamp(dot(viewDir, halfway), 0.0, 1.0);
+
+		vec3 fresnel = fresnelSchlick(vDotH, f0);
+
+		vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * baseColor.rgb / PI;
+		vec3 specular = fresnel * distributionGGX(nDotH, alpha) * visibilitySmithGGX(nDotV, nDotL, alpha);
+
+		direct += (diffuse + specular) * radiance * nDotL;
+	}
+
+	vec3 ambient = AMBIENT * baseColor.rgb * occlusion;
 
-	outFragColor = vec4(diffuse + ambient, 1.0);
-}
\ No newline at end of file
+	outFragColor = vec4(direct + ambient + emissive, 1.0);
+}
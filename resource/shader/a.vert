@@ -2,6 +2,9 @@
 
 layout (location = 0) in vec3 position;
 layout (location = 1) in vec3 normal;
+layout (location = 2) in vec2 texCoord;
+layout (location = 3) in vec4 tangent;
+layout (location = 4) in vec4 color;
 
 layout(set = 0, binding = 0) uniform GlobalSceneData {
 	mat4 view;
@@ -9,14 +12,33 @@ layout(set = 0, binding = 0) uniform GlobalSceneData {
 	mat4 vp;
 } globalSceneData;
 
+layout(push_constant) uniform ObjectData {
+	mat4 model;
+	// Inverse transpose of the model matrix, so non-uniform scales don't skew normals.
+	mat4 normalMatrix;
+} objectData;
+
 layout (location = 0) out vec3 ssPosition;
 layout (location = 1) out vec3 ssNormal;
+layout (location = 2) out vec2 outTexCoord;
+layout (location = 3) out vec4 ssTangent;
+layout (location = 4) out vec4 outColor;
 
 void main() 
 {
-	ssNormal = (vec4(normal, 0.0) * globalSceneData.view).xyz;
-	ssPosition = (vec4(position, 1.0) * globalSceneData.view).xyz;
+	vec4 worldPosition = objectData.model * vec4(position, 1.0);
+	vec4 worldNormal = objectData.normalMatrix * vec4(normal, 0.0);
+	vec4 worldTangent = objectData.model * vec4(tangent.xyz, 0.0);
+
+	// Shading happens in view space, so the specular term needs real view space positions.
+	ssNormal = (globalSceneData.view * worldNormal).xyz;
+	ssPosition = (globalSceneData.view * worldPosition).xyz;
+	// Mirroring flips handedness, so the bitangent has to flip with it.
+	float handedness = tangent.w * sign(determinant(mat3(objectData.model)));
+	ssTangent = vec4((globalSceneData.view * worldTangent).xyz, handedness);
+	outTexCoord = texCoord;
+	outColor = color;
 	
-	vec4 projectedPosition = globalSceneData.vp * vec4(position, 1.0);
+	vec4 projectedPosition = globalSceneData.vp * worldPosition;
 	gl_Position = projectedPosition;
 }
\ No newline at end of file
@@ -0,0 +1,13 @@
+#version 450
+
+layout (location = 0) in vec3 position;
+
+layout(push_constant) uniform ShadowData {
+	// Model space straight to the light's clip space.
+	mat4 mvp;
+} shadowData;
+
+void main() 
+{
+	gl_Position = shadowData.mvp * vec4(position, 1.0);
+}
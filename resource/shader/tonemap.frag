@@ -0,0 +1,111 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D hdrImage;
+
+layout(push_constant) uniform PostData {
+	// Linear scale applied before tonemapping.
+	float exposure;
+	uint tonemapper;
+} postData;
+
+layout (location = 0) out vec4 outFragColor;
+
+const uint TONEMAPPER_ACES = 0;
+const uint TONEMAPPER_REINHARD = 1;
+const uint TONEMAPPER_AGX = 2;
+
+// Stephen Hill's fit of the ACES reference rendering and output device transforms.
+// The matrices are sRGB to and from the ACES working space, with the ACES white point.
+const mat3 ACES_INPUT = mat3(
+	0.59719, 0.07600, 0.02840,
+	0.35458, 0.90834, 0.13383,
+	0.04823, 0.01566, 0.83777
+);
+
+const mat3 ACES_OUTPUT = mat3(
+	1.60475, -0.10208, -0.00327,
+	-0.53108, 1.10813, -0.07276,
+	-0.07367, -0.00605, 1.07602
+);
+
+vec3 aces(vec3 color)
+{
+	color = ACES_INPUT * color;
+
+	vec3 a = color * (color + 0.0245786) - 0.000090537;
+	vec3 b = color * (0.983729 * color + 0.4329510) + 0.238081;
+	color = a / b;
+
+	return clamp(ACES_OUTPUT * color, 0.0, 1.0);
+}
+
+vec3 reinhard(vec3 color)
+{
+	return color / (1.0 + color);
+}
+
+// AgX, after Benjamin Wrensch's fit of the default contrast curve.
+const mat3 AGX_INSET = mat3(
+	0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+	0.0784335999999992, 0.878468636469772, 0.0784336,
+	0.0792237451477643, 0.0791661274605434, 0.879142973793104
+);
+
+const mat3 AGX_OUTSET = mat3(
+	1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+	-0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+	-0.0990297440797205, -0.0989611768448433, 1.15107367264116
+);
+
+// The range of exposure values the curve covers, relative to middle grey.
+const float AGX_MIN_EV = -12.47393;
+const float AGX_MAX_EV = 4.026069;
+
+vec3 agxContrast(vec3 x)
+{
+	vec3 x2 = x * x;
+	vec3 x4 = x2 * x2;
+
+	return 15.5 * x4 * x2
+		- 40.14 * x4 * x
+		+ 31.96 * x4
+		- 6.868 * x2 * x
+		+ 0.4298 * x2
+		+ 0.1191 * x
+		- 0.00232;
+}
+
+vec3 agx(vec3 color)
+{
+	color = AGX_INSET * color;
+
+	// Work in log space, squeezing the covered range down to [0, 1].
+	color = clamp(log2(max(color, 1e-10)), AGX_MIN_EV, AGX_MAX_EV);
+	color = (color - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+	color = agxContrast(color);
+
+	color = AGX_OUTSET * color;
+
+	// The curve produces display encoded values, and the target expects linear ones.
+	return pow(max(color, 0.0), vec3(2.2));
+}
+
+void main()
+{
+	// The target is the same size as the HDR image, so just read the matching pixel.
+	vec3 color = texelFetch(hdrImage, ivec2(gl_FragCoord.xy), 0).rgb * postData.exposure;
+
+	switch (postData.tonemapper) {
+	case TONEMAPPER_ACES:
+		color = aces(color);
+		break;
+	case TONEMAPPER_REINHARD:
+		color = reinhard(color);
+		break;
+	case TONEMAPPER_AGX:
+		color = agx(color);
+		break;
+	}
+
+	outFragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use crate::{pack::PackOptions, report::MessageFormat, RsrcError, RsrcResult};
+
+const USAGE: &str = "usage: rsrc [--watch] [--prune] [--pack <file> [--compress]] \
+                     [--message-format human|json] <source dir> <output dir>";
+
+pub struct Args {
+    pub source_dir: PathBuf,
+    pub out_dir: PathBuf,
+    // Keep running and rebuild whatever changes.
+    pub watch: bool,
+    // Delete outputs whose sources are gone after building.
+    pub prune: bool,
+    // Bundle the outputs into a pack file after building.
+    pub pack: Option<PackOptions>,
+    pub message_format: MessageFormat,
+}
+
+fn parse_message_format(s: &str) -> RsrcResult<MessageFormat> {
+    match s {
+        "human" => Ok(MessageFormat::Human),
+        "json" => Ok(MessageFormat::Json),
+        _ => Err(RsrcError::Other(
+            "message format should be one of human or json".to_string(),
+        )),
+    }
+}
+
+impl Args {
+    pub fn parse() -> RsrcResult<Self> {
+        let mut watch = false;
+        let mut prune = false;
+        let mut pack_path = None;
+        let mut compress = false;
+        let mut message_format = MessageFormat::default();
+        let mut dirs = Vec::new();
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--watch" => watch = true,
+                "--prune" => prune = true,
+                "--pack" => {
+                    let value = args
+                        .next()
+                        .ok_or(RsrcError::Other("--pack requires a value".to_string()))?;
+                    pack_path = Some(PathBuf::from(value));
+                }
+                "--compress" => compress = true,
+                "--message-format" => {
+                    let value = args.next().ok_or(RsrcError::Other(
+                        "--message-format requires a value".to_string(),
+                    ))?;
+                    message_format = parse_message_format(&value)?;
+                }
+                _ if arg.starts_with("--") => {
+                    return Err(RsrcError::Other(format!("unknown argument {arg}\n{USAGE}")));
+                }
+                _ => dirs.push(PathBuf::from(arg)),
+            }
+        }
+
+        let [source_dir, out_dir] =
+            <[PathBuf; 2]>::try_from(dirs).map_err(|_e| RsrcError::Other(USAGE.to_string()))?;
+
+        let pack = match pack_path {
+            Some(path) => Some(PackOptions { path, compress }),
+            None if compress => {
+                return Err(RsrcError::Other(
+                    "--compress only applies to --pack".to_string(),
+                ));
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            source_dir,
+            out_dir,
+            watch,
+            prune,
+            pack,
+            message_format,
+        })
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use rkyv::{rancor, Archive, Deserialize, Serialize};
+
+use crate::{write_atomically, RsrcError, RsrcResult};
+
+pub(crate) const DB_FILE_NAME: &str = ".rsrc.db";
+
+// Bump this whenever a change to rsrc changes what it outputs, so everything gets rebuilt.
+const FORMAT_VERSION: u32 = 1;
+
+fn tool_version() -> String {
+    format!("{}+{FORMAT_VERSION}", env!("CARGO_PKG_VERSION"))
+}
+
+pub(crate) fn hash_file(path: &Path) -> RsrcResult<u128> {
+    Ok(xxhash_rust::xxh3::xxh3_128(&fs::read(path)?))
+}
+
+// A file that an output was built from, and what it looked like at the time.
+#[derive(Archive, Serialize, Deserialize)]
+pub(crate) struct Input {
+    // Canonicalized, so it doesn't matter where rsrc is run from.
+    path: String,
+    hash: u128,
+}
+
+impl Input {
+    pub(crate) fn new(path: &Path) -> RsrcResult<Self> {
+        Ok(Self {
+            path: path.canonicalize()?.to_string_lossy().to_string(),
+            hash: hash_file(path)?,
+        })
+    }
+
+    fn is_unchanged(&self) -> bool {
+        hash_file(Path::new(&self.path)).is_ok_and(|hash| hash == self.hash)
+    }
+}
+
+#[derive(Archive, Serialize, Deserialize)]
+pub(crate) struct Record {
+    tool_version: String,
+    // The source first, then everything it pulled in.
+    inputs: Vec<Input>,
+}
+
+impl Record {
+    // `source` should be hashed before building, so changes made while we were busy aren't
+    // mistaken for what we built from.
+    pub(crate) fn new(source: Input, dependencies: &[PathBuf]) -> RsrcResult<Self> {
+        let mut inputs = vec![source];
+        for path in dependencies {
+            let input = Input::new(path)?;
+            if inputs.iter().all(|existing| existing.path != input.path) {
+                inputs.push(input);
+            }
+        }
+
+        Ok(Self {
+            tool_version: tool_version(),
+            inputs,
+        })
+    }
+}
+
+// What every output in an output dir was built from, so we know what needs rebuilding. Lives in
+// the output dir itself.
+#[derive(Archive, Serialize, Deserialize, Default)]
+pub(crate) struct BuildDb {
+    // Keyed by output path, relative to the output dir.
+    records: HashMap<String, Record>,
+}
+
+impl BuildDb {
+    // Starts from scratch if there's no database yet, or it can't be read.
+    pub(crate) fn load(out_dir: &Path) -> Self {
+        let path = out_dir.join(DB_FILE_NAME);
+        let Ok(bytes) = fs::read(&path) else {
+            return Self::default();
+        };
+
+        match rkyv::from_bytes::<BuildDb, rancor::Error>(&bytes) {
+            Ok(db) => db,
+            Err(e) => {
+                eprintln!(
+                    "warning: ignoring unreadable build database {}: {e}",
+                    path.display()
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub(crate) fn save(&self, out_dir: &Path) -> RsrcResult<()> {
+        let bytes = rkyv::to_bytes::<rancor::Error>(self).map_err(RsrcError::RkyvError)?;
+
+        write_atomically(&out_dir.join(DB_FILE_NAME), &bytes)
+    }
+
+    // Whether `dest` exists and was built by this version of rsrc from exactly the inputs that
+    // are on disk now.
+    pub(crate) fn is_up_to_date(&self, key: &str, dest: &Path) -> bool {
+        let Some(record) = self.records.get(key) else {
+            return false;
+        };
+
+        record.tool_version == tool_version()
+            && dest.exists()
+            && record.inputs.iter().all(Input::is_unchanged)
+    }
+
+    pub(crate) fn insert(&mut self, key: String, record: Record) {
+        self.records.insert(key, record);
+    }
+
+    // The source of every output that was built from `path`, which should be canonical.
+    pub(crate) fn dependents(&self, path: &Path) -> Vec<PathBuf> {
+        let path = path.to_string_lossy();
+
+        self.records
+            .values()
+            .filter(|record| record.inputs.iter().any(|input| input.path == path))
+            .filter_map(|record| record.inputs.first())
+            .map(|source| PathBuf::from(&source.path))
+            .collect()
+    }
+
+    // Forget about every output `keep` returns false for.
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        self.records.retain(|key, _| keep(key));
+    }
+}
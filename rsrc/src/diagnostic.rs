@@ -0,0 +1,188 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+// Something wrong with a file, pointing at the line if we know it.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub line: Option<u32>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{line}: ", self.file.display())?,
+            None => write!(f, "{}: ", self.file.display())?,
+        }
+
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+// glslc reports problems as `file:line: error: message`, where the file might be something the
+// shader included. Anything else it says is pinned on `source`.
+pub fn parse_glslc_output(source: &Path, output: &str) -> Vec<Diagnostic> {
+    output
+        .lines()
+        .map(str::trim)
+        // Just a count of what came before, e.g. "2 errors generated."
+        .filter(|line| !line.is_empty() && !line.ends_with(" generated."))
+        .map(|line| parse_glslc_line(source, line))
+        .collect()
+}
+
+fn parse_glslc_line(source: &Path, line: &str) -> Diagnostic {
+    let parsed = [
+        (": error: ", Severity::Error),
+        (": warning: ", Severity::Warning),
+    ]
+    .into_iter()
+    .find_map(|(separator, severity)| {
+        let (location, message) = line.split_once(separator)?;
+        Some((location, severity, message))
+    });
+
+    let Some((location, severity, message)) = parsed else {
+        return Diagnostic {
+            file: source.to_path_buf(),
+            line: None,
+            severity: Severity::Error,
+            message: line.to_string(),
+        };
+    };
+
+    // Split from the right, so a drive letter doesn't look like a line number.
+    let (file, line) = location
+        .rsplit_once(':')
+        .and_then(|(file, line)| Some((file, Some(line.parse::<u32>().ok()?))))
+        .unwrap_or((location, None));
+    // glslc names itself for errors that aren't tied to a file, so pin those on the source.
+    let file = match file {
+        "glslc" => source.to_path_buf(),
+        file => PathBuf::from(file),
+    };
+
+    Diagnostic {
+        file,
+        line,
+        severity,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(file: &str, line: Option<u32>, severity: Severity, message: &str) -> Diagnostic {
+        Diagnostic {
+            file: PathBuf::from(file),
+            line,
+            severity,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn glslc_errors_in_includes() {
+        let output = "shaders/a.vert:3: error: 'foo' : undeclared identifier\n\
+                      shaders/common.glsl:12: warning: unused variable\n";
+
+        assert_eq!(
+            parse_glslc_output(Path::new("shaders/a.vert"), output),
+            [
+                diagnostic(
+                    "shaders/a.vert",
+                    Some(3),
+                    Severity::Error,
+                    "'foo' : undeclared identifier"
+                ),
+                diagnostic(
+                    "shaders/common.glsl",
+                    Some(12),
+                    Severity::Warning,
+                    "unused variable"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn glslc_drive_letters() {
+        let output = "C:\\shaders\\common.glsl:12: error: syntax error";
+
+        assert_eq!(
+            parse_glslc_output(Path::new("C:\\shaders\\a.vert"), output),
+            [diagnostic(
+                "C:\\shaders\\common.glsl",
+                Some(12),
+                Severity::Error,
+                "syntax error"
+            )]
+        );
+    }
+
+    #[test]
+    fn glslc_lines_without_a_location() {
+        let output = "glslc: error: linking multiple files is not supported yet\n\
+                      something went wrong\n";
+
+        assert_eq!(
+            parse_glslc_output(Path::new("shaders/a.vert"), output),
+            [
+                diagnostic(
+                    "shaders/a.vert",
+                    None,
+                    Severity::Error,
+                    "linking multiple files is not supported yet"
+                ),
+                diagnostic(
+                    "shaders/a.vert",
+                    None,
+                    Severity::Error,
+                    "something went wrong"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn glslc_counts_are_skipped() {
+        let output = "shaders/a.vert:3: error: 'foo' : undeclared identifier\n\
+                      1 error generated.\n\
+                      \n\
+                      2 errors and 1 warning generated.\n";
+
+        assert_eq!(
+            parse_glslc_output(Path::new("shaders/a.vert"), output),
+            [diagnostic(
+                "shaders/a.vert",
+                Some(3),
+                Severity::Error,
+                "'foo' : undeclared identifier"
+            )]
+        );
+    }
+}
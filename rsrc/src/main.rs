@@ -1,25 +1,61 @@
 use std::{
     fmt::Display,
-    fs::{self, File},
-    io::{self, Write},
+    fs, io,
     path::{Path, PathBuf},
     process::exit,
 };
 
+use rayon::prelude::*;
 use rkyv::rancor;
 use walkdir::WalkDir;
 
-use crate::model::{new_model_from_gltf_file, ModelError};
+use crate::{
+    args::Args,
+    db::{BuildDb, Input, Record},
+    diagnostic::{parse_glslc_output, Diagnostic, Severity},
+    manifest::{prune, write_manifest},
+    model::{gltf_dependencies, new_model_from_gltf_file, ModelError},
+    pack::write_pack,
+    report::{BuildSummary, Reporter},
+    texture::{new_texture_from_image_file, TextureError},
+};
 
+mod args;
+mod db;
+mod diagnostic;
+mod manifest;
+mod material;
 mod model;
+mod pack;
+mod report;
+mod texture;
+mod watch;
 
 enum RsrcError {
     IoError(io::Error),
     ModelError(model::ModelError),
+    TextureError(texture::TextureError),
     RkyvError(rancor::Error),
+    WatchError(notify::Error),
+    ShaderError(Vec<Diagnostic>),
     Other(String),
 }
 
+impl RsrcError {
+    // What went wrong with `source`, as precisely as we know it.
+    fn diagnostics(&self, source: &Path) -> Vec<Diagnostic> {
+        match self {
+            RsrcError::ShaderError(diagnostics) => diagnostics.clone(),
+            _ => vec![Diagnostic {
+                file: source.to_path_buf(),
+                line: None,
+                severity: Severity::Error,
+                message: self.to_string(),
+            }],
+        }
+    }
+}
+
 impl From<io::Error> for RsrcError {
     fn from(value: io::Error) -> Self {
         RsrcError::IoError(value)
@@ -38,18 +74,39 @@ impl From<String> for RsrcError {
     }
 }
 
+impl From<notify::Error> for RsrcError {
+    fn from(value: notify::Error) -> Self {
+        RsrcError::WatchError(value)
+    }
+}
+
 impl From<ModelError> for RsrcError {
     fn from(value: ModelError) -> Self {
         RsrcError::ModelError(value)
     }
 }
 
+impl From<TextureError> for RsrcError {
+    fn from(value: TextureError) -> Self {
+        RsrcError::TextureError(value)
+    }
+}
+
 impl Display for RsrcError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             RsrcError::IoError(error) => write!(f, "io error: {error}"),
             RsrcError::ModelError(error) => write!(f, "model load error: {error}"),
+            RsrcError::TextureError(error) => write!(f, "texture load error: {error}"),
             RsrcError::RkyvError(error) => write!(f, "rkyv error: {error}"),
+            RsrcError::WatchError(error) => write!(f, "watch error: {error}"),
+            RsrcError::ShaderError(diagnostics) => {
+                write!(f, "shader compile error:")?;
+                for diagnostic in diagnostics {
+                    write!(f, "\n{diagnostic}")?;
+                }
+                Ok(())
+            }
             RsrcError::Other(s) => write!(f, "{s}"),
         }
     }
@@ -63,112 +120,299 @@ fn get_shader_output_path(path: &Path, ext: &str) -> PathBuf {
     path.with_extension(ext)
 }
 
+// Write the whole thing out before replacing what's there, so getting killed halfway doesn't leave
+// a broken file behind, and urbrs never sees one change under a mapping it already has.
+fn write_atomically(path: &Path, bytes: &[u8]) -> RsrcResult<()> {
+    let temp_path = get_temp_path(path);
+
+    fs::write(&temp_path, bytes)?;
+    fs::rename(temp_path, path)?;
+
+    Ok(())
+}
+
+fn get_temp_path(path: &Path) -> PathBuf {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+
+    PathBuf::from(temp_path)
+}
+
 fn get_output_rel_path(path: &Path) -> RsrcResult<PathBuf> {
     let ext = path.extension().map(|os_str| os_str.to_str()).flatten();
 
     Ok(match ext {
         Some("vert") => get_shader_output_path(path, "vert"),
         Some("frag") => get_shader_output_path(path, "frag"),
-        Some("glb") => path.with_extension("mdl"),
+        Some("glb") | Some("gltf") => path.with_extension("mdl"),
+        Some("png") | Some("jpg") | Some("jpeg") => path.with_extension("tex"),
         _ => path.to_path_buf(),
     })
 }
 
-fn glslc_compile(source: &Path, dest: &Path) -> RsrcResult<()> {
+// Pull the prerequisites out of a make rule like `a.spv.vert: a.vert common.glsl`, which is what
+// glslc writes for `-MD`.
+fn parse_make_deps(rule: &str) -> Vec<PathBuf> {
+    let rule = rule.replace("\\\r\n", " ").replace("\\\n", " ");
+    let Some((_, prerequisites)) = rule.split_once(": ") else {
+        return Vec::new();
+    };
+
+    let mut deps = Vec::new();
+    let mut current = String::new();
+    let mut chars = prerequisites.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // Spaces in paths are escaped.
+            '\\' if chars.peek() == Some(&' ') => {
+                current.push(' ');
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    deps.push(PathBuf::from(std::mem::take(&mut current)));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        deps.push(PathBuf::from(current));
+    }
+
+    deps
+}
+
+// Returns every file the shader `#include`d, along with the source.
+fn glslc_compile(source: &Path, dest: &Path) -> RsrcResult<Vec<PathBuf>> {
+    let mut dep_file = dest.as_os_str().to_owned();
+    dep_file.push(".d");
+    let dep_file = PathBuf::from(dep_file);
+
+    let temp_path = get_temp_path(dest);
     let output = std::process::Command::new("glslc")
+        .arg("-MD")
+        .arg("-MF")
+        .arg(dep_file.as_os_str())
         .arg("-o")
-        .arg(dest.as_os_str())
+        .arg(temp_path.as_os_str())
         .arg(source.as_os_str())
         .output()?;
 
     if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-        Err(stderr.into())
-    } else {
-        Ok(())
+        let _ = fs::remove_file(&dep_file);
+        let _ = fs::remove_file(&temp_path);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(RsrcError::ShaderError(parse_glslc_output(source, &stderr)));
     }
+
+    fs::rename(temp_path, dest)?;
+    let rule = fs::read_to_string(&dep_file)?;
+    fs::remove_file(&dep_file)?;
+
+    Ok(parse_make_deps(&rule))
 }
 
-fn gltf_process(source: &Path, dest: &Path) -> RsrcResult<()> {
+// Returns the external buffers and images the model was loaded from.
+fn gltf_process(source: &Path, dest: &Path) -> RsrcResult<Vec<PathBuf>> {
+    let dependencies = gltf_dependencies(source)?;
     let model = new_model_from_gltf_file(source)?;
 
     let bytes = rkyv::to_bytes::<rancor::Error>(&model).map_err(|e| RsrcError::RkyvError(e))?;
 
-    File::create(dest)?.write_all(&bytes)?;
+    write_atomically(dest, &bytes)?;
 
-    Ok(())
+    Ok(dependencies)
 }
 
-fn basic_copy(source: &Path, dest: &Path) -> RsrcResult<()> {
-    fs::copy(source, dest)?;
+fn texture_process(source: &Path, dest: &Path) -> RsrcResult<()> {
+    let texture = new_texture_from_image_file(source)?;
+
+    let bytes = rkyv::to_bytes::<rancor::Error>(&texture).map_err(RsrcError::RkyvError)?;
+
+    write_atomically(dest, &bytes)?;
 
     Ok(())
 }
 
-fn should_skip_process(source: &Path, dest: &Path) -> RsrcResult<bool> {
-    if !fs::exists(dest)? {
-        return Ok(false);
-    }
+fn basic_copy(source: &Path, dest: &Path) -> RsrcResult<()> {
+    let temp_path = get_temp_path(dest);
 
-    let source_mtime = fs::metadata(source)?.modified()?;
-    let dest_mtime = fs::metadata(dest)?.modified()?;
+    fs::copy(source, &temp_path)?;
+    fs::rename(temp_path, dest)?;
 
-    Ok(source_mtime <= dest_mtime)
+    Ok(())
 }
 
-fn process(source: &Path, dest: &Path) -> RsrcResult<()> {
-    if should_skip_process(source, dest)? {
-        return Ok(());
-    }
+fn has_output(source: &Path) -> bool {
+    let ext = source.extension().and_then(|os_str| os_str.to_str());
 
+    // We don't want to process these.
+    !matches!(ext, Some("blend") | Some("blend1"))
+}
+
+// Returns any files besides `source` that went into `dest`.
+fn process(source: &Path, dest: &Path) -> RsrcResult<Vec<PathBuf>> {
     let ext = source.extension().map(|os_str| os_str.to_str()).flatten();
 
     match ext {
         Some("vert") => glslc_compile(source, dest),
         Some("frag") => glslc_compile(source, dest),
-        Some("glb") => gltf_process(source, dest),
-        // No-op, we don't want to process these.
-        Some("blend") | Some("blend1") => Ok(()),
-        _ => basic_copy(source, dest),
+        Some("glb") | Some("gltf") => gltf_process(source, dest),
+        Some("png") | Some("jpg") | Some("jpeg") => {
+            texture_process(source, dest).map(|()| Vec::new())
+        }
+        _ => basic_copy(source, dest).map(|()| Vec::new()),
     }
 }
 
-fn rsrc_main() -> RsrcResult<()> {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 3 {
-        return Err(RsrcError::Other("invalid number of arguments".to_string()));
+// Where `source` ends up under `out_dir`.
+fn get_dest_path(source_dir: &Path, out_dir: &Path, source: &Path) -> RsrcResult<PathBuf> {
+    let rel = source.strip_prefix(source_dir).map_err(|_e| {
+        format!("could not calculate relative path for {}", source.display()).to_string()
+    })?;
+
+    let output_rel_path = get_output_rel_path(rel)?;
+
+    Ok(out_dir.join(output_rel_path))
+}
+
+struct BuiltOutput {
+    dest: PathBuf,
+    // Where the record goes in the build database.
+    key: String,
+    record: Record,
+}
+
+// Returns None if the output was already up to date.
+fn build_file(
+    db: &BuildDb,
+    reporter: Reporter,
+    source_dir: &Path,
+    out_dir: &Path,
+    source: &Path,
+) -> RsrcResult<Option<BuiltOutput>> {
+    let dest = get_dest_path(source_dir, out_dir, source)?;
+    let key = dest
+        .strip_prefix(out_dir)
+        .map_err(|_e| format!("{} is outside the output dir", dest.display()))?
+        .to_string_lossy()
+        .to_string();
+
+    if db.is_up_to_date(&key, &dest) {
+        return Ok(None);
     }
 
-    let source_dir = Path::new(&args[1]);
-    let out_dir = Path::new(&args[2]);
+    reporter.building(source, &dest);
+
+    // Make sure our output dir exists before processing.
+    if let Some(dir) = dest.parent() {
+        fs::create_dir_all(dir)?;
+    }
 
-    let walk = WalkDir::new(source_dir);
+    let input = Input::new(source)?;
+    let dependencies = process(source, &dest)?;
+
+    Ok(Some(BuiltOutput {
+        dest,
+        key,
+        record: Record::new(input, &dependencies)?,
+    }))
+}
+
+// Rebuild whichever of `sources`, all somewhere in `source_dir`, are out of date, in parallel.
+// One failure doesn't stop the others. They're all reported together at the end.
+fn build(
+    reporter: Reporter,
+    source_dir: &Path,
+    out_dir: &Path,
+    sources: &[PathBuf],
+) -> RsrcResult<BuildSummary> {
+    let mut db = BuildDb::load(out_dir);
+
+    let results: Vec<_> = sources
+        .par_iter()
+        .filter(|source| has_output(source))
+        .map(|source| {
+            let result = build_file(&db, reporter, source_dir, out_dir, source);
+            (source, result)
+        })
+        .collect();
+
+    let mut summary = BuildSummary::default();
+    let mut failures = Vec::new();
+    for (source, result) in results {
+        match result {
+            Ok(Some(output)) => {
+                reporter.built(source, &output.dest);
+                db.insert(output.key, output.record);
+                summary.built += 1;
+            }
+            Ok(None) => summary.skipped += 1,
+            Err(e) => {
+                failures.push((source.as_path(), e.diagnostics(source)));
+                summary.failed += 1;
+            }
+        }
+    }
+
+    fs::create_dir_all(out_dir)?;
+    db.save(out_dir)?;
+
+    reporter.finished(
+        &summary,
+        failures
+            .iter()
+            .map(|(source, diagnostics)| (*source, diagnostics.as_slice())),
+    );
+
+    Ok(summary)
+}
+
+// Every file under `dir`.
+fn find_files(dir: &Path) -> RsrcResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    let walk = WalkDir::new(dir);
     for entry in walk {
         let entry = entry?;
-        if !entry.file_type().is_file() {
-            continue;
+        if entry.file_type().is_file() {
+            files.push(entry.into_path());
         }
+    }
 
-        let source = entry.path();
+    Ok(files)
+}
 
-        let rel = entry.path().strip_prefix(source_dir).map_err(|_e| {
-            format!(
-                "could not calculate relative path for {}",
-                entry.path().display()
-            )
-            .to_string()
-        })?;
+fn rsrc_main() -> RsrcResult<()> {
+    let args = Args::parse()?;
+    let reporter = Reporter::new(args.message_format);
 
-        let output_rel_path = get_output_rel_path(rel)?;
+    let sources = find_files(&args.source_dir)?;
+    let summary = build(reporter, &args.source_dir, &args.out_dir, &sources)?;
 
-        let dest = out_dir.join(output_rel_path);
-        println!("{} -> {}", entry.path().display(), dest.display());
+    if args.prune {
+        prune(reporter, &args.source_dir, &args.out_dir)?;
+    }
 
-        // Make sure our output dir exists before processing.
-        if let Some(dir) = dest.parent() {
-            fs::create_dir_all(dir)?;
-        }
-        process(source, &dest)?;
+    write_manifest(&args.source_dir, &args.out_dir, &sources)?;
+
+    if let Some(pack) = &args.pack {
+        write_pack(reporter, &args.out_dir, pack)?;
+    }
+
+    if args.watch {
+        watch::watch(
+            reporter,
+            &args.source_dir,
+            &args.out_dir,
+            args.pack.as_ref(),
+        )?;
+    }
+
+    if summary.failed > 0 {
+        return Err(format!("{} of {} assets failed", summary.failed, summary.total()).into());
     }
 
     Ok(())
@@ -182,3 +426,68 @@ fn main() {
         exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn make_deps() {
+        let deps = parse_make_deps("shaders/a.spv.vert: shaders/a.vert shaders/common.glsl\n");
+
+        assert_eq!(
+            deps,
+            [
+                PathBuf::from("shaders/a.vert"),
+                PathBuf::from("shaders/common.glsl")
+            ]
+        );
+    }
+
+    #[test]
+    fn make_deps_escaped_spaces() {
+        let deps =
+            parse_make_deps("my\\ shaders/a.spv.vert: my\\ shaders/a.vert my\\ shaders/b c.glsl");
+
+        assert_eq!(
+            deps,
+            [
+                PathBuf::from("my shaders/a.vert"),
+                PathBuf::from("my shaders/b"),
+                PathBuf::from("c.glsl")
+            ]
+        );
+    }
+
+    #[test]
+    fn make_deps_continued_lines() {
+        let deps = parse_make_deps("a.spv.vert: a.vert \\\n  common.glsl \\\r\n  lights.glsl\n");
+
+        assert_eq!(
+            deps,
+            [
+                PathBuf::from("a.vert"),
+                PathBuf::from("common.glsl"),
+                PathBuf::from("lights.glsl")
+            ]
+        );
+    }
+
+    #[test]
+    fn make_deps_drive_letters() {
+        let deps = parse_make_deps("C:\\out\\a.spv.vert: C:\\shaders\\a.vert D:\\common.glsl");
+
+        assert_eq!(
+            deps,
+            [
+                PathBuf::from("C:\\shaders\\a.vert"),
+                PathBuf::from("D:\\common.glsl")
+            ]
+        );
+    }
+
+    #[test]
+    fn make_deps_without_prerequisites() {
+        assert!(parse_make_deps("a.spv.vert:").is_empty());
+    }
+}
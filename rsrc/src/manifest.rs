@@ -0,0 +1,137 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use common::{AssetKind, Manifest, ManifestEntry, MANIFEST_FILE_NAME};
+use rkyv::rancor;
+use walkdir::WalkDir;
+
+use crate::{
+    db::{hash_file, BuildDb, DB_FILE_NAME},
+    find_files, get_dest_path, has_output,
+    report::Reporter,
+    write_atomically, RsrcError, RsrcResult,
+};
+
+fn asset_kind(source: &Path) -> AssetKind {
+    let ext = source.extension().and_then(|os_str| os_str.to_str());
+
+    match ext {
+        Some("vert") => AssetKind::VertexShader,
+        Some("frag") => AssetKind::FragmentShader,
+        Some("glb") | Some("gltf") => AssetKind::Model,
+        Some("png") | Some("jpg") | Some("jpeg") => AssetKind::Texture,
+        _ => AssetKind::File,
+    }
+}
+
+// The same on every platform, so names and paths can be written into code.
+fn to_slash_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// Models and textures are known by their path without the extension, since which format they
+// came in doesn't matter once they're processed. Shaders keep theirs, it tells the stages apart.
+fn logical_name(rel: &Path, kind: AssetKind) -> String {
+    match kind {
+        AssetKind::Model | AssetKind::Texture => to_slash_path(&rel.with_extension("")),
+        _ => to_slash_path(rel),
+    }
+}
+
+fn rel_path<'a>(path: &'a Path, dir: &Path) -> RsrcResult<&'a Path> {
+    path.strip_prefix(dir)
+        .map_err(|_e| RsrcError::Other(format!("{} is outside {}", path.display(), dir.display())))
+}
+
+// Record every output of `sources` that made it into `out_dir`. Sources that never built
+// successfully are left out.
+pub fn write_manifest(source_dir: &Path, out_dir: &Path, sources: &[PathBuf]) -> RsrcResult<()> {
+    let mut entries: Vec<ManifestEntry> = Vec::new();
+    for source in sources.iter().filter(|source| has_output(source)) {
+        let dest = get_dest_path(source_dir, out_dir, source)?;
+        let Ok(metadata) = fs::metadata(&dest) else {
+            continue;
+        };
+
+        let kind = asset_kind(source);
+        entries.push(ManifestEntry {
+            name: logical_name(rel_path(source, source_dir)?, kind),
+            kind,
+            path: to_slash_path(rel_path(&dest, out_dir)?),
+            size: metadata.len(),
+            hash: hash_file(&dest)?,
+        });
+    }
+
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    entries.dedup_by(|later, first| {
+        let duplicate = later.name == first.name;
+        if duplicate {
+            eprintln!(
+                "warning: {} and {} are both called {}, ignoring {}",
+                first.path, later.path, first.name, later.path
+            );
+        }
+        duplicate
+    });
+
+    let bytes =
+        rkyv::to_bytes::<rancor::Error>(&Manifest { entries }).map_err(RsrcError::RkyvError)?;
+
+    // Leave it alone if nothing changed, so anything watching the output dir doesn't hear about it.
+    let path = out_dir.join(MANIFEST_FILE_NAME);
+    if fs::read(&path).is_ok_and(|existing| existing == bytes.as_slice()) {
+        return Ok(());
+    }
+
+    fs::create_dir_all(out_dir)?;
+    write_atomically(&path, &bytes)
+}
+
+// Delete everything in `out_dir` that isn't the output of something in `source_dir`, and forget
+// about it in the build database. Returns how many files were deleted.
+pub fn prune(reporter: Reporter, source_dir: &Path, out_dir: &Path) -> RsrcResult<usize> {
+    let mut expected = find_files(source_dir)?
+        .iter()
+        .filter(|source| has_output(source))
+        .map(|source| get_dest_path(source_dir, out_dir, source))
+        .collect::<RsrcResult<HashSet<PathBuf>>>()?;
+    expected.insert(out_dir.join(MANIFEST_FILE_NAME));
+    expected.insert(out_dir.join(DB_FILE_NAME));
+
+    if !out_dir.exists() {
+        return Ok(0);
+    }
+
+    let mut removed = 0;
+    for file in find_files(out_dir)? {
+        if expected.contains(&file) {
+            continue;
+        }
+
+        fs::remove_file(&file)?;
+        reporter.removed(&file);
+        removed += 1;
+    }
+
+    // Children come first, so a directory that only held empty directories goes too. Ones with
+    // anything left in them fail to be removed, which is what we want.
+    for entry in WalkDir::new(out_dir).min_depth(1).contents_first(true) {
+        let entry = entry?;
+        if entry.file_type().is_dir() {
+            let _ = fs::remove_dir(entry.path());
+        }
+    }
+
+    let mut db = BuildDb::load(out_dir);
+    db.retain(|key| expected.contains(&out_dir.join(key)));
+    db.save(out_dir)?;
+
+    Ok(removed)
+}
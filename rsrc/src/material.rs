@@ -0,0 +1,51 @@
+use common::Material;
+
+// Textures are stored by glTF image index.
+fn texture_index(texture: gltf::Texture) -> u32 {
+    texture.source().index() as u32
+}
+
+pub fn new_material(material: &gltf::Material, name: String) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let normal = material.normal_texture();
+    let occlusion = material.occlusion_texture();
+
+    Material {
+        name,
+        base_color_factor: pbr.base_color_factor(),
+        base_color_texture: pbr
+            .base_color_texture()
+            .map(|info| texture_index(info.texture())),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        metallic_roughness_texture: pbr
+            .metallic_roughness_texture()
+            .map(|info| texture_index(info.texture())),
+        normal_scale: normal.as_ref().map_or(1.0, |info| info.scale()),
+        normal_texture: normal.map(|info| texture_index(info.texture())),
+        occlusion_strength: occlusion.as_ref().map_or(1.0, |info| info.strength()),
+        occlusion_texture: occlusion.map(|info| texture_index(info.texture())),
+        emissive_factor: material.emissive_factor(),
+        emissive_texture: material
+            .emissive_texture()
+            .map(|info| texture_index(info.texture())),
+    }
+}
+
+// What glTF says to use for primitives without a material.
+pub fn new_default_material(name: String) -> Material {
+    Material {
+        name,
+        base_color_factor: [1.0; 4],
+        base_color_texture: None,
+        metallic_factor: 1.0,
+        roughness_factor: 1.0,
+        metallic_roughness_texture: None,
+        normal_texture: None,
+        normal_scale: 1.0,
+        occlusion_texture: None,
+        occlusion_strength: 1.0,
+        emissive_factor: [0.0; 3],
+        emissive_texture: None,
+    }
+}
@@ -1,10 +1,20 @@
-use std::{fmt::Display, path::Path};
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
 
-use common::{Model, Vertex};
-use gltf::{mesh::Mode, Semantic};
+use common::{Bounds, Light, LightKind, Model, Submesh, TextureFormat, Vertex};
+use gltf::{buffer, khr_lights_punctual::Kind, mesh::Mode, Document, Node, Primitive};
 
+use crate::{
+    material::{new_default_material, new_material},
+    texture::{new_texture_from_gltf_image, TextureError},
+};
+
+#[allow(clippy::enum_variant_names)] // Named after the errors they wrap.
 pub enum ModelError {
     GltfError(gltf::Error),
+    TextureError(TextureError),
     FormatError(&'static str),
 }
 
@@ -14,41 +24,113 @@ impl From<gltf::Error> for ModelError {
     }
 }
 
+impl From<TextureError> for ModelError {
+    fn from(value: TextureError) -> Self {
+        ModelError::TextureError(value)
+    }
+}
+
 impl Display for ModelError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ModelError::GltfError(error) => write!(f, "glTF load error: {error}"),
+            ModelError::TextureError(error) => write!(f, "embedded texture error: {error}"),
             ModelError::FormatError(s) => write!(f, "model file format error: {s}"),
         }
     }
 }
 
-pub fn new_model_from_gltf_file(path: &Path) -> Result<Model, ModelError> {
-    let (file, buffers, _) = gltf::import(path)?;
+// Adapts one primitive's vertices for MikkTSpace tangent generation.
+struct TangentGeometry<'a> {
+    vertices: &'a mut [Vertex],
+    indices: &'a [u32],
+}
 
-    let scene = file
-        .default_scene()
-        .ok_or(ModelError::FormatError("file had no default scene"))?;
+impl TangentGeometry<'_> {
+    fn vertex_idx(&self, face: usize, vert: usize) -> usize {
+        self.indices[face * 3 + vert] as usize
+    }
+}
+
+impl bevy_mikktspace::Geometry for TangentGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
 
-    // Our scenes are very simple right now. Just grab the first mesh we find.
-    let mut meshes = scene.nodes().filter_map(|n| n.mesh());
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertices[self.vertex_idx(face, vert)].position
+    }
 
-    let mesh = meshes
-        .next()
-        .ok_or(ModelError::FormatError("file had no meshes"))?;
-    if meshes.next().is_some() {
-        return Err(ModelError::FormatError("file had more than one mesh"));
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertices[self.vertex_idx(face, vert)].normal
     }
 
-    // And grab the first primitive. Maybe later we can handle two of these?
-    let mut primitives = mesh.primitives();
-    let primitive = primitives
-        .next()
-        .ok_or(ModelError::FormatError("mesh had no primitives"))?;
-    if primitives.next().is_some() {
-        return Err(ModelError::FormatError("file had more than one primitive"))?;
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.vertices[self.vertex_idx(face, vert)].tex_coord
     }
 
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        let idx = self.vertex_idx(face, vert);
+        self.vertices[idx].tangent = tangent;
+    }
+}
+
+// Any tangent perpendicular to the normal. Only used when there are no texture
+// coordinates to derive a real one from.
+fn fallback_tangents(vertices: &mut [Vertex]) {
+    for vertex in vertices {
+        let tangent = glam::Vec3::from(vertex.normal).any_orthonormal_vector();
+        vertex.tangent = tangent.extend(1.0).into();
+    }
+}
+
+// The sphere is centered on the box, which isn't the tightest fit but is cheap and close enough.
+fn compute_bounds(vertices: &[Vertex], indices: &[u32]) -> Bounds {
+    if indices.is_empty() {
+        return Bounds {
+            min: [0.0; 3],
+            max: [0.0; 3],
+            center: [0.0; 3],
+            radius: 0.0,
+        };
+    }
+
+    // Only count the vertices we actually draw.
+    let positions = || {
+        indices
+            .iter()
+            .map(|idx| glam::Vec3::from(vertices[*idx as usize].position))
+    };
+
+    let (min, max) = positions().fold(
+        (glam::Vec3::INFINITY, glam::Vec3::NEG_INFINITY),
+        |(min, max), position| (min.min(position), max.max(position)),
+    );
+
+    let center = (min + max) / 2.0;
+    let radius = positions()
+        .map(|position| position.distance(center))
+        .fold(0.0, f32::max);
+
+    Bounds {
+        min: min.into(),
+        max: max.into(),
+        center: center.into(),
+        radius,
+    }
+}
+
+fn append_primitive(
+    model: &mut Model,
+    name: String,
+    primitive: &Primitive,
+    buffers: &[buffer::Data],
+    transform: glam::Mat4,
+) -> Result<(), ModelError> {
     if primitive.mode() != Mode::Triangles {
         return Err(ModelError::FormatError(
             "primitive was not in triangle format",
@@ -57,38 +139,260 @@ pub fn new_model_from_gltf_file(path: &Path) -> Result<Model, ModelError> {
 
     let reader = primitive.reader(|prim_buffer| Some(&buffers[prim_buffer.index()]));
 
-    let pos_iter = reader
+    let positions: Vec<[f32; 3]> = reader
         .read_positions()
-        .ok_or(ModelError::FormatError("mesh had no positions"))?;
-    let normal_iter = reader
+        .ok_or(ModelError::FormatError("mesh had no positions"))?
+        .collect();
+    let num_vertices = positions.len();
+
+    let normals: Vec<[f32; 3]> = reader
         .read_normals()
-        .ok_or(ModelError::FormatError("mesh had no normals"))?;
+        .ok_or(ModelError::FormatError("mesh had no normals"))?
+        .collect();
 
-    let num_vertices = primitive
-        .get(&Semantic::Positions)
-        .ok_or(ModelError::FormatError("mesh had no positions"))?
-        .count();
+    let has_tex_coords = reader.read_tex_coords(0).is_some();
+    let tex_coords: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+        Some(tex_coords) => tex_coords.into_f32().collect(),
+        None => vec![[0.0; 2]; num_vertices],
+    };
 
-    let mut vertices: Vec<Vertex> = Vec::with_capacity(num_vertices);
-    vertices.extend(
-        pos_iter
-            .zip(normal_iter)
-            .map(|(position, normal)| Vertex { position, normal }),
-    );
+    // Untinted if there are no vertex colors.
+    let colors: Vec<[f32; 4]> = match reader.read_colors(0) {
+        Some(colors) => colors.into_rgba_f32().collect(),
+        None => vec![[1.0; 4]; num_vertices],
+    };
+
+    // Filled in below if the file doesn't have them.
+    let tangents: Option<Vec<[f32; 4]>> = reader.read_tangents().map(|t| t.collect());
+
+    if normals.len() != num_vertices
+        || tex_coords.len() != num_vertices
+        || colors.len() != num_vertices
+        || tangents.as_ref().is_some_and(|t| t.len() != num_vertices)
+    {
+        return Err(ModelError::FormatError(
+            "mesh had a different number of values for each vertex attribute",
+        ));
+    }
 
-    let num_indices = primitive
-        .indices()
-        .ok_or(ModelError::FormatError("mesh had no indicies"))?
-        .count();
+    // Normals need the inverse transpose so non-uniform scales don't skew them.
+    let normal_transform = glam::Mat3::from_mat4(transform).inverse().transpose();
+    let tangent_transform = glam::Mat3::from_mat4(transform);
 
-    let mut indices: Vec<u32> = Vec::with_capacity(num_indices);
+    // Mirroring transforms flip the handedness of the tangent frame, and the winding order.
+    let mirrored = transform.determinant() < 0.0;
+    let handedness = if mirrored { -1.0 } else { 1.0 };
 
-    indices.extend(
-        reader
-            .read_indices()
-            .ok_or(ModelError::FormatError("mesh had no indices"))?
-            .into_u32(),
-    );
+    let vertex_offset = model.vertices.len();
+    model.vertices.reserve(num_vertices);
+    for i in 0..num_vertices {
+        let tangent = match &tangents {
+            Some(tangents) => {
+                let [x, y, z, w] = tangents[i];
+                (tangent_transform * glam::vec3(x, y, z))
+                    .normalize_or_zero()
+                    .extend(w * handedness)
+                    .into()
+            }
+            None => [0.0; 4],
+        };
+
+        model.vertices.push(Vertex {
+            position: transform
+                .transform_point3(glam::Vec3::from(positions[i]))
+                .into(),
+            normal: (normal_transform * glam::Vec3::from(normals[i]))
+                .normalize_or_zero()
+                .into(),
+            tex_coord: tex_coords[i],
+            tangent,
+            color: colors[i],
+        });
+    }
+
+    let first_index = model.indices.len();
+    match reader.read_indices() {
+        Some(indices) => model.indices.extend(indices.into_u32()),
+        // Non-indexed primitives just draw their vertices in order.
+        None => model.indices.extend(0..num_vertices as u32),
+    }
+
+    if mirrored {
+        for triangle in model.indices[first_index..].chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+
+    if model.indices[first_index..]
+        .iter()
+        .any(|idx| *idx as usize >= num_vertices)
+    {
+        return Err(ModelError::FormatError("mesh had an out of range index"));
+    }
+
+    if tangents.is_none() {
+        let vertices = &mut model.vertices[vertex_offset..];
+
+        let generated = has_tex_coords
+            && bevy_mikktspace::generate_tangents(&mut TangentGeometry {
+                vertices,
+                indices: &model.indices[first_index..],
+            });
+
+        if !generated {
+            fallback_tangents(&mut model.vertices[vertex_offset..]);
+        }
+    }
+
+    let index_count = model.indices.len() - first_index;
+
+    // Primitives without a material use the default one, which comes after the file's own.
+    let default_material = model.materials.len() - 1;
+
+    model.submeshes.push(Submesh {
+        name,
+        first_index: first_index as u32,
+        index_count: index_count as u32,
+        vertex_offset: vertex_offset as u32,
+        material: primitive.material().index().unwrap_or(default_material) as u32,
+        bounds: compute_bounds(
+            &model.vertices[vertex_offset..],
+            &model.indices[first_index..],
+        ),
+    });
+
+    Ok(())
+}
+
+fn new_light(
+    model_name: &str,
+    light: &gltf::khr_lights_punctual::Light,
+    transform: glam::Mat4,
+) -> Light {
+    let kind = match light.kind() {
+        Kind::Directional => LightKind::Directional,
+        Kind::Point => LightKind::Point,
+        Kind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        } => LightKind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        },
+    };
+
+    let name = light
+        .name()
+        .map(|n| n.to_string())
+        .unwrap_or_else(|| format!("{model_name}.light{}", light.index()));
+
+    // Lights shine down their node's -Z. Scale doesn't affect them, so normalize it away.
+    Light {
+        name,
+        kind,
+        color: light.color(),
+        intensity: light.intensity(),
+        range: light.range(),
+        position: transform.transform_point3(glam::Vec3::ZERO).into(),
+        direction: transform
+            .transform_vector3(glam::Vec3::NEG_Z)
+            .normalize_or_zero()
+            .into(),
+    }
+}
+
+fn visit_node(
+    model: &mut Model,
+    node: &Node,
+    buffers: &[buffer::Data],
+    parent_transform: glam::Mat4,
+) -> Result<(), ModelError> {
+    // We don't keep the hierarchy around, so bake each node's world transform into its vertices.
+    let transform = parent_transform * glam::Mat4::from_cols_array_2d(&node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        let mesh_name = mesh
+            .name()
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| format!("mesh{}", mesh.index()));
+
+        for primitive in mesh.primitives() {
+            let name = format!("{mesh_name}.{}", primitive.index());
+            append_primitive(model, name, &primitive, buffers, transform)?;
+        }
+    }
+
+    if let Some(light) = node.light() {
+        let light = new_light(&model.name, &light, transform);
+        model.lights.push(light);
+    }
+
+    for child in node.children() {
+        visit_node(model, &child, buffers, transform)?;
+    }
+
+    Ok(())
+}
+
+// Color textures are stored as sRGB, anything else is data and stays linear.
+fn image_formats(file: &Document) -> Vec<TextureFormat> {
+    let mut formats = vec![TextureFormat::Rgba8Unorm; file.images().len()];
+
+    for material in file.materials() {
+        let color_textures = [
+            material
+                .pbr_metallic_roughness()
+                .base_color_texture()
+                .map(|info| info.texture()),
+            material.emissive_texture().map(|info| info.texture()),
+        ];
+
+        for texture in color_textures.into_iter().flatten() {
+            formats[texture.source().index()] = TextureFormat::Rgba8Srgb;
+        }
+    }
+
+    formats
+}
+
+// Files besides `path` itself that the model gets loaded from, i.e. external buffers and images.
+pub fn gltf_dependencies(path: &Path) -> Result<Vec<PathBuf>, ModelError> {
+    let file = gltf::Gltf::open(path)?;
+
+    let buffer_uris = file.buffers().filter_map(|buffer| match buffer.source() {
+        buffer::Source::Uri(uri) => Some(uri),
+        buffer::Source::Bin => None,
+    });
+    let image_uris = file.images().filter_map(|image| match image.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(uri),
+        gltf::image::Source::View { .. } => None,
+    });
+
+    // Relative URIs are relative to the file they're in.
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    let mut dependencies = Vec::new();
+    for uri in buffer_uris.chain(image_uris) {
+        // Read the same way `gltf::import` does. Embedded data is covered by the file itself,
+        // and any other scheme would fail to import anyway.
+        if let Some(file) = uri.strip_prefix("file://").or(uri.strip_prefix("file:")) {
+            dependencies.push(PathBuf::from(file));
+        } else if !uri.contains(':') {
+            let decoded = urlencoding::decode(uri)
+                .map_err(|_e| ModelError::FormatError("URI is not valid UTF-8 once decoded"))?;
+            dependencies.push(base.join(decoded.as_ref()));
+        }
+    }
+
+    Ok(dependencies)
+}
+
+pub fn new_model_from_gltf_file(path: &Path) -> Result<Model, ModelError> {
+    let (file, buffers, images) = gltf::import(path)?;
+
+    let scene = file
+        .default_scene()
+        .ok_or(ModelError::FormatError("file had no default scene"))?;
 
     let name: String = path
         .file_stem()
@@ -98,9 +402,135 @@ pub fn new_model_from_gltf_file(path: &Path) -> Result<Model, ModelError> {
         .to_string_lossy()
         .to_string();
 
-    Ok(Model {
+    let mut model = Model {
         name,
-        vertices,
-        indices,
-    })
+        vertices: Vec::new(),
+        indices: Vec::new(),
+        submeshes: Vec::new(),
+        materials: Vec::new(),
+        textures: Vec::new(),
+        lights: Vec::new(),
+    };
+
+    for material in file.materials() {
+        let material_name = material
+            .name()
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| format!("{}.material{}", model.name, model.materials.len()));
+
+        model.materials.push(new_material(&material, material_name));
+    }
+
+    model.materials.push(new_default_material(format!(
+        "{}.default_material",
+        model.name
+    )));
+
+    for (image, format) in file.images().zip(image_formats(&file)) {
+        let texture_name = image
+            .name()
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| format!("{}.image{}", model.name, image.index()));
+
+        model.textures.push(new_texture_from_gltf_image(
+            texture_name,
+            format,
+            &images[image.index()],
+        )?);
+    }
+
+    for node in scene.nodes() {
+        visit_node(&mut model, &node, &buffers, glam::Mat4::IDENTITY)?;
+    }
+
+    if model.submeshes.is_empty() {
+        return Err(ModelError::FormatError("file had no meshes"));
+    }
+
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gltf_dependencies_are_decoded() {
+        let dir = std::env::temp_dir().join(format!("rsrc-{}-gltf", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("model.gltf");
+        std::fs::write(
+            &path,
+            r#"{
+                "asset": { "version": "2.0" },
+                "buffers": [
+                    { "uri": "my%20mesh.bin", "byteLength": 4 },
+                    { "uri": "data:application/octet-stream;base64,AAAA", "byteLength": 3 }
+                ],
+                "images": [{ "uri": "textures/my%20texture.png" }]
+            }"#,
+        )
+        .unwrap();
+
+        let dependencies = gltf_dependencies(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            dependencies.unwrap_or_else(|e| panic!("{e}")),
+            [dir.join("my mesh.bin"), dir.join("textures/my texture.png")]
+        );
+    }
+
+    fn vertex(position: [f32; 3]) -> Vertex {
+        Vertex {
+            position,
+            normal: [0.0, 0.0, 1.0],
+            tex_coord: [0.0; 2],
+            tangent: [1.0, 0.0, 0.0, 1.0],
+            color: [1.0; 4],
+        }
+    }
+
+    #[test]
+    fn bounds_of_nothing() {
+        let bounds = compute_bounds(&[vertex([1.0, 2.0, 3.0])], &[]);
+
+        assert_eq!(bounds.min, [0.0; 3]);
+        assert_eq!(bounds.max, [0.0; 3]);
+        assert_eq!(bounds.radius, 0.0);
+    }
+
+    #[test]
+    fn bounds_only_count_indexed_vertices() {
+        let vertices = [
+            vertex([0.0, 0.0, 0.0]),
+            vertex([2.0, 0.0, 0.0]),
+            vertex([0.0, 2.0, 0.0]),
+            vertex([100.0, 100.0, 100.0]),
+        ];
+        let bounds = compute_bounds(&vertices, &[0, 1, 2]);
+
+        assert_eq!(bounds.min, [0.0, 0.0, 0.0]);
+        assert_eq!(bounds.max, [2.0, 2.0, 0.0]);
+        assert_eq!(bounds.center, [1.0, 1.0, 0.0]);
+        assert!((bounds.radius - 2.0_f32.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn bounds_sphere_holds_every_vertex() {
+        let vertices = [
+            vertex([-3.0, 1.0, 2.0]),
+            vertex([4.0, -2.0, 0.5]),
+            vertex([0.0, 5.0, -1.0]),
+        ];
+        let bounds = compute_bounds(&vertices, &[0, 1, 2]);
+
+        let center = glam::Vec3::from(bounds.center);
+        for vertex in &vertices {
+            let position = glam::Vec3::from(vertex.position);
+            assert!(position.distance(center) <= bounds.radius + 1e-6);
+            assert!(position.cmpge(bounds.min.into()).all());
+            assert!(position.cmple(bounds.max.into()).all());
+        }
+    }
 }
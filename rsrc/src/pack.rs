@@ -0,0 +1,139 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use common::{Manifest, PackWriter, MANIFEST_FILE_NAME};
+use rkyv::rancor;
+
+use crate::{report::Reporter, write_atomically, RsrcError, RsrcResult};
+
+pub struct PackOptions {
+    pub path: PathBuf,
+    // Compress entries with LZ4 wherever it's worth it.
+    pub compress: bool,
+}
+
+// Decompressing costs time on every load, and stops the entry being used straight out of the
+// mapped pack, so it has to save at least an eighth to be kept.
+fn compress(data: &[u8]) -> Option<Vec<u8>> {
+    let compressed = lz4_flex::compress(data);
+
+    (compressed.len() < data.len() - data.len() / 8).then_some(compressed)
+}
+
+// Bundle everything in the manifest in `out_dir` into a single file, so it can be shipped
+// without the loose files.
+pub fn write_pack(reporter: Reporter, out_dir: &Path, options: &PackOptions) -> RsrcResult<()> {
+    let manifest_bytes = fs::read(out_dir.join(MANIFEST_FILE_NAME))?;
+    let manifest = rkyv::from_bytes::<Manifest, rancor::Error>(&manifest_bytes)
+        .map_err(RsrcError::RkyvError)?;
+
+    let count = manifest.entries.len();
+    // Entries were already sorted by name in the manifest.
+    let mut pack = PackWriter::default();
+    for entry in manifest.entries {
+        let data = fs::read(out_dir.join(&entry.path))?;
+        let compressed = if options.compress {
+            compress(&data)
+        } else {
+            None
+        };
+
+        // The output might have been rebuilt since the manifest was written.
+        let hash = xxhash_rust::xxh3::xxh3_128(&data);
+        pack.add(entry.name, entry.kind, &data, compressed.as_deref(), hash);
+    }
+
+    let bytes = pack.finish().map_err(RsrcError::RkyvError)?;
+
+    if let Some(dir) = options.path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    write_atomically(&options.path, &bytes)?;
+
+    reporter.packed(&options.path, count);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{AssetKind, Compression, ManifestEntry, PackHeader, PackToc, PACK_ALIGNMENT};
+
+    use super::*;
+    use crate::report::MessageFormat;
+
+    // Doesn't shrink under LZ4, unlike the runs of zeros.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pack_round_trip() {
+        let out_dir = std::env::temp_dir().join(format!("rsrc-{}-pack", std::process::id()));
+        fs::create_dir_all(&out_dir).unwrap();
+
+        // Odd sizes, so entries only line up if they're padded.
+        let files = [("a", vec![0; 1001]), ("b", noise(777)), ("c", vec![0; 3])];
+        let mut manifest = Manifest::default();
+        for (name, data) in &files {
+            fs::write(out_dir.join(name), data).unwrap();
+            manifest.entries.push(ManifestEntry {
+                name: name.to_string(),
+                kind: AssetKind::File,
+                path: name.to_string(),
+                size: data.len() as u64,
+                hash: xxhash_rust::xxh3::xxh3_128(data),
+            });
+        }
+        let manifest = rkyv::to_bytes::<rancor::Error>(&manifest).unwrap();
+        fs::write(out_dir.join(MANIFEST_FILE_NAME), &manifest).unwrap();
+
+        let options = PackOptions {
+            path: out_dir.join("test.pak"),
+            compress: true,
+        };
+        write_pack(Reporter::new(MessageFormat::Json), &out_dir, &options)
+            .unwrap_or_else(|e| panic!("{e}"));
+        let bytes = fs::read(&options.path).unwrap();
+        fs::remove_dir_all(&out_dir).unwrap();
+
+        let header = PackHeader::from_bytes(&bytes).unwrap();
+        assert_eq!(header.toc_offset % PACK_ALIGNMENT, 0);
+        let toc_start = header.toc_offset as usize;
+        let toc_end = toc_start + header.toc_size as usize;
+        assert_eq!(toc_end, bytes.len());
+        let toc = rkyv::from_bytes::<PackToc, rancor::Error>(&bytes[toc_start..toc_end]).unwrap();
+
+        let compression: Vec<Compression> =
+            toc.entries.iter().map(|entry| entry.compression).collect();
+        assert_eq!(
+            compression,
+            [Compression::Lz4, Compression::None, Compression::None]
+        );
+
+        for (entry, (name, data)) in toc.entries.iter().zip(&files) {
+            assert_eq!(entry.name, *name);
+            assert_eq!(entry.offset % PACK_ALIGNMENT, 0);
+            assert_eq!(entry.hash, xxhash_rust::xxh3::xxh3_128(data));
+
+            let stored = &bytes[entry.offset as usize..(entry.offset + entry.size) as usize];
+            let unpacked = match entry.compression {
+                Compression::None => stored.to_vec(),
+                Compression::Lz4 => {
+                    lz4_flex::decompress(stored, entry.uncompressed_size as usize).unwrap()
+                }
+            };
+            assert_eq!(unpacked, *data);
+        }
+    }
+}
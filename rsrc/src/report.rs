@@ -0,0 +1,169 @@
+use std::{fmt::Display, path::Path};
+
+use serde::Serialize;
+
+use crate::diagnostic::Diagnostic;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MessageFormat {
+    // Progress as we go, and a report of everything that failed at the end.
+    #[default]
+    Human,
+    // One JSON object per line on stdout, for tools to pick through.
+    Json,
+}
+
+#[derive(Default)]
+pub struct BuildSummary {
+    pub built: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+impl BuildSummary {
+    pub fn total(&self) -> usize {
+        self.built + self.skipped + self.failed
+    }
+}
+
+impl Display for BuildSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} built, {} up to date, {} failed",
+            self.built, self.skipped, self.failed
+        )
+    }
+}
+
+// Everything that can go out in `MessageFormat::Json`, tagged with its kind in `reason`.
+#[derive(Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+enum Message<'a> {
+    AssetBuilt {
+        source: &'a Path,
+        output: &'a Path,
+    },
+    AssetFailed {
+        source: &'a Path,
+        diagnostics: &'a [Diagnostic],
+    },
+    Watching {
+        source_dir: &'a Path,
+    },
+    OutputRemoved {
+        output: &'a Path,
+    },
+    PackWritten {
+        output: &'a Path,
+        entries: usize,
+    },
+    BuildFinished {
+        built: usize,
+        skipped: usize,
+        failed: usize,
+    },
+    Error {
+        message: &'a str,
+    },
+}
+
+// Tells whoever ran us what's going on, in whichever format they asked for.
+#[derive(Clone, Copy)]
+pub struct Reporter {
+    format: MessageFormat,
+}
+
+impl Reporter {
+    pub fn new(format: MessageFormat) -> Self {
+        Self { format }
+    }
+
+    fn emit(&self, message: &Message) {
+        match serde_json::to_string(message) {
+            Ok(json) => println!("{json}"),
+            Err(e) => eprintln!("error: failed to serialize message: {e}"),
+        }
+    }
+
+    // About to build `source` into `output`.
+    pub fn building(&self, source: &Path, output: &Path) {
+        if self.format == MessageFormat::Human {
+            println!("{} -> {}", source.display(), output.display());
+        }
+    }
+
+    pub fn built(&self, source: &Path, output: &Path) {
+        if self.format == MessageFormat::Json {
+            self.emit(&Message::AssetBuilt { source, output });
+        }
+    }
+
+    // Done with the first build, and waiting for changes to `source_dir`.
+    pub fn watching(&self, source_dir: &Path) {
+        match self.format {
+            MessageFormat::Human => println!("watching {} for changes", source_dir.display()),
+            MessageFormat::Json => self.emit(&Message::Watching { source_dir }),
+        }
+    }
+
+    // `output` was removed because its source is gone.
+    pub fn removed(&self, output: &Path) {
+        match self.format {
+            MessageFormat::Human => println!("removed {}", output.display()),
+            MessageFormat::Json => self.emit(&Message::OutputRemoved { output }),
+        }
+    }
+
+    // Everything in the output dir was bundled into the pack at `output`.
+    pub fn packed(&self, output: &Path, entries: usize) {
+        match self.format {
+            MessageFormat::Human => println!("packed {entries} assets into {}", output.display()),
+            MessageFormat::Json => self.emit(&Message::PackWritten { output, entries }),
+        }
+    }
+
+    // Something went wrong outside of building any one asset, e.g. while watching.
+    pub fn error(&self, message: impl Display) {
+        match self.format {
+            MessageFormat::Human => eprintln!("error: {message}"),
+            MessageFormat::Json => self.emit(&Message::Error {
+                message: &message.to_string(),
+            }),
+        }
+    }
+
+    // Failures are grouped by the source that failed to build, in the order they're given.
+    pub fn finished<'a>(
+        &self,
+        summary: &BuildSummary,
+        failures: impl IntoIterator<Item = (&'a Path, &'a [Diagnostic])>,
+    ) {
+        match self.format {
+            MessageFormat::Human => {
+                for (source, diagnostics) in failures {
+                    eprintln!("failed to build {}", source.display());
+                    for diagnostic in diagnostics {
+                        eprintln!("    {diagnostic}");
+                    }
+                }
+
+                println!("{summary}");
+            }
+            MessageFormat::Json => {
+                for (source, diagnostics) in failures {
+                    self.emit(&Message::AssetFailed {
+                        source,
+                        diagnostics,
+                    });
+                }
+
+                self.emit(&Message::BuildFinished {
+                    built: summary.built,
+                    skipped: summary.skipped,
+                    failed: summary.failed,
+                });
+            }
+        }
+    }
+}
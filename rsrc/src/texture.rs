@@ -0,0 +1,253 @@
+use std::{fmt::Display, ops::Range, path::Path};
+
+use common::{Texture, TextureFormat, TextureMip};
+
+pub enum TextureError {
+    ImageError(image::ImageError),
+    FormatError(&'static str),
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(value: image::ImageError) -> Self {
+        TextureError::ImageError(value)
+    }
+}
+
+impl Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::ImageError(error) => write!(f, "image load error: {error}"),
+            TextureError::FormatError(s) => write!(f, "texture format error: {s}"),
+        }
+    }
+}
+
+const BYTES_PER_PIXEL: usize = 4;
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+
+    (value * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+// Source texels covered by destination texel `dst` along one axis. The last one also picks up
+// the leftover texel of an odd size.
+fn source_range(dst: u32, dst_size: u32, src_size: u32) -> Range<u32> {
+    let start = (dst * 2).min(src_size - 1);
+    let end = if dst == dst_size - 1 {
+        src_size
+    } else {
+        dst * 2 + 2
+    };
+
+    start..end
+}
+
+// Halve an RGBA8 image with a box filter.
+fn downsample(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    format: TextureFormat,
+) -> (Vec<u8>, u32, u32) {
+    let next_width = (width / 2).max(1);
+    let next_height = (height / 2).max(1);
+
+    // Averaging sRGB values directly darkens the smaller mips, so filter in linear space.
+    let to_linear: Vec<f32> = (0..=u8::MAX)
+        .map(|v| match format {
+            TextureFormat::Rgba8Srgb => srgb_to_linear(v),
+            TextureFormat::Rgba8Unorm => v as f32 / 255.0,
+        })
+        .collect();
+
+    let mut next = Vec::with_capacity(next_width as usize * next_height as usize * BYTES_PER_PIXEL);
+
+    for y in 0..next_height {
+        for x in 0..next_width {
+            let mut sum = [0.0f32; BYTES_PER_PIXEL];
+            let mut count = 0.0;
+
+            for src_y in source_range(y, next_height, height) {
+                for src_x in source_range(x, next_width, width) {
+                    let idx = (src_y as usize * width as usize + src_x as usize) * BYTES_PER_PIXEL;
+
+                    for (channel, total) in sum.iter_mut().enumerate() {
+                        let value = pixels[idx + channel];
+
+                        // Alpha is always linear.
+                        *total += if channel == 3 {
+                            value as f32 / 255.0
+                        } else {
+                            to_linear[value as usize]
+                        };
+                    }
+                    count += 1.0;
+                }
+            }
+
+            for (channel, total) in sum.iter().enumerate() {
+                let average = total / count;
+
+                next.push(match format {
+                    TextureFormat::Rgba8Srgb if channel != 3 => linear_to_srgb(average),
+                    _ => (average * 255.0).round().clamp(0.0, 255.0) as u8,
+                });
+            }
+        }
+    }
+
+    (next, next_width, next_height)
+}
+
+// Build a texture with a full mip chain from tightly packed RGBA8 pixels.
+pub fn new_texture_from_rgba8(
+    name: String,
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+) -> Result<Texture, TextureError> {
+    if width == 0 || height == 0 {
+        return Err(TextureError::FormatError("texture had zero size"));
+    }
+
+    if pixels.len() != width as usize * height as usize * BYTES_PER_PIXEL {
+        return Err(TextureError::FormatError(
+            "texture data did not match its size",
+        ));
+    }
+
+    let mut mips = Vec::new();
+    let mut data = Vec::new();
+
+    let mut level = pixels;
+    let (mut level_width, mut level_height) = (width, height);
+
+    loop {
+        mips.push(TextureMip {
+            width: level_width,
+            height: level_height,
+            offset: data.len() as u32,
+            size: level.len() as u32,
+        });
+        data.extend_from_slice(&level);
+
+        if level_width == 1 && level_height == 1 {
+            break;
+        }
+
+        (level, level_width, level_height) = downsample(&level, level_width, level_height, format);
+    }
+
+    Ok(Texture {
+        name,
+        format,
+        mips,
+        data,
+    })
+}
+
+// Standalone images are assumed to hold color data.
+pub fn new_texture_from_image_file(path: &Path) -> Result<Texture, TextureError> {
+    let name = path
+        .file_stem()
+        .ok_or(TextureError::FormatError(
+            "image should be loaded from a path with filename",
+        ))?
+        .to_string_lossy()
+        .to_string();
+
+    let image = image::open(path)?.into_rgba8();
+    let (width, height) = image.dimensions();
+
+    new_texture_from_rgba8(
+        name,
+        TextureFormat::Rgba8Srgb,
+        width,
+        height,
+        image.into_raw(),
+    )
+}
+
+// Convert an image decoded by the glTF importer.
+pub fn new_texture_from_gltf_image(
+    name: String,
+    format: TextureFormat,
+    image: &gltf::image::Data,
+) -> Result<Texture, TextureError> {
+    use gltf::image::Format;
+
+    let pixels = &image.pixels;
+
+    // 16 bit and float channels get squashed down to 8 bits.
+    let unorm16 = |bytes: &[u8]| (u16::from_le_bytes([bytes[0], bytes[1]]) >> 8) as u8;
+    let float32 = |bytes: &[u8]| {
+        let value = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        (value * 255.0).round().clamp(0.0, 255.0) as u8
+    };
+
+    let rgba: Vec<u8> = match image.format {
+        Format::R8G8B8A8 => pixels.clone(),
+        Format::R8G8B8 => pixels
+            .chunks_exact(3)
+            .flat_map(|px| [px[0], px[1], px[2], u8::MAX])
+            .collect(),
+        Format::R8G8 => pixels
+            .chunks_exact(2)
+            .flat_map(|px| [px[0], px[1], 0, u8::MAX])
+            .collect(),
+        Format::R8 => pixels.iter().flat_map(|r| [*r, *r, *r, u8::MAX]).collect(),
+        Format::R16G16B16A16 => pixels.chunks_exact(2).map(unorm16).collect(),
+        Format::R16G16B16 => pixels
+            .chunks_exact(6)
+            .flat_map(|px| {
+                [
+                    unorm16(&px[0..]),
+                    unorm16(&px[2..]),
+                    unorm16(&px[4..]),
+                    u8::MAX,
+                ]
+            })
+            .collect(),
+        Format::R16G16 => pixels
+            .chunks_exact(4)
+            .flat_map(|px| [unorm16(&px[0..]), unorm16(&px[2..]), 0, u8::MAX])
+            .collect(),
+        Format::R16 => pixels
+            .chunks_exact(2)
+            .flat_map(|px| {
+                let r = unorm16(px);
+                [r, r, r, u8::MAX]
+            })
+            .collect(),
+        Format::R32G32B32A32FLOAT => pixels.chunks_exact(4).map(float32).collect(),
+        Format::R32G32B32FLOAT => pixels
+            .chunks_exact(12)
+            .flat_map(|px| {
+                [
+                    float32(&px[0..]),
+                    float32(&px[4..]),
+                    float32(&px[8..]),
+                    u8::MAX,
+                ]
+            })
+            .collect(),
+    };
+
+    new_texture_from_rgba8(name, format, image.width, image.height, rgba)
+}
@@ -0,0 +1,151 @@
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+use notify::{RecursiveMode, Watcher};
+
+use crate::{
+    build,
+    db::BuildDb,
+    find_files, get_dest_path,
+    manifest::write_manifest,
+    pack::{write_pack, PackOptions},
+    report::Reporter,
+    RsrcResult,
+};
+
+// Editors and exporters tend to save in several steps, e.g. writing a temporary file and renaming
+// it over the old one. Wait for things to go quiet so we only look at where they ended up.
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+// Keep `out_dir` up to date with `source_dir` until we're killed. Errors with individual files are
+// printed rather than returned, so a broken shader doesn't stop the watch.
+pub fn watch(
+    reporter: Reporter,
+    source_dir: &Path,
+    out_dir: &Path,
+    pack: Option<&PackOptions>,
+) -> RsrcResult<()> {
+    // Events come with the paths the watcher resolved, so spell ours the same way or working out
+    // where their outputs go won't line up.
+    let source_dir = &source_dir.canonicalize()?;
+    let out_dir = &out_dir.canonicalize()?;
+
+    let (sender, events) = mpsc::channel();
+
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(source_dir, RecursiveMode::Recursive)?;
+
+    reporter.watching(source_dir);
+
+    let mut changed = BTreeSet::new();
+    loop {
+        let event = events
+            .recv()
+            .map_err(|_e| "file watcher stopped".to_string())?;
+        collect_paths(reporter, event, &mut changed);
+
+        while let Ok(event) = events.recv_timeout(SETTLE_TIME) {
+            collect_paths(reporter, event, &mut changed);
+        }
+
+        // Only reads, some of them our own while updating the manifest, so there's nothing to redo.
+        if changed.is_empty() {
+            continue;
+        }
+
+        // Whatever happened to a path, be it a write, create, delete or either end of a rename,
+        // what's on disk now tells us what to do about it.
+        let db = BuildDb::load(out_dir);
+        let mut sources = BTreeSet::new();
+        for path in std::mem::take(&mut changed) {
+            // Anything built from it needs rebuilding too, e.g. shaders that include it.
+            sources.extend(
+                db.dependents(&path)
+                    .into_iter()
+                    .filter(|source| source.is_file()),
+            );
+
+            let result = if path.is_dir() {
+                find_files(&path).map(|files| sources.extend(files))
+            } else if path.is_file() {
+                sources.insert(path.clone());
+                Ok(())
+            } else {
+                remove_outputs(reporter, source_dir, out_dir, &path)
+            };
+
+            if let Err(e) = result {
+                reporter.error(format_args!("{}: {e}", path.display()));
+            }
+        }
+
+        // Failures were already reported, and might be fixed by the next change.
+        if !sources.is_empty() {
+            let sources: Vec<PathBuf> = sources.into_iter().collect();
+            if let Err(e) = build(reporter, source_dir, out_dir, &sources) {
+                reporter.error(e);
+            }
+        }
+
+        let manifest = find_files(source_dir)
+            .and_then(|sources| write_manifest(source_dir, out_dir, &sources));
+        if let Err(e) = manifest {
+            reporter.error(format_args!("failed to update the manifest: {e}"));
+            continue;
+        }
+
+        if let Some(pack) = pack {
+            if let Err(e) = write_pack(reporter, out_dir, pack) {
+                reporter.error(format_args!(
+                    "failed to update {}: {e}",
+                    pack.path.display()
+                ));
+            }
+        }
+    }
+}
+
+fn collect_paths(
+    reporter: Reporter,
+    event: notify::Result<notify::Event>,
+    changed: &mut BTreeSet<PathBuf>,
+) {
+    match event {
+        Ok(event) if event.kind.is_access() => {}
+        Ok(event) => changed.extend(event.paths),
+        Err(e) => reporter.error(format_args!("watch error: {e}")),
+    }
+}
+
+fn remove_outputs(
+    reporter: Reporter,
+    source_dir: &Path,
+    out_dir: &Path,
+    source: &Path,
+) -> RsrcResult<()> {
+    // Directories keep their names in the output, so if this was one, its outputs are in here.
+    let rel = source
+        .strip_prefix(source_dir)
+        .map_err(|_e| format!("could not calculate relative path for {}", source.display()))?;
+    let mirrored = out_dir.join(rel);
+    if mirrored.is_dir() {
+        fs::remove_dir_all(&mirrored)?;
+        reporter.removed(&mirrored);
+        return Ok(());
+    }
+
+    let dest = get_dest_path(source_dir, out_dir, source)?;
+    match fs::remove_file(&dest) {
+        Ok(()) => reporter.removed(&dest),
+        // It never had an output, e.g. a temporary file or something we skip.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use std::path::PathBuf;
+
+use urbrs::{assets::DATA_DIR, renderer::post::Tonemapper};
+
+pub struct HeadlessArgs {
+    pub width: u32,
+    pub height: u32,
+    pub frames: u32,
+}
+
+// Renderer settings, for windowed and headless rendering alike.
+pub struct RenderArgs {
+    // Samples per pixel. Clamped to what the device supports.
+    pub msaa_samples: u32,
+    pub tonemapper: Tonemapper,
+    // In stops.
+    pub exposure: f32,
+}
+
+pub struct Args {
+    // Set if we should render offscreen without creating a window.
+    pub headless: Option<HeadlessArgs>,
+    // Save a screenshot here. The first frame in windowed mode, the last one when headless.
+    pub screenshot: Option<PathBuf>,
+    // Either a directory of processed assets or a pack made from one.
+    pub assets: PathBuf,
+    pub render: RenderArgs,
+}
+
+const DEFAULT_WIDTH: u32 = 1920;
+const DEFAULT_HEIGHT: u32 = 1080;
+const DEFAULT_MSAA_SAMPLES: u32 = 4;
+
+fn parse_tonemapper(s: &str) -> anyhow::Result<Tonemapper> {
+    match s {
+        "aces" => Ok(Tonemapper::Aces),
+        "reinhard" => Ok(Tonemapper::Reinhard),
+        "agx" => Ok(Tonemapper::AgX),
+        _ => Err(anyhow::anyhow!(
+            "tonemapper should be one of aces, reinhard or agx"
+        )),
+    }
+}
+
+fn parse_size(s: &str) -> anyhow::Result<(u32, u32)> {
+    let (width, height) = s
+        .split_once('x')
+        .ok_or(anyhow::anyhow!("size should be formatted as WIDTHxHEIGHT"))?;
+
+    let width: u32 = width.parse()?;
+    let height: u32 = height.parse()?;
+
+    if width == 0 || height == 0 {
+        return Err(anyhow::anyhow!("size must be non-zero"));
+    }
+
+    Ok((width, height))
+}
+
+impl Args {
+    pub fn parse() -> anyhow::Result<Self> {
+        let mut headless = false;
+        let mut size = (DEFAULT_WIDTH, DEFAULT_HEIGHT);
+        let mut frames = 1;
+        let mut screenshot = None;
+        let mut assets = PathBuf::from(DATA_DIR);
+        let mut msaa_samples = DEFAULT_MSAA_SAMPLES;
+        let mut tonemapper = Tonemapper::default();
+        let mut exposure = 0.0;
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => headless = true,
+                "--size" => {
+                    let value = args
+                        .next()
+                        .ok_or(anyhow::anyhow!("--size requires a value"))?;
+                    size = parse_size(&value)?;
+                }
+                "--frames" => {
+                    let value = args
+                        .next()
+                        .ok_or(anyhow::anyhow!("--frames requires a value"))?;
+                    frames = value.parse()?;
+                }
+                "--screenshot" => {
+                    let value = args
+                        .next()
+                        .ok_or(anyhow::anyhow!("--screenshot requires a value"))?;
+                    screenshot = Some(PathBuf::from(value));
+                }
+                "--assets" => {
+                    let value = args
+                        .next()
+                        .ok_or(anyhow::anyhow!("--assets requires a value"))?;
+                    assets = PathBuf::from(value);
+                }
+                "--msaa" => {
+                    let value = args
+                        .next()
+                        .ok_or(anyhow::anyhow!("--msaa requires a value"))?;
+                    msaa_samples = value.parse()?;
+                    if msaa_samples == 0 {
+                        return Err(anyhow::anyhow!("--msaa must be at least 1"));
+                    }
+                }
+                "--tonemapper" => {
+                    let value = args
+                        .next()
+                        .ok_or(anyhow::anyhow!("--tonemapper requires a value"))?;
+                    tonemapper = parse_tonemapper(&value)?;
+                }
+                "--exposure" => {
+                    let value = args
+                        .next()
+                        .ok_or(anyhow::anyhow!("--exposure requires a value"))?;
+                    exposure = value.parse()?;
+                }
+                _ => return Err(anyhow::anyhow!("unknown argument {arg}")),
+            }
+        }
+
+        let headless = headless.then_some(HeadlessArgs {
+            width: size.0,
+            height: size.1,
+            frames,
+        });
+
+        Ok(Self {
+            headless,
+            screenshot,
+            assets,
+            render: RenderArgs {
+                msaa_samples,
+                tonemapper,
+                exposure,
+            },
+        })
+    }
+}
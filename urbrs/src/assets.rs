@@ -0,0 +1,331 @@
+use std::{
+    fs::{self, File},
+    ops::{Deref, Range},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Context as anyhow_context};
+use common::{Compression, Manifest, PackHeader, PackToc, MANIFEST_FILE_NAME};
+use memmap2::Mmap;
+use rkyv::{api::high::HighValidator, bytecheck::CheckBytes, rancor, util::AlignedVec, Portable};
+
+// Where rsrc puts processed assets, unless told otherwise.
+pub const DATA_DIR: &str = "./data";
+
+enum Source {
+    // An output dir of rsrc's, with a file per asset.
+    Loose {
+        root: PathBuf,
+        manifest: Manifest,
+    },
+    // A single file made with `rsrc --pack`, mapped for as long as we're around.
+    Pack {
+        path: PathBuf,
+        map: Arc<Mmap>,
+        toc: PackToc,
+    },
+}
+
+enum AssetData {
+    Mapped { map: Arc<Mmap>, range: Range<usize> },
+    // Aligned like entries in a pack, so archived data in it can be accessed the same way.
+    Decompressed(AlignedVec),
+}
+
+// The contents of one asset, straight out of the mapped file where possible.
+pub struct Asset {
+    data: AssetData,
+}
+
+impl Deref for Asset {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.data {
+            AssetData::Mapped { map, range } => &map[range.clone()],
+            AssetData::Decompressed(data) => data,
+        }
+    }
+}
+
+impl Asset {
+    // Validate the archived data in the asset, e.g. an `ArchivedModel`, and borrow it in place.
+    pub fn access<T>(&self) -> anyhow::Result<&T>
+    where
+        T: Portable + for<'a> CheckBytes<HighValidator<'a, rancor::Error>>,
+    {
+        Ok(rkyv::access::<T, rancor::Error>(self)?)
+    }
+}
+
+fn map_file(path: &Path) -> anyhow::Result<Mmap> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+
+    // SAFETY: The file mustn't change while it's mapped. rsrc replaces every file it writes, packs
+    // and loose outputs alike, instead of writing over them.
+    Ok(unsafe { Mmap::map(&file)? })
+}
+
+// Where `offset` and `size` point in a pack, if that's actually inside it.
+fn pack_range(map: &Mmap, offset: u64, size: u64) -> anyhow::Result<Range<usize>> {
+    let start = usize::try_from(offset)?;
+    let end = start
+        .checked_add(usize::try_from(size)?)
+        .filter(|end| *end <= map.len())
+        .ok_or(anyhow!("pack is truncated"))?;
+
+    Ok(start..end)
+}
+
+// Processed assets, looked up by name in either the manifest rsrc writes next to them or the
+// table of contents of a pack.
+pub struct Assets {
+    source: Source,
+}
+
+impl Assets {
+    // A pack if `path` is a file, otherwise a directory of loose assets.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if path.is_file() {
+            Self::open_pack(path)
+        } else {
+            Self::load(path)
+        }
+    }
+
+    pub fn load(root: &Path) -> anyhow::Result<Self> {
+        let path = root.join(MANIFEST_FILE_NAME);
+        let bytes = fs::read(&path).with_context(|| {
+            format!(
+                "failed to read asset manifest {}, have the assets been processed?",
+                path.display()
+            )
+        })?;
+        let manifest = rkyv::from_bytes::<Manifest, rancor::Error>(&bytes)?;
+
+        Ok(Self {
+            source: Source::Loose {
+                root: root.to_path_buf(),
+                manifest,
+            },
+        })
+    }
+
+    pub fn open_pack(path: &Path) -> anyhow::Result<Self> {
+        let map = map_file(path)?;
+        let header = PackHeader::from_bytes(&map)
+            .map_err(|e| anyhow!("failed to read pack {}: {e}", path.display()))?;
+
+        let toc_range = pack_range(&map, header.toc_offset, header.toc_size)?;
+        let toc = rkyv::from_bytes::<PackToc, rancor::Error>(&map[toc_range])
+            .with_context(|| format!("failed to read the contents of pack {}", path.display()))?;
+
+        Ok(Self {
+            source: Source::Pack {
+                path: path.to_path_buf(),
+                map: Arc::new(map),
+                toc,
+            },
+        })
+    }
+
+    // Read the manifest or pack again, after rsrc has changed it.
+    pub fn reload(&self) -> anyhow::Result<Self> {
+        match &self.source {
+            Source::Loose { root, .. } => Self::load(root),
+            Source::Pack { path, .. } => Self::open_pack(path),
+        }
+    }
+
+    // The directory loose assets are in. None if they're packed.
+    pub fn root(&self) -> Option<&Path> {
+        match &self.source {
+            Source::Loose { root, .. } => Some(root),
+            Source::Pack { .. } => None,
+        }
+    }
+
+    pub fn manifest_path(&self) -> Option<PathBuf> {
+        self.root().map(|root| root.join(MANIFEST_FILE_NAME))
+    }
+
+    // Where the asset called `name`, e.g. "models/jerma", is on disk. Packed assets don't have a
+    // file of their own.
+    pub fn path(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let Source::Loose { root, manifest } = &self.source else {
+            return Err(anyhow!("{name} is in a pack, not a file of its own"));
+        };
+
+        let entry = manifest
+            .get(name)
+            .ok_or(anyhow!("no asset called {name} in the manifest"))?;
+
+        Ok(root.join(&entry.path))
+    }
+
+    pub fn read(&self, name: &str) -> anyhow::Result<Asset> {
+        let (map, toc) = match &self.source {
+            Source::Loose { .. } => {
+                let map = map_file(&self.path(name)?)?;
+                let range = 0..map.len();
+                let data = AssetData::Mapped {
+                    map: Arc::new(map),
+                    range,
+                };
+
+                return Ok(Asset { data });
+            }
+            Source::Pack { map, toc, .. } => (map, toc),
+        };
+
+        let entry = toc
+            .get(name)
+            .ok_or(anyhow!("no asset called {name} in the pack"))?;
+        let range = pack_range(map, entry.offset, entry.size)?;
+
+        let data = match entry.compression {
+            Compression::None => AssetData::Mapped {
+                map: map.clone(),
+                range,
+            },
+            Compression::Lz4 => {
+                let mut data = AlignedVec::new();
+                data.resize(usize::try_from(entry.uncompressed_size)?, 0);
+
+                let size = lz4_flex::decompress_into(&map[range], &mut data)
+                    .with_context(|| format!("failed to decompress {name}"))?;
+                if size != data.len() {
+                    return Err(anyhow!("{name} decompressed to the wrong size"));
+                }
+
+                AssetData::Decompressed(data)
+            }
+        };
+
+        Ok(Asset { data })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{ArchivedModel, AssetKind, Model, PackWriter, Vertex, PACK_ALIGNMENT};
+
+    use super::*;
+
+    fn model(name: &str) -> Model {
+        let vertices: Vec<Vertex> = (0..64)
+            .map(|idx| Vertex {
+                position: [idx as f32, 0.0, 0.0],
+                normal: [0.0, 0.0, 1.0],
+                tex_coord: [0.0; 2],
+                tangent: [1.0, 0.0, 0.0, 1.0],
+                color: [1.0; 4],
+            })
+            .collect();
+
+        Model {
+            name: name.to_string(),
+            indices: (0..vertices.len() as u32).collect(),
+            vertices,
+            submeshes: Vec::new(),
+            materials: Vec::new(),
+            textures: Vec::new(),
+            lights: Vec::new(),
+        }
+    }
+
+    // Write `bytes` somewhere no other test is using, and try to open it as a pack.
+    fn open_pack_bytes(name: &str, bytes: &[u8]) -> anyhow::Result<Assets> {
+        let path = std::env::temp_dir().join(format!("urbrs-{}-{name}.pak", std::process::id()));
+        fs::write(&path, bytes)?;
+
+        let assets = Assets::open_pack(&path);
+        // The map outlives the file on everything we run tests on.
+        fs::remove_file(&path)?;
+
+        assets
+    }
+
+    fn model_pack() -> (Vec<u8>, [Model; 2]) {
+        let models = [model("compressed"), model("uncompressed")];
+        let compressed = rkyv::to_bytes::<rancor::Error>(&models[0]).unwrap();
+        let uncompressed = rkyv::to_bytes::<rancor::Error>(&models[1]).unwrap();
+
+        let mut pack = PackWriter::default();
+        pack.add(
+            "models/compressed".to_string(),
+            AssetKind::Model,
+            &compressed,
+            Some(&lz4_flex::compress(&compressed)),
+            0,
+        );
+        pack.add(
+            "models/uncompressed".to_string(),
+            AssetKind::Model,
+            &uncompressed,
+            None,
+            0,
+        );
+        let bytes = pack.finish().unwrap();
+
+        (bytes, models)
+    }
+
+    #[test]
+    fn pack_round_trip() {
+        let (bytes, models) = model_pack();
+        let assets = open_pack_bytes("round_trip", &bytes).unwrap();
+
+        let Source::Pack { toc, .. } = &assets.source else {
+            panic!("expected a pack");
+        };
+        assert!(toc
+            .entries
+            .iter()
+            .all(|entry| entry.offset % PACK_ALIGNMENT == 0));
+
+        for model in &models {
+            let asset = assets.read(&format!("models/{}", model.name)).unwrap();
+            assert_eq!(asset.as_ptr() as u64 % PACK_ALIGNMENT, 0);
+
+            let archived = asset.access::<ArchivedModel>().unwrap();
+            assert_eq!(archived.name, model.name);
+            assert_eq!(archived.indices.len(), model.indices.len());
+            assert!(archived
+                .indices
+                .iter()
+                .zip(&model.indices)
+                .all(|(archived, index)| archived.to_native() == *index));
+            assert!(archived.vertices.iter().zip(&model.vertices).all(
+                |(archived, vertex)| archived.position.map(|p| p.to_native()) == vertex.position
+            ));
+        }
+
+        assert!(assets.read("models/missing").is_err());
+    }
+
+    #[test]
+    fn truncated_pack() {
+        let (bytes, _) = model_pack();
+
+        let err = open_pack_bytes("truncated", &bytes[..bytes.len() - 1])
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "pack is truncated");
+
+        let err = open_pack_bytes("truncated_header", &bytes[..PackHeader::SIZE - 1])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("file is too small to be a pack"));
+    }
+
+    #[test]
+    fn wrong_magic() {
+        let (mut bytes, _) = model_pack();
+        bytes[..8].copy_from_slice(b"NOTAPACK");
+
+        let err = open_pack_bytes("wrong_magic", &bytes).err().unwrap();
+        assert!(err.to_string().contains("not a pack file"));
+    }
+}
@@ -1,9 +1,139 @@
+use crate::vulkan::pipeline::DepthMode;
+
+// Everything `Camera::set_arcball` needs to place the camera.
+#[derive(Clone, Copy, Debug)]
+pub struct ArcballPose {
+    pub target: glam::Vec3,
+    pub angles: glam::Vec2,
+    pub dist: f32,
+}
+
+// Everything `Camera::set_fly_pose` needs to place the camera.
+#[derive(Clone, Copy, Debug)]
+pub struct FlyPose {
+    pub position: glam::Vec3,
+    // Pitch then yaw, like `ArcballPose`.
+    pub angles: glam::Vec2,
+}
+
+fn rotation_from_angles(angles: glam::Vec2) -> glam::Mat4 {
+    // Never rotate on Z since that would look weird.
+    glam::Mat4::from_euler(glam::EulerRot::YXZ, angles.y, angles.x, 0.0)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Projection {
+    Perspective { fov: f32, near: f32, far: f32 },
+    // Perspective with the far plane at infinity. Depth is reversed, which keeps precision far
+    // more even over distance than standard depth.
+    ReverseZ { fov: f32, near: f32 },
+    // No perspective, `height` world units fit on screen vertically.
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+impl Projection {
+    // Distances to the near and far planes. The far plane may be infinite.
+    pub fn depth_range(&self) -> (f32, f32) {
+        match *self {
+            Projection::Perspective { near, far, .. } => (near, far),
+            Projection::ReverseZ { near, .. } => (near, f32::INFINITY),
+            Projection::Orthographic { near, far, .. } => (near, far),
+        }
+    }
+
+    pub fn depth_mode(&self) -> DepthMode {
+        match self {
+            Projection::ReverseZ { .. } => DepthMode::Reversed,
+            Projection::Perspective { .. } | Projection::Orthographic { .. } => DepthMode::Standard,
+        }
+    }
+}
+
+// The planes bounding everything a camera can see. Each is a normal pointing into the frustum in
+// xyz and a distance in w, so a point is inside if `dot(plane.xyz, point) + plane.w >= 0`.
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    planes: [glam::Vec4; 6],
+}
+
+impl Frustum {
+    // Pulls the planes straight out of a view-projection matrix, so they're in world space.
+    pub fn from_matrix(vp: glam::Mat4) -> Self {
+        let [x, y, z, w] = [vp.row(0), vp.row(1), vp.row(2), vp.row(3)];
+
+        // Vulkan clips to -w <= x, y <= w and 0 <= z <= w.
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+            // An infinite far plane has no normal, and everything is in front of it.
+            let length = plane.truncate().length();
+            if length > 0.0 {
+                plane / length
+            } else {
+                plane
+            }
+        });
+
+        Self { planes }
+    }
+
+    pub fn planes(&self) -> &[glam::Vec4; 6] {
+        &self.planes
+    }
+
+    pub fn intersects_sphere(&self, center: glam::Vec3, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(center) + plane.w >= -radius)
+    }
+
+    pub fn intersects_box(&self, min: glam::Vec3, max: glam::Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            // Only the corner furthest along the normal has to be in front.
+            let normal = plane.truncate();
+            let corner = glam::Vec3::select(normal.cmpge(glam::Vec3::ZERO), max, min);
+
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+
+    // Whether model space bounds placed in the world by `transform` might be visible. Only false
+    // if they're definitely not.
+    pub fn intersects(&self, bounds: &common::Bounds, transform: glam::Mat4) -> bool {
+        let scale = transform
+            .x_axis
+            .truncate()
+            .length()
+            .max(transform.y_axis.truncate().length())
+            .max(transform.z_axis.truncate().length());
+
+        let center = transform.transform_point3(bounds.center.into());
+        if !self.intersects_sphere(center, bounds.radius * scale) {
+            return false;
+        }
+
+        // The sphere is quick to check but loose, so try again with the box.
+        // Transforming the box's extents with the absolute matrix gives the world box around it.
+        let min = glam::Vec3::from(bounds.min);
+        let max = glam::Vec3::from(bounds.max);
+        let box_center = transform.transform_point3((min + max) / 2.0);
+
+        let linear = glam::Mat3::from_mat4(transform);
+        let abs = glam::Mat3::from_cols(
+            linear.x_axis.abs(),
+            linear.y_axis.abs(),
+            linear.z_axis.abs(),
+        );
+        let extents = abs * ((max - min) / 2.0);
+
+        self.intersects_box(box_center - extents, box_center + extents)
+    }
+}
+
 pub struct Camera {
     pos: glam::Vec3,
     rot: glam::Quat,
 
-    _screen: glam::Vec2,
-    _fov: f32,
+    screen: glam::Vec2,
+    projection: Projection,
 
     proj: glam::Mat4,
     view: glam::Mat4,
@@ -13,23 +143,64 @@ pub struct Camera {
 // OpenGL style NDC. We invert the viewport over in Vulkan setup
 // so that the NDC works correctly.
 // +X up, +Y right, +Z out of the screen.
-fn compute_proj(screen: glam::Vec2, fov: f32) -> glam::Mat4 {
+fn compute_proj(screen: glam::Vec2, projection: Projection) -> glam::Mat4 {
     let aspect = screen.x / screen.y;
-    glam::Mat4::perspective_rh(fov, aspect, 0.01f32, 10000f32)
+
+    match projection {
+        Projection::Perspective { fov, near, far } => {
+            glam::Mat4::perspective_rh(fov, aspect, near, far)
+        }
+        Projection::ReverseZ { fov, near } => {
+            glam::Mat4::perspective_infinite_reverse_rh(fov, aspect, near)
+        }
+        Projection::Orthographic { height, near, far } => {
+            let half_height = height / 2.0;
+            let half_width = half_height * aspect;
+
+            glam::Mat4::orthographic_rh(
+                -half_width,
+                half_width,
+                -half_height,
+                half_height,
+                near,
+                far,
+            )
+        }
+    }
 }
 
 impl Camera {
-    pub fn new(screen: glam::Vec2, fov: f32) -> Self {
+    pub fn new(screen: glam::Vec2, projection: Projection) -> Self {
         Self {
             pos: glam::Vec3::default(),
             rot: glam::Quat::default(),
-            _screen: screen,
-            _fov: fov,
-            proj: compute_proj(screen, fov),
+            screen,
+            projection,
+            proj: compute_proj(screen, projection),
             view: glam::Mat4::default(),
         }
     }
 
+    // Call when the screen size changes, e.g. when the window is resized.
+    pub fn set_screen(&mut self, screen: glam::Vec2) {
+        self.screen = screen;
+        self.proj = compute_proj(self.screen, self.projection);
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+        self.proj = compute_proj(self.screen, self.projection);
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    // How the depth buffer should be cleared and compared against for this camera.
+    pub fn depth_mode(&self) -> DepthMode {
+        self.projection.depth_mode()
+    }
+
     pub fn view(&self) -> glam::Mat4 {
         self.view
     }
@@ -42,23 +213,81 @@ impl Camera {
         self.proj * self.view
     }
 
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.vp())
+    }
+
+    // The corners of the slice of the view between `near` and `far` units in front of the camera,
+    // in world space. The near corners come first.
+    pub fn frustum_corners(&self, near: f32, far: f32) -> [glam::Vec3; 8] {
+        let aspect = self.screen.x / self.screen.y;
+
+        // Half the size of the view at a given distance.
+        let half_size = |dist: f32| match self.projection {
+            Projection::Perspective { fov, .. } | Projection::ReverseZ { fov, .. } => {
+                let half_height = dist * (fov / 2.0).tan();
+                glam::vec2(half_height * aspect, half_height)
+            }
+            Projection::Orthographic { height, .. } => glam::vec2(height * aspect, height) / 2.0,
+        };
+
+        let camera_to_world = glam::Mat4::from_rotation_translation(self.rot, self.pos);
+
+        let mut corners = [glam::Vec3::ZERO; 8];
+        for (slice, dist) in [near, far].into_iter().enumerate() {
+            let half_size = half_size(dist);
+
+            for (corner, sign) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+                .into_iter()
+                .enumerate()
+            {
+                let view_corner = glam::vec3(half_size.x * sign.0, half_size.y * sign.1, -dist);
+                corners[slice * 4 + corner] = camera_to_world.transform_point3(view_corner);
+            }
+        }
+
+        corners
+    }
+
     pub fn _transform(&self) -> (glam::Vec3, glam::Vec3) {
         (self.pos, self.rot.to_euler(glam::EulerRot::XYZ).into())
     }
 
+    pub fn position(&self) -> glam::Vec3 {
+        self.pos
+    }
+
+    // The direction the camera is looking in.
+    pub fn forward(&self) -> glam::Vec3 {
+        self.rot * glam::Vec3::NEG_Z
+    }
+
+    pub fn set_arcball_pose(&mut self, pose: ArcballPose) {
+        self.set_arcball(pose.target, pose.angles, pose.dist);
+    }
+
     pub fn set_arcball(&mut self, target: glam::Vec3, angles: glam::Vec2, dist: f32) {
         // Calculate a transform based on being `dist` units away from `target`, rotated by angles.
 
         // Build our initial offset.
         let offset = glam::Mat4::from_translation(glam::Vec3::new(0.0, 0.0, dist));
 
-        // First apply our rotation. Never rotate on Z since that would look weird.
-        let rotation = glam::Mat4::from_euler(glam::EulerRot::YXZ, angles.y, angles.x, 0.0);
+        // First apply our rotation.
+        let rotation = rotation_from_angles(angles);
 
         // And then finally move the whole setup over to our target.
         let translation = glam::Mat4::from_translation(target);
 
-        let transform = translation * rotation * offset;
+        self.set_transform(translation * rotation * offset);
+    }
+
+    pub fn set_fly_pose(&mut self, pose: FlyPose) {
+        let translation = glam::Mat4::from_translation(pose.position);
+
+        self.set_transform(translation * rotation_from_angles(pose.angles));
+    }
+
+    fn set_transform(&mut self, transform: glam::Mat4) {
         // Extract out our position and rotation to save in the struct.
         let (_, rot, pos) = transform.to_scale_rotation_translation();
 
@@ -67,3 +296,144 @@ impl Camera {
         self.view = transform.inverse();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: glam::Vec2 = glam::vec2(1920.0, 1080.0);
+
+    // With a 90 degree fov, the perspective projections are as tall as the orthographic one 10
+    // units in front of the camera, which is where most of these tests look.
+    const FOV: f32 = std::f32::consts::FRAC_PI_2;
+
+    const PERSPECTIVE: Projection = Projection::Perspective {
+        fov: FOV,
+        near: 0.1,
+        far: 100.0,
+    };
+    const REVERSE_Z: Projection = Projection::ReverseZ {
+        fov: FOV,
+        near: 0.1,
+    };
+    const ORTHOGRAPHIC: Projection = Projection::Orthographic {
+        height: 20.0,
+        near: 0.1,
+        far: 100.0,
+    };
+
+    // At the origin, looking down -Z.
+    fn frustum(projection: Projection) -> Frustum {
+        let mut camera = Camera::new(SCREEN, projection);
+        camera.set_fly_pose(FlyPose {
+            position: glam::Vec3::ZERO,
+            angles: glam::Vec2::ZERO,
+        });
+
+        camera.frustum()
+    }
+
+    #[test]
+    fn spheres() {
+        for projection in [PERSPECTIVE, REVERSE_Z, ORTHOGRAPHIC] {
+            let frustum = frustum(projection);
+
+            assert!(
+                frustum.intersects_sphere(glam::vec3(0.0, 0.0, -10.0), 1.0),
+                "{projection:?}: in front"
+            );
+            assert!(
+                !frustum.intersects_sphere(glam::vec3(0.0, 0.0, 10.0), 1.0),
+                "{projection:?}: behind"
+            );
+            assert!(
+                !frustum.intersects_sphere(glam::vec3(0.0, 12.0, -10.0), 1.0),
+                "{projection:?}: above"
+            );
+            assert!(
+                frustum.intersects_sphere(glam::vec3(0.0, 10.5, -10.0), 1.0),
+                "{projection:?}: straddling the top"
+            );
+            assert!(
+                frustum.intersects_sphere(glam::Vec3::ZERO, 0.5),
+                "{projection:?}: straddling the near plane"
+            );
+        }
+    }
+
+    #[test]
+    fn boxes() {
+        for projection in [PERSPECTIVE, REVERSE_Z, ORTHOGRAPHIC] {
+            let frustum = frustum(projection);
+
+            assert!(
+                frustum.intersects_box(glam::vec3(-1.0, -1.0, -11.0), glam::vec3(1.0, 1.0, -9.0)),
+                "{projection:?}: in front"
+            );
+            assert!(
+                !frustum.intersects_box(glam::vec3(-1.0, -1.0, 9.0), glam::vec3(1.0, 1.0, 11.0)),
+                "{projection:?}: behind"
+            );
+            assert!(
+                !frustum.intersects_box(glam::vec3(-1.0, 15.0, -11.0), glam::vec3(1.0, 20.0, -9.0)),
+                "{projection:?}: above"
+            );
+            assert!(
+                frustum.intersects_box(glam::vec3(-1.0, 9.0, -11.0), glam::vec3(1.0, 12.0, -9.0)),
+                "{projection:?}: straddling the top"
+            );
+            assert!(
+                frustum.intersects_box(glam::vec3(-1.0, -1.0, -1.0), glam::vec3(1.0, 1.0, 1.0)),
+                "{projection:?}: straddling the near plane"
+            );
+        }
+    }
+
+    #[test]
+    fn far_plane() {
+        let center = glam::vec3(0.0, 0.0, -200.0);
+        let (min, max) = (center - 1.0, center + 1.0);
+
+        for projection in [PERSPECTIVE, ORTHOGRAPHIC] {
+            let frustum = frustum(projection);
+
+            assert!(!frustum.intersects_sphere(center, 1.0), "{projection:?}");
+            assert!(!frustum.intersects_box(min, max), "{projection:?}");
+            assert!(
+                frustum.intersects_sphere(glam::vec3(0.0, 0.0, -100.0), 1.0),
+                "{projection:?}: straddling the far plane"
+            );
+        }
+
+        // The far plane is infinitely far away, so only the sides can cull.
+        let frustum = frustum(REVERSE_Z);
+        assert!(frustum.intersects_sphere(center, 1.0));
+        assert!(frustum.intersects_box(min, max));
+        assert!(frustum.intersects_sphere(glam::vec3(0.0, 0.0, -1.0e6), 1.0));
+    }
+
+    #[test]
+    fn transformed_bounds() {
+        let frustum = frustum(PERSPECTIVE);
+        let bounds = common::Bounds {
+            min: [-1.0; 3],
+            max: [1.0; 3],
+            center: [0.0; 3],
+            radius: 3.0_f32.sqrt(),
+        };
+
+        let in_front = glam::Mat4::from_translation(glam::vec3(0.0, 0.0, -10.0));
+        assert!(frustum.intersects(&bounds, in_front));
+
+        let behind = glam::Mat4::from_translation(glam::vec3(0.0, 0.0, 10.0));
+        assert!(!frustum.intersects(&bounds, behind));
+
+        // Scaled up enough to reach back into view.
+        let scaled = glam::Mat4::from_scale_rotation_translation(
+            glam::Vec3::splat(20.0),
+            glam::Quat::IDENTITY,
+            glam::vec3(0.0, 30.0, -10.0),
+        );
+        assert!(frustum.intersects(&bounds, scaled));
+    }
+}
@@ -0,0 +1,163 @@
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+use crate::{
+    camera::{ArcballPose, Camera, FlyPose},
+    input::Input,
+};
+
+// Radians per pixel of mouse motion.
+const LOOK_SPEED: f32 = 0.005;
+// Fraction of the distance to the target panned per pixel, so panning feels the same at any zoom.
+const PAN_SPEED: f32 = 0.001;
+// How much each mouse wheel line scales the orbit distance or fly speed by.
+const ZOOM_FACTOR: f32 = 1.1;
+const MIN_DIST: f32 = 0.1;
+const DEFAULT_DIST: f32 = 100.0;
+
+// Units per second.
+const DEFAULT_FLY_SPEED: f32 = 50.0;
+const FAST_MULTIPLIER: f32 = 4.0;
+
+// Looking straight up or down flips the view around, so stop just short.
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+// Long frames, e.g. while the window is being dragged, shouldn't send the camera flying.
+const MAX_DT: f32 = 0.1;
+
+fn look(angles: glam::Vec2, mouse_delta: glam::Vec2) -> glam::Vec2 {
+    let angles = angles - glam::vec2(mouse_delta.y, mouse_delta.x) * LOOK_SPEED;
+
+    glam::vec2(angles.x.clamp(-MAX_PITCH, MAX_PITCH), angles.y)
+}
+
+fn rotation(angles: glam::Vec2) -> glam::Quat {
+    glam::Quat::from_euler(glam::EulerRot::YXZ, angles.y, angles.x, 0.0)
+}
+
+// Drag with the left mouse button to rotate around the target, the right or middle button to pan,
+// and scroll to zoom.
+pub struct OrbitController {
+    pub pose: ArcballPose,
+}
+
+impl OrbitController {
+    pub fn new(pose: ArcballPose) -> Self {
+        Self { pose }
+    }
+
+    pub fn update(&mut self, input: &Input) {
+        let mouse_delta = input.mouse_delta();
+
+        if input.is_button_down(MouseButton::Left) {
+            self.pose.angles = look(self.pose.angles, mouse_delta);
+        }
+
+        if input.is_button_down(MouseButton::Right) || input.is_button_down(MouseButton::Middle) {
+            // Drag the target along with the mouse, in the plane of the screen.
+            let rotation = rotation(self.pose.angles);
+            let right = rotation * glam::Vec3::X;
+            let up = rotation * glam::Vec3::Y;
+
+            self.pose.target +=
+                (up * mouse_delta.y - right * mouse_delta.x) * PAN_SPEED * self.pose.dist;
+        }
+
+        self.pose.dist = (self.pose.dist * ZOOM_FACTOR.powf(-input.scroll_delta())).max(MIN_DIST);
+    }
+}
+
+// Hold the right mouse button to look around, and move with WASD. Q and E move down and up,
+// shift moves faster, and scrolling changes the speed.
+pub struct FlyController {
+    pub pose: FlyPose,
+    pub speed: f32,
+
+    // How far in front of us to orbit around if we switch back to orbiting.
+    orbit_dist: f32,
+}
+
+impl FlyController {
+    pub fn new(pose: FlyPose) -> Self {
+        Self {
+            pose,
+            speed: DEFAULT_FLY_SPEED,
+            orbit_dist: DEFAULT_DIST,
+        }
+    }
+
+    pub fn update(&mut self, input: &Input, dt: f32) {
+        if input.is_button_down(MouseButton::Right) {
+            self.pose.angles = look(self.pose.angles, input.mouse_delta());
+        }
+
+        self.speed *= ZOOM_FACTOR.powf(input.scroll_delta());
+
+        let axis = |positive: KeyCode, negative: KeyCode| {
+            input.is_key_down(positive) as i32 as f32 - input.is_key_down(negative) as i32 as f32
+        };
+
+        // Move relative to where we're looking, but keep up and down straight along the world axis.
+        let rotation = rotation(self.pose.angles);
+        let direction = rotation * glam::Vec3::NEG_Z * axis(KeyCode::KeyW, KeyCode::KeyS)
+            + rotation * glam::Vec3::X * axis(KeyCode::KeyD, KeyCode::KeyA)
+            + glam::Vec3::Y * axis(KeyCode::KeyE, KeyCode::KeyQ);
+
+        let speed = if input.is_key_down(KeyCode::ShiftLeft) {
+            self.speed * FAST_MULTIPLIER
+        } else {
+            self.speed
+        };
+
+        // Normalize so moving diagonally isn't any faster.
+        self.pose.position += direction.normalize_or_zero() * speed * dt;
+    }
+}
+
+// Switch between modes with tab.
+pub enum CameraController {
+    Orbit(OrbitController),
+    Fly(FlyController),
+}
+
+impl CameraController {
+    pub fn new_orbit(pose: ArcballPose) -> Self {
+        Self::Orbit(OrbitController::new(pose))
+    }
+
+    // `camera` should be where this controller last put it, so switching doesn't make it jump.
+    pub fn update(&mut self, input: &Input, dt: f32, camera: &Camera) {
+        if input.was_key_pressed(KeyCode::Tab) {
+            self.toggle(camera);
+        }
+
+        let dt = dt.min(MAX_DT);
+        match self {
+            Self::Orbit(orbit) => orbit.update(input),
+            Self::Fly(fly) => fly.update(input, dt),
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        match self {
+            Self::Orbit(orbit) => camera.set_arcball_pose(orbit.pose),
+            Self::Fly(fly) => camera.set_fly_pose(fly.pose),
+        }
+    }
+
+    fn toggle(&mut self, camera: &Camera) {
+        *self = match self {
+            Self::Orbit(orbit) => Self::Fly(FlyController {
+                orbit_dist: orbit.pose.dist,
+                ..FlyController::new(FlyPose {
+                    position: camera.position(),
+                    angles: orbit.pose.angles,
+                })
+            }),
+            Self::Fly(fly) => Self::Orbit(OrbitController::new(ArcballPose {
+                target: camera.position() + camera.forward() * fly.orbit_dist,
+                angles: fly.pose.angles,
+                dist: fly.orbit_dist,
+            })),
+        }
+    }
+}
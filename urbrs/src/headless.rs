@@ -0,0 +1,77 @@
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    assets::Assets,
+    camera::{ArcballPose, Projection},
+    renderer::{
+        post::Tonemapper,
+        readback::Screenshot,
+        target::{OffscreenTarget, RenderTarget},
+        FrameStats, Renderer,
+    },
+    vulkan::context::Context,
+};
+
+// Like `Window`, but renders into an offscreen image instead of a swapchain.
+pub struct Headless {
+    context: Arc<Context>,
+    renderer: Renderer,
+}
+
+impl Headless {
+    pub fn new(width: u32, height: u32, assets: Assets) -> anyhow::Result<Self> {
+        let context = Arc::new(Context::new_headless()?);
+
+        let target = OffscreenTarget::new(context.clone(), width, height)?;
+        let renderer = Renderer::new(context.clone(), RenderTarget::Offscreen(target), assets)?;
+
+        Ok(Self { context, renderer })
+    }
+
+    pub fn render(&mut self) -> anyhow::Result<()> {
+        self.renderer.render()
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.renderer.set_projection(projection);
+    }
+
+    // Returns the sample count we actually got, see `Renderer::set_msaa_samples`.
+    pub fn set_msaa_samples(&mut self, samples: u32) -> anyhow::Result<u32> {
+        self.renderer.set_msaa_samples(samples)
+    }
+
+    pub fn set_tonemapper(&mut self, tonemapper: Tonemapper) {
+        self.renderer.set_tonemapper(tonemapper);
+    }
+
+    // In stops.
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.renderer.set_exposure(exposure);
+    }
+
+    pub fn set_fixed_camera(&mut self, pose: Option<ArcballPose>) {
+        self.renderer.set_fixed_camera(pose);
+    }
+
+    // What the last rendered frame drew.
+    pub fn stats(&self) -> FrameStats {
+        self.renderer.stats()
+    }
+
+    // Render a frame and read it back.
+    pub fn capture(&mut self) -> anyhow::Result<Screenshot> {
+        self.renderer.capture()
+    }
+
+    // Render a frame and save it as a PNG at `path`.
+    pub fn screenshot(&mut self, path: &Path) -> anyhow::Result<()> {
+        self.capture()?.save_png(path)
+    }
+
+    pub fn exit(&self) -> anyhow::Result<()> {
+        self.context.wait_idle()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+
+use winit::{
+    event::{DeviceEvent, ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+
+// Roughly how many pixels a trackpad scrolls per mouse wheel line.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+// Keyboard and mouse state, collected from winit events between frames.
+#[derive(Default)]
+pub struct Input {
+    keys_down: HashSet<KeyCode>,
+    // Only the presses since the last `end_frame`, so holding a key doesn't repeat.
+    keys_pressed: HashSet<KeyCode>,
+    buttons_down: HashSet<MouseButton>,
+
+    // Accumulated since the last `end_frame`.
+    mouse_delta: glam::Vec2,
+    // In mouse wheel lines, positive when scrolling up.
+    scroll_delta: f32,
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(code),
+                        state,
+                        repeat,
+                        ..
+                    },
+                ..
+            } => match state {
+                ElementState::Pressed => {
+                    self.keys_down.insert(*code);
+                    if !repeat {
+                        self.keys_pressed.insert(*code);
+                    }
+                }
+                ElementState::Released => {
+                    self.keys_down.remove(code);
+                }
+            },
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed => {
+                    self.buttons_down.insert(*button);
+                }
+                ElementState::Released => {
+                    self.buttons_down.remove(button);
+                }
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll_delta += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / PIXELS_PER_LINE,
+                };
+            }
+            // We won't hear about releases while unfocused, so don't leave anything stuck down.
+            WindowEvent::Focused(false) => {
+                self.keys_down.clear();
+                self.buttons_down.clear();
+            }
+            _ => {}
+        }
+    }
+
+    // Mouse motion comes from device events, so it's unaffected by cursor acceleration or the window edges.
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.mouse_delta += glam::vec2(delta.0 as f32, delta.1 as f32);
+        }
+    }
+
+    // Call once everything has had a chance to read this frame's input.
+    pub fn end_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.mouse_delta = glam::Vec2::ZERO;
+        self.scroll_delta = 0.0;
+    }
+
+    pub fn is_key_down(&self, code: KeyCode) -> bool {
+        self.keys_down.contains(&code)
+    }
+
+    pub fn was_key_pressed(&self, code: KeyCode) -> bool {
+        self.keys_pressed.contains(&code)
+    }
+
+    pub fn is_button_down(&self, button: MouseButton) -> bool {
+        self.buttons_down.contains(&button)
+    }
+
+    // In pixels, +X right and +Y down.
+    pub fn mouse_delta(&self) -> glam::Vec2 {
+        self.mouse_delta
+    }
+
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+}
@@ -0,0 +1,9 @@
+pub mod assets;
+pub mod camera;
+pub mod controller;
+pub mod headless;
+pub mod input;
+pub mod renderer;
+pub mod scene;
+pub mod vulkan;
+pub mod window;
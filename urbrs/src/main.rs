@@ -1,17 +1,35 @@
-use window::Window;
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use args::{Args, HeadlessArgs, RenderArgs};
+use urbrs::{assets::Assets, headless::Headless, window::Window};
 use winit::{
     application::ApplicationHandler,
-    event::WindowEvent,
+    event::{DeviceEvent, DeviceId, ElementState, KeyEvent, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    keyboard::{Key, NamedKey},
 };
 
-mod camera;
-mod renderer;
-mod vulkan;
-mod window;
+mod args;
 
 struct App {
     window: Option<Window>,
+    // Handed to the window once it's created.
+    assets: Option<Assets>,
+    // Screenshot to take as soon as the window is up.
+    initial_screenshot: Option<PathBuf>,
+    render_args: RenderArgs,
+}
+
+fn new_screenshot_path() -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+
+    PathBuf::from(format!("urbrs_{timestamp}.png"))
 }
 
 impl ApplicationHandler for App {
@@ -21,7 +39,20 @@ impl ApplicationHandler for App {
             return;
         }
 
-        self.window = Some(Window::new(event_loop).expect("window creation should succeed"))
+        let assets = self.assets.take().expect("assets should only be used once");
+        let mut window = Window::new(event_loop, assets).expect("window creation should succeed");
+
+        window
+            .set_msaa_samples(self.render_args.msaa_samples)
+            .expect("setting the sample count should succeed");
+        window.set_tonemapper(self.render_args.tonemapper);
+        window.set_exposure(self.render_args.exposure);
+
+        if let Some(path) = self.initial_screenshot.take() {
+            window.request_screenshot(path);
+        }
+
+        self.window = Some(window);
     }
 
     fn window_event(
@@ -30,14 +61,48 @@ impl ApplicationHandler for App {
         _window_id: winit::window::WindowId,
         event: WindowEvent,
     ) {
+        if let Some(window) = self.window.as_mut() {
+            window.handle_window_event(&event);
+        }
+
         match event {
             WindowEvent::CloseRequested => {
                 event_loop.exit();
             }
+            WindowEvent::Resized(size) => {
+                if let Some(window) = self.window.as_mut() {
+                    window.resize(size);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key: Key::Named(NamedKey::F12),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(window) = self.window.as_mut() {
+                    window.request_screenshot(new_screenshot_path());
+                }
+            }
             _ => {}
         }
     }
 
+    fn device_event(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+        _device_id: DeviceId,
+        event: DeviceEvent,
+    ) {
+        if let Some(window) = self.window.as_mut() {
+            window.handle_device_event(&event);
+        }
+    }
+
     fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
         if let Err(err) = self.window.as_mut().unwrap().render() {
             eprintln!("rendering failed: {err:?}");
@@ -52,10 +117,61 @@ impl ApplicationHandler for App {
     }
 }
 
+fn run_headless(
+    args: &HeadlessArgs,
+    assets: Assets,
+    screenshot: Option<&Path>,
+    render_args: &RenderArgs,
+) -> anyhow::Result<()> {
+    let mut headless = Headless::new(args.width, args.height, assets)?;
+    headless.set_msaa_samples(render_args.msaa_samples)?;
+    headless.set_tonemapper(render_args.tonemapper);
+    headless.set_exposure(render_args.exposure);
+
+    for i in 0..args.frames {
+        match screenshot {
+            Some(path) if i + 1 == args.frames => headless.screenshot(path)?,
+            _ => headless.render()?,
+        }
+    }
+
+    headless.exit()
+}
+
 fn main() {
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("invalid arguments: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    let assets = match Assets::open(&args.assets) {
+        Ok(assets) => assets,
+        Err(err) => {
+            eprintln!("failed to open assets: {err:?}");
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(headless_args) = &args.headless {
+        let screenshot = args.screenshot.as_deref();
+        if let Err(err) = run_headless(headless_args, assets, screenshot, &args.render) {
+            eprintln!("headless rendering failed: {err:?}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let event_loop = EventLoop::new().expect("event loop creation should succeed");
     event_loop.set_control_flow(ControlFlow::Poll);
 
-    let mut app = App { window: None };
+    let mut app = App {
+        window: None,
+        assets: Some(assets),
+        initial_screenshot: args.screenshot,
+        render_args: args.render,
+    };
     let _ = event_loop.run_app(&mut app);
 }
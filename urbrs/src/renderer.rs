@@ -1,161 +1,124 @@
-use std::{fs::File, io::Read, ops::Rem, path::Path, sync::Arc, time::Instant};
+use std::{ops::Rem, path::PathBuf, sync::Arc, time::Instant};
 
 use anyhow::{anyhow, Context as anyhow_context};
 use ash::vk::DescriptorType;
 use bytemuck::bytes_of;
-use common::{Model, Vertex};
+use common::{ArchivedModel, Vertex};
 use rkyv::rancor;
 
 use crate::{
-    camera::Camera,
-    renderer::{buffer::UniformBuffer, mesh::Mesh},
+    assets::{Asset, Assets},
+    camera::{ArcballPose, Camera, Projection},
+    controller::CameraController,
+    input::Input,
+    renderer::{
+        buffer::{StorageBuffer, UniformBuffer},
+        graph::{
+            BufferAccess, ImageAccess, PassContext, RenderGraph, ResolvedImage, TransientDesc,
+            TransientImages,
+        },
+        light::{gather_lights, LightData, MAX_LIGHTS},
+        material::{Materials, RetiredMaterials},
+        mesh::Mesh,
+        post::{PostProcess, Tonemapper, POST_SHADERS},
+        readback::{Readback, Screenshot},
+        reload::{is_same_file, AssetWatcher, DeletionQueue},
+        shadow::{compute_cascades, ShadowMap, CASCADE_COUNT, SHADOW_VERTEX_SHADER},
+        target::{RenderTarget, TargetImage},
+    },
+    scene::{Entity, Light, LightKind, MaterialHandle, MeshHandle, Scene, Transform},
     vulkan::{
         command::{CommandBuffer, CommandPool},
         context::Context,
         descriptor::{DescriptorPool, DescriptorSet, DescriptorSetLayout},
         device::Device,
+        image::Image,
         mesh::MeshVertex,
         phys_device::PhysicalDevice,
-        pipeline::{Pipeline, PipelineBuilder},
-        swapchain::Swapchain,
+        pipeline::{DepthMode, Pipeline, PipelineBuilder},
         sync::{Fence, Semaphore},
+        upload::Uploader,
         util::{self},
     },
 };
 
 mod buffer;
+mod graph;
+mod light;
+mod material;
 mod mesh;
-
-struct DepthBuffer {
-    context: Arc<Context>,
-    image: ash::vk::Image,
-    allocation: gpu_allocator::vulkan::Allocation,
-    image_view: ash::vk::ImageView,
-    format: ash::vk::Format,
-}
-
-impl DepthBuffer {
-    const DESIRED_DEPTH_FORMATS: [ash::vk::Format; 3] = [
-        ash::vk::Format::D32_SFLOAT,
-        ash::vk::Format::D32_SFLOAT_S8_UINT,
-        ash::vk::Format::D24_UNORM_S8_UINT,
-    ];
-
-    fn select_depth_format(physical_device: &PhysicalDevice) -> Option<ash::vk::Format> {
-        for format in Self::DESIRED_DEPTH_FORMATS {
-            let props = physical_device.get_format_properties(format);
-            if props
-                .optimal_tiling_features
-                .contains(ash::vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
-            {
-                return Some(format);
-            }
+pub mod post;
+pub mod readback;
+mod reload;
+mod shadow;
+pub mod target;
+mod texture;
+
+const DESIRED_DEPTH_FORMATS: [ash::vk::Format; 3] = [
+    ash::vk::Format::D32_SFLOAT,
+    ash::vk::Format::D32_SFLOAT_S8_UINT,
+    ash::vk::Format::D24_UNORM_S8_UINT,
+];
+
+fn select_depth_format(physical_device: &PhysicalDevice) -> Option<ash::vk::Format> {
+    for format in DESIRED_DEPTH_FORMATS {
+        let props = physical_device.get_format_properties(format);
+        if props
+            .optimal_tiling_features
+            .contains(ash::vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+        {
+            return Some(format);
         }
-
-        None
     }
 
-    pub fn new(context: Arc<Context>, width: u32, height: u32) -> anyhow::Result<Self> {
-        let depth_format = Self::select_depth_format(context.device().physical_device())
-            .ok_or(anyhow::anyhow!("no valid depth format found"))?;
+    None
+}
 
-        let image_create_info = ash::vk::ImageCreateInfo::default()
-            .image_type(ash::vk::ImageType::TYPE_2D)
-            .extent(
-                ash::vk::Extent3D::default()
-                    .width(width)
-                    .height(height)
-                    .depth(1),
-            )
-            .mip_levels(1)
-            .array_layers(1)
-            .format(depth_format)
-            .tiling(ash::vk::ImageTiling::OPTIMAL)
-            .usage(ash::vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
-            .sharing_mode(ash::vk::SharingMode::EXCLUSIVE)
-            .samples(ash::vk::SampleCountFlags::TYPE_1);
+// The main pass renders lighting in HDR, and the post-process pass tonemaps it into the target.
+const HDR_FORMAT: ash::vk::Format = ash::vk::Format::R16G16B16A16_SFLOAT;
 
-        unsafe {
-            let image = context
-                .device()
-                .handle()
-                .create_image(&image_create_info, None)?;
-
-            let mem_reqs = context
-                .device()
-                .handle()
-                .get_image_memory_requirements(image);
-
-            let allocation =
-                context.alloc_gpu_mem(&gpu_allocator::vulkan::AllocationCreateDesc {
-                    name: "depth_buffer",
-                    requirements: mem_reqs,
-                    location: gpu_allocator::MemoryLocation::GpuOnly,
-                    linear: false,
-                    allocation_scheme: gpu_allocator::vulkan::AllocationScheme::DedicatedImage(
-                        image,
-                    ),
-                })?;
-
-            context.device().handle().bind_image_memory(
-                image,
-                allocation.memory(),
-                allocation.offset(),
-            )?;
+struct HdrBuffer {
+    image: Image,
+}
 
-            let mapping = ash::vk::ComponentMapping::default()
-                .r(ash::vk::ComponentSwizzle::IDENTITY)
-                .g(ash::vk::ComponentSwizzle::IDENTITY)
-                .b(ash::vk::ComponentSwizzle::IDENTITY)
-                .a(ash::vk::ComponentSwizzle::IDENTITY);
-
-            let range = ash::vk::ImageSubresourceRange::default()
-                .aspect_mask(ash::vk::ImageAspectFlags::DEPTH)
-                .base_mip_level(0)
-                .level_count(1)
-                .base_array_layer(0)
-                .layer_count(1);
-
-            let image_view_info = ash::vk::ImageViewCreateInfo::default()
-                .image(image)
-                .format(depth_format)
-                .view_type(ash::vk::ImageViewType::TYPE_2D)
-                .components(mapping)
-                .subresource_range(range);
-
-            let image_view = context
-                .device()
-                .handle()
-                .create_image_view(&image_view_info, None)?;
-
-            Ok(Self {
-                context,
-                image,
-                allocation,
-                image_view,
-                format: depth_format,
-            })
-        }
+impl HdrBuffer {
+    pub fn new(context: Arc<Context>, extent: ash::vk::Extent2D) -> anyhow::Result<Self> {
+        let image = Image::new(
+            context,
+            "hdr_color_buffer",
+            HDR_FORMAT,
+            extent,
+            1,
+            ash::vk::ImageUsageFlags::COLOR_ATTACHMENT | ash::vk::ImageUsageFlags::SAMPLED,
+            ash::vk::ImageAspectFlags::COLOR,
+        )?;
+
+        Ok(Self { image })
     }
 }
 
-impl Drop for DepthBuffer {
-    fn drop(&mut self) {
-        let allocation = std::mem::take(&mut self.allocation);
-        self.context.free_gpu_mem(allocation).unwrap();
+// The highest sample count the device supports, up to `requested`.
+fn select_sample_count(
+    physical_device: &PhysicalDevice,
+    requested: u32,
+) -> ash::vk::SampleCountFlags {
+    const SAMPLE_COUNTS: [ash::vk::SampleCountFlags; 7] = [
+        ash::vk::SampleCountFlags::TYPE_64,
+        ash::vk::SampleCountFlags::TYPE_32,
+        ash::vk::SampleCountFlags::TYPE_16,
+        ash::vk::SampleCountFlags::TYPE_8,
+        ash::vk::SampleCountFlags::TYPE_4,
+        ash::vk::SampleCountFlags::TYPE_2,
+        ash::vk::SampleCountFlags::TYPE_1,
+    ];
 
-        unsafe {
-            self.context
-                .device()
-                .handle()
-                .destroy_image_view(self.image_view, None);
-
-            self.context
-                .device()
-                .handle()
-                .destroy_image(self.image, None);
-        };
-    }
+    let supported = physical_device.supported_sample_counts();
+
+    // Each flag's value is its sample count.
+    SAMPLE_COUNTS
+        .into_iter()
+        .find(|count| count.as_raw() <= requested && supported.contains(*count))
+        .unwrap_or(ash::vk::SampleCountFlags::TYPE_1)
 }
 
 struct Frame {
@@ -169,7 +132,7 @@ struct Frame {
 
 struct FrameBeginResult<'frame> {
     command_buffer: &'frame CommandBuffer,
-    swap_image_idx: u32,
+    target_image: TargetImage,
 }
 
 impl Frame {
@@ -194,104 +157,48 @@ impl Frame {
         })
     }
 
-    fn begin(
-        &'_ self,
-        device: Arc<Device>,
-        swapchain: Arc<Swapchain>,
-        depth_buffer: &DepthBuffer,
-        window_size: winit::dpi::PhysicalSize<u32>,
-    ) -> anyhow::Result<FrameBeginResult<'_>> {
-        let wnd_width = window_size.width as f32;
-        let wnd_height = window_size.height as f32;
-
+    // Start recording. The frame's render graph records everything between this and `end`.
+    fn begin(&'_ self, target: &RenderTarget) -> anyhow::Result<Option<FrameBeginResult<'_>>> {
         // Wait one sec for the fence to be available.
         self.render_fence.wait(1_000_000_000)?;
+
+        // Acquire before resetting the fence, so bailing out for an out of date swapchain
+        // doesn't leave it unsignalled forever.
+        let Some(target_image) = target.acquire(&self.swap_acquired)? else {
+            return Ok(None);
+        };
+
         self.render_fence.reset()?;
 
         self.command_buffer
             .begin(ash::vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
 
-        let swap_image = swapchain.acquire_image(&self.swap_acquired)?;
-
-        util::swap_acquire_transition(device.clone(), &self.command_buffer, swap_image.image);
-
-        let color_clear_value = ash::vk::ClearValue::default();
-        let mut depth_clear = ash::vk::ClearValue::default();
-        depth_clear.depth_stencil = ash::vk::ClearDepthStencilValue::default().depth(1.0);
-
-        let color_attachment_info = ash::vk::RenderingAttachmentInfo::default()
-            .image_view(swap_image.view)
-            .image_layout(ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-            .load_op(ash::vk::AttachmentLoadOp::CLEAR)
-            .clear_value(color_clear_value)
-            .store_op(ash::vk::AttachmentStoreOp::STORE);
-
-        let color_attachments = &[color_attachment_info];
-
-        let depth_attachment_info = ash::vk::RenderingAttachmentInfo::default()
-            .image_view(depth_buffer.image_view)
-            .image_layout(ash::vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
-            .load_op(ash::vk::AttachmentLoadOp::CLEAR)
-            .clear_value(depth_clear)
-            .store_op(ash::vk::AttachmentStoreOp::STORE);
-
-        let rendering_info = ash::vk::RenderingInfo::default()
-            .color_attachments(color_attachments)
-            .layer_count(1)
-            .depth_attachment(&depth_attachment_info)
-            .render_area(swapchain.swap_area());
-
-        let viewport = ash::vk::Viewport::default()
-            .max_depth(1.0f32)
-            .width(wnd_width)
-            // These are little weird so that we can flip the viewport and have Y up,
-            // like good old OpenGL.
-            .height(-wnd_height)
-            .y(wnd_height);
-
-        let scissor = swapchain.swap_area();
-        let viewports = &[viewport];
-
-        unsafe {
-            device
-                .handle()
-                .cmd_begin_rendering(self.command_buffer.handle(), &rendering_info);
-
-            device
-                .handle()
-                .cmd_set_viewport(self.command_buffer.handle(), 0, viewports);
-
-            let scissors = &[scissor];
-            device
-                .handle()
-                .cmd_set_scissor(self.command_buffer.handle(), 0, scissors);
-        }
-
-        Ok(FrameBeginResult {
+        Ok(Some(FrameBeginResult {
             command_buffer: &self.command_buffer,
-            swap_image_idx: swap_image.idx,
-        })
+            target_image,
+        }))
     }
 
+    // Returns true if the swapchain should be recreated before the next frame.
     fn end(
         &self,
         device: Arc<Device>,
-        swapchain: Arc<Swapchain>,
+        target: &RenderTarget,
         begin_result: FrameBeginResult,
-    ) -> anyhow::Result<()> {
-        let swap_image = swapchain
-            .get_image(begin_result.swap_image_idx)
-            .ok_or(anyhow!("swap image not found"))?;
-
-        unsafe {
-            device
-                .handle()
-                .cmd_end_rendering(self.command_buffer.handle());
-        }
+    ) -> anyhow::Result<bool> {
+        self.command_buffer.end()?;
 
-        util::swap_present_transition(device.clone(), &self.command_buffer, swap_image.image);
+        let RenderTarget::Swapchain(swapchain) = target else {
+            // Nothing to present, so there's nothing to synchronize with besides the fence.
+            self.submit(device, &[], &[])?;
+            return Ok(false);
+        };
 
-        self.command_buffer.end()?;
+        let swap_image = begin_result
+            .target_image
+            .swap_idx
+            .and_then(|idx| swapchain.get_image(idx))
+            .ok_or(anyhow!("swap image not found"))?;
 
         let wait_submits = &[self
             .swap_acquired
@@ -301,6 +208,21 @@ impl Frame {
             .render_complete
             .submit_info(ash::vk::PipelineStageFlags2::ALL_COMMANDS)];
 
+        self.submit(device.clone(), wait_submits, signal_submits)?;
+
+        let present_queue = device
+            .present_queue()
+            .ok_or(anyhow!("device has no present queue"))?;
+
+        swapchain.present(swap_image.idx, present_queue, &self.render_complete)
+    }
+
+    fn submit(
+        &self,
+        device: Arc<Device>,
+        wait_submits: &[ash::vk::SemaphoreSubmitInfo],
+        signal_submits: &[ash::vk::SemaphoreSubmitInfo],
+    ) -> anyhow::Result<()> {
         let buffer_submits = &[self.command_buffer.submit_info()];
 
         let submit_info = ash::vk::SubmitInfo2::default()
@@ -318,46 +240,245 @@ impl Frame {
             )?
         };
 
-        swapchain.present(
-            swap_image.idx,
-            device.present_queue(),
-            &self.render_complete,
-        )?;
-
         Ok(())
     }
 }
 
 const FRAMES_IN_FLIGHT: usize = 3;
 
+// Anything bigger than this gets a staging buffer of its own.
+const STAGING_SIZE: usize = 64 * 1024 * 1024;
+
 #[derive(Clone, Copy)]
+#[repr(C)]
 #[allow(dead_code)] // We don't read these in Rust but we do upload them to the GPU.
 struct GlobalSceneData {
     view: glam::Mat4,
     proj: glam::Mat4,
     // Pre-multiply before we send to the GPU, save us some effort.
     vp: glam::Mat4,
+    // How many of the light buffer's entries are filled in.
+    light_count: u32,
+    // Index of the light the shadow map is for, or -1 if there isn't one.
+    shadow_light: i32,
+    _padding: [u32; 2],
+    // View space to each cascade's clip space.
+    shadow_matrices: [glam::Mat4; CASCADE_COUNT],
+    // How far from the camera each cascade reaches.
+    cascade_splits: glam::Vec4,
+}
+
+// Pushed per draw: the model matrix, then the matrix for transforming normals.
+type ObjectData = [glam::Mat4; 2];
+
+// What the last frame drew.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    pub objects_drawn: u32,
+    // Entities with a mesh that were entirely outside the camera's view.
+    pub objects_culled: u32,
+    // Submeshes outside the view, from entities that weren't culled.
+    pub submeshes_culled: u32,
+}
+
+// Handles to everything `Renderer::load_model` loaded from a model file.
+pub struct LoadedModel {
+    pub mesh: MeshHandle,
+    // In the same order as the model's materials.
+    pub materials: Vec<MaterialHandle>,
+    // In model space. Add them to the scene with `Scene::add_model_lights`.
+    pub lights: Vec<common::Light>,
+}
+
+// As an `Asset`, so the model can be used in place with `Asset::access` instead of being
+// deserialized.
+fn open_model(assets: &Assets, name: &str) -> anyhow::Result<Asset> {
+    assets
+        .read(name)
+        .with_context(|| format!("failed to open model {name}"))
+}
+
+// Start the main pass. With a multisampled color buffer we render into that and resolve it into
+// the HDR buffer when rendering ends.
+fn begin_main_pass(
+    context: &PassContext,
+    hdr: &ResolvedImage,
+    msaa_color: Option<&ResolvedImage>,
+    depth: &ResolvedImage,
+    depth_mode: DepthMode,
+) {
+    let device = &context.device;
+    let command_buffer = context.command_buffer;
+
+    let area = ash::vk::Rect2D::default().extent(hdr.extent);
+    let wnd_width = area.extent.width as f32;
+    let wnd_height = area.extent.height as f32;
+
+    let color_clear_value = ash::vk::ClearValue::default();
+    let mut depth_clear = ash::vk::ClearValue::default();
+    depth_clear.depth_stencil =
+        ash::vk::ClearDepthStencilValue::default().depth(depth_mode.clear_depth());
+
+    let color_attachment_info = ash::vk::RenderingAttachmentInfo::default()
+        .image_layout(ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .load_op(ash::vk::AttachmentLoadOp::CLEAR)
+        .clear_value(color_clear_value);
+
+    let color_attachment_info = match msaa_color {
+        // Only the resolved image is needed afterwards.
+        Some(msaa_color) => color_attachment_info
+            .image_view(msaa_color.view)
+            .resolve_mode(ash::vk::ResolveModeFlags::AVERAGE)
+            .resolve_image_view(hdr.view)
+            .resolve_image_layout(ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .store_op(ash::vk::AttachmentStoreOp::DONT_CARE),
+        None => color_attachment_info
+            .image_view(hdr.view)
+            .store_op(ash::vk::AttachmentStoreOp::STORE),
+    };
+
+    let color_attachments = &[color_attachment_info];
+
+    // Nothing reads depth after the pass.
+    let depth_attachment_info = ash::vk::RenderingAttachmentInfo::default()
+        .image_view(depth.view)
+        .image_layout(ash::vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+        .load_op(ash::vk::AttachmentLoadOp::CLEAR)
+        .clear_value(depth_clear)
+        .store_op(ash::vk::AttachmentStoreOp::DONT_CARE);
+
+    let rendering_info = ash::vk::RenderingInfo::default()
+        .color_attachments(color_attachments)
+        .layer_count(1)
+        .depth_attachment(&depth_attachment_info)
+        .render_area(area);
+
+    let viewport = ash::vk::Viewport::default()
+        .max_depth(1.0f32)
+        .width(wnd_width)
+        // These are little weird so that we can flip the viewport and have Y up,
+        // like good old OpenGL.
+        .height(-wnd_height)
+        .y(wnd_height);
+
+    let scissor = area;
+    let viewports = &[viewport];
+
+    unsafe {
+        device
+            .handle()
+            .cmd_begin_rendering(command_buffer.handle(), &rendering_info);
+
+        device
+            .handle()
+            .cmd_set_viewport(command_buffer.handle(), 0, viewports);
+
+        let scissors = &[scissor];
+        device
+            .handle()
+            .cmd_set_scissor(command_buffer.handle(), 0, scissors);
+
+        device
+            .handle()
+            .cmd_set_depth_compare_op(command_buffer.handle(), depth_mode.compare_op());
+    }
+}
+
+const MAIN_SHADERS: [&str; 2] = ["shader/a.vert", "shader/a.frag"];
+
+// Loaded into the scene on startup.
+const DEFAULT_MODEL: &str = "models/jerma";
+
+fn build_graphics_pipeline(
+    device: Arc<Device>,
+    assets: &Assets,
+    depth_format: ash::vk::Format,
+    samples: ash::vk::SampleCountFlags,
+    descriptor_set_layouts: &[Arc<DescriptorSetLayout>],
+) -> anyhow::Result<Pipeline> {
+    let [vertex_shader, fragment_shader] = MAIN_SHADERS;
+    let vertex_shader_data = assets
+        .read(vertex_shader)
+        .and_then(|asset| util::spirv_from_bytes(&asset))
+        .with_context(|| format!("failed to read vertex shader {vertex_shader}"))?;
+    let fragment_shader_data = assets
+        .read(fragment_shader)
+        .and_then(|asset| util::spirv_from_bytes(&asset))
+        .with_context(|| format!("failed to read fragment shader {fragment_shader}"))?;
+
+    PipelineBuilder::new()
+        .with_color_format(HDR_FORMAT)
+        .with_depth_format(depth_format)
+        .with_samples(samples)
+        .with_vertex_shader_data(&vertex_shader_data)
+        .with_fragment_shader_data(&fragment_shader_data)
+        .with_vertex_layout_info(Vertex::layout())
+        .with_descriptor_set_layouts(descriptor_set_layouts)
+        .with_push_constants::<ObjectData>()
+        .build(device)
 }
 
 pub struct Renderer {
+    context: Arc<Context>,
     device: Arc<Device>,
-    swapchain: Arc<Swapchain>,
+    target: RenderTarget,
+    // How the last frame left an offscreen target, so the next one can wait for it. Swap images
+    // are ordered by acquisition instead.
+    target_last_access: ImageAccess,
 
     _command_pool: CommandPool,
+    assets: Assets,
     graphics_pipeline: Pipeline,
+    // Kept around so the pipeline can be rebuilt.
+    descriptor_set_layouts: Vec<Arc<DescriptorSetLayout>>,
 
     camera: Camera,
+    controller: CameraController,
+    // If set, hold the camera here instead of following the controller.
+    fixed_camera: Option<ArcballPose>,
 
-    start: Instant,
+    last_camera_update: Instant,
     window_size: winit::dpi::PhysicalSize<u32>,
-
-    mesh: Mesh,
+    // Set when the swapchain no longer matches the window and must be rebuilt.
+    swapchain_outdated: bool,
+
+    uploader: Uploader,
+    meshes: Vec<Mesh>,
+    // The asset each mesh was loaded from, in the same order as `meshes`.
+    mesh_names: Vec<String>,
+    // The handles `materials` gave each mesh's model, in the same order as `meshes`.
+    mesh_materials: Vec<Vec<MaterialHandle>>,
+    materials: Materials,
+    scene: Scene,
     uniform_buffer: UniformBuffer<GlobalSceneData>,
+    light_buffer: StorageBuffer<LightData>,
+    shadow_map: ShadowMap,
 
-    depth_buffer: DepthBuffer,
+    samples: ash::vk::SampleCountFlags,
+    depth_format: ash::vk::Format,
+    hdr_buffer: HdrBuffer,
+    // Depth and multisampled color, allocated by the render graph.
+    transients: TransientImages,
+
+    post_process: PostProcess,
+    tonemapper: Tonemapper,
+    // In stops, 0 leaves the image as it is.
+    exposure: f32,
 
     frames: Vec<Frame>,
     frame_idx: usize,
+    // How many frames have been submitted, ever.
+    frame_count: u64,
+
+    // Set by `enable_hot_reload`.
+    asset_watcher: Option<AssetWatcher>,
+    // Pipelines and meshes that were replaced by hot reloading.
+    deletion_queue: DeletionQueue,
+
+    stats: FrameStats,
+
+    // Where to save a screenshot of the next frame, if one was requested.
+    screenshot_request: Option<PathBuf>,
 
     _descriptor_pool: Arc<DescriptorPool>,
 }
@@ -365,34 +486,49 @@ pub struct Renderer {
 impl Renderer {
     pub fn new(
         context: Arc<Context>,
-        swapchain: Arc<Swapchain>,
-        window_size: winit::dpi::PhysicalSize<u32>,
+        target: RenderTarget,
+        assets: Assets,
     ) -> anyhow::Result<Self> {
         let device = context.device();
 
+        let window_size =
+            winit::dpi::PhysicalSize::new(target.extent().width, target.extent().height);
+
         let command_pool = CommandPool::new(
             device.clone(),
             device.graphics_queue(),
             ash::vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
         )?;
 
-        let vertex_shader_data = util::read_spirv(Path::new("./data/shader/a.spv.vert"))
-            .with_context(|| "failed to read vertex shader a.spv.vert")?;
-        let fragment_shader_data = util::read_spirv(Path::new("./data/shader/a.spv.frag"))
-            .with_context(|| "failed to read vertex shader a.spv.frag")?;
+        // Start without multisampling, see `set_msaa_samples`.
+        let samples = ash::vk::SampleCountFlags::TYPE_1;
+        let depth_format = select_depth_format(context.device().physical_device())
+            .ok_or(anyhow::anyhow!("no valid depth format found"))?;
+        let hdr_buffer = HdrBuffer::new(context.clone(), target.extent())?;
 
-        let depth_buffer = DepthBuffer::new(
-            context.clone(),
-            swapchain.extent().width,
-            swapchain.extent().height,
-        )?;
+        let transients = TransientImages::new(context.clone());
+
+        let post_process = PostProcess::new(context.clone(), &assets, target.color_format())?;
+        post_process.set_input(&hdr_buffer.image);
 
         let global_scene_binding = ash::vk::DescriptorSetLayoutBinding::default()
             .descriptor_count(1)
             .descriptor_type(ash::vk::DescriptorType::UNIFORM_BUFFER)
             .stage_flags(ash::vk::ShaderStageFlags::ALL_GRAPHICS);
 
-        let bindings = [global_scene_binding];
+        let light_binding = ash::vk::DescriptorSetLayoutBinding::default()
+            .binding(1)
+            .descriptor_count(1)
+            .descriptor_type(ash::vk::DescriptorType::STORAGE_BUFFER)
+            .stage_flags(ash::vk::ShaderStageFlags::FRAGMENT);
+
+        let shadow_map_binding = ash::vk::DescriptorSetLayoutBinding::default()
+            .binding(2)
+            .descriptor_count(1)
+            .descriptor_type(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(ash::vk::ShaderStageFlags::FRAGMENT);
+
+        let bindings = [global_scene_binding, light_binding, shadow_map_binding];
 
         let global_descriptor_layout = Arc::new(DescriptorSetLayout::new(
             device.clone(),
@@ -400,20 +536,28 @@ impl Renderer {
             ash::vk::DescriptorSetLayoutCreateFlags::empty(),
         )?);
 
-        let graphics_pipeline = PipelineBuilder::new()
-            .with_color_format(swapchain.surface_color_format())
-            .with_depth_format(depth_buffer.format)
-            .with_vertex_shader_data(&vertex_shader_data)
-            .with_fragment_shader_data(&fragment_shader_data)
-            .with_vertex_layout_info(Vertex::layout())
-            .with_descriptor_set_layouts(&[global_descriptor_layout.clone()])
-            .with_push_constants::<glam::Mat4>()
-            .build(device.clone())?;
-
-        let mut bytes: Vec<u8> = Vec::new();
-        File::open(Path::new("./data/models/jerma.mdl"))?.read_to_end(&mut bytes)?;
-        let archived_model = rkyv::from_bytes::<Model, rancor::Error>(&bytes)?;
-        let mesh = Mesh::new_from_model(context.clone(), &archived_model)?;
+        let material_descriptor_layout =
+            Arc::new(Materials::new_descriptor_set_layout(device.clone())?);
+
+        let descriptor_set_layouts = vec![
+            global_descriptor_layout.clone(),
+            material_descriptor_layout.clone(),
+        ];
+
+        let graphics_pipeline = build_graphics_pipeline(
+            device.clone(),
+            &assets,
+            depth_format,
+            samples,
+            &descriptor_set_layouts,
+        )?;
+
+        let mut uploader = Uploader::new(context.clone(), STAGING_SIZE)?;
+        let materials = Materials::new(
+            context.clone(),
+            &mut uploader,
+            material_descriptor_layout.clone(),
+        )?;
 
         let uniform_buffer = UniformBuffer::new(
             context.clone(),
@@ -422,9 +566,31 @@ impl Renderer {
             Some("global_scene_uniforms"),
         )?;
 
+        let shadow_map = ShadowMap::new(context.clone(), &assets)?;
+
+        let light_buffer = StorageBuffer::new(
+            context.clone(),
+            FRAMES_IN_FLIGHT,
+            MAX_LIGHTS,
+            ash::vk::SharingMode::EXCLUSIVE,
+            Some("lights"),
+        )?;
+
+        let pool_sizes = [
+            ash::vk::DescriptorPoolSize::default()
+                .ty(DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(FRAMES_IN_FLIGHT as u32),
+            ash::vk::DescriptorPoolSize::default()
+                .ty(DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(FRAMES_IN_FLIGHT as u32),
+            ash::vk::DescriptorPoolSize::default()
+                .ty(DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(FRAMES_IN_FLIGHT as u32),
+        ];
+
         let descriptor_pool = Arc::new(DescriptorPool::new(
             device.clone(),
-            DescriptorType::UNIFORM_BUFFER,
+            &pool_sizes,
             FRAMES_IN_FLIGHT as u32,
         )?);
 
@@ -443,116 +609,776 @@ impl Renderer {
 
         let camera = Camera::new(
             glam::vec2(window_size.width as f32, window_size.height as f32),
-            f32::to_radians(45.0),
+            Projection::Perspective {
+                fov: f32::to_radians(45.0),
+                near: 0.01,
+                far: 10000.0,
+            },
         );
 
-        Ok(Self {
+        let mut renderer = Self {
+            context,
             device,
-            swapchain,
+            target,
+            // Nothing has touched it yet.
+            target_last_access: ImageAccess::Acquired,
             frames,
             frame_idx: 0,
+            frame_count: 0,
+            asset_watcher: None,
+            deletion_queue: DeletionQueue::default(),
+            screenshot_request: None,
+            stats: FrameStats::default(),
             camera,
+            controller: CameraController::new_orbit(ArcballPose {
+                target: glam::vec3(0.5, 0.5, 0.5),
+                angles: glam::vec2(f32::to_radians(-15.0), 0.0),
+                dist: 100.0,
+            }),
+            fixed_camera: None,
             _command_pool: command_pool,
+            assets,
             graphics_pipeline,
-            mesh,
+            descriptor_set_layouts,
+            uploader,
+            meshes: Vec::new(),
+            mesh_names: Vec::new(),
+            mesh_materials: Vec::new(),
+            materials,
+            scene: Scene::new(),
             uniform_buffer,
+            light_buffer,
+            shadow_map,
             window_size,
-            start: Instant::now(),
-            depth_buffer,
+            swapchain_outdated: false,
+            last_camera_update: Instant::now(),
+            samples,
+            hdr_buffer,
+            depth_format,
+            transients,
+            post_process,
+            tonemapper: Tonemapper::default(),
+            exposure: 0.0,
             _descriptor_pool: descriptor_pool,
+        };
+
+        let jerma = renderer.load_model(DEFAULT_MODEL)?;
+        let jerma_entity = renderer
+            .scene
+            .add(Entity::new("jerma").with_mesh(jerma.mesh))?;
+        renderer
+            .scene
+            .add_model_lights(jerma_entity, &jerma.lights)?;
+
+        // Something has to light the scene if the model didn't bring its own lights.
+        if jerma.lights.is_empty() {
+            let sun = Light {
+                kind: LightKind::Directional,
+                color: glam::Vec3::ONE,
+                // Bright enough that a white diffuse surface facing the light comes out white.
+                intensity: std::f32::consts::PI,
+                range: None,
+            };
+
+            renderer.scene.add(
+                Entity::new("sun")
+                    .with_transform(Transform::looking_along(
+                        glam::Vec3::ZERO,
+                        glam::vec3(1.0, 1.0, 1.0),
+                    ))
+                    .with_light(sun),
+            )?;
+        }
+
+        Ok(renderer)
+    }
+
+    // Load a processed model, e.g. "models/jerma", and upload it. Blocks until the upload is done.
+    pub fn load_model(&mut self, name: &str) -> anyhow::Result<LoadedModel> {
+        let result = self.try_load_model(name);
+        if result.is_err() {
+            // Whatever's still pending was for the model we just gave up on.
+            self.uploader.discard();
+        }
+
+        result
+    }
+
+    fn try_load_model(&mut self, name: &str) -> anyhow::Result<LoadedModel> {
+        let asset = open_model(&self.assets, name)?;
+        let model = asset.access::<ArchivedModel>()?;
+        let materials = self.materials.add_model(&mut self.uploader, model)?;
+        let mesh = self.upload_mesh(model, &materials)?;
+        let lights = rkyv::deserialize::<Vec<common::Light>, rancor::Error>(&model.lights)?;
+
+        self.meshes.push(mesh);
+        self.mesh_names.push(name.to_string());
+        self.mesh_materials.push(materials.clone());
+
+        Ok(LoadedModel {
+            mesh: MeshHandle((self.meshes.len() - 1) as u32),
+            materials,
+            lights,
         })
     }
 
-    pub fn render(&mut self) -> anyhow::Result<()> {
-        let dt = Instant::now().duration_since(self.start).as_secs_f32();
+    // Waits for the mesh's upload, and any others pending, like its model's textures.
+    fn upload_mesh(
+        &mut self,
+        model: &ArchivedModel,
+        materials: &[MaterialHandle],
+    ) -> anyhow::Result<Mesh> {
+        let mesh =
+            Mesh::new_from_archived(self.context.clone(), &mut self.uploader, model, materials)?;
+
+        let upload = self.uploader.flush()?;
+        self.uploader.wait(upload)?;
 
-        let pitch = f32::to_radians(-15.0);
-        let yaw = f32::to_radians((dt * 20.0) % 360.0);
+        Ok(mesh)
+    }
+
+    // Watch the asset directory, and rebuild pipelines and meshes in place whenever their files
+    // change. Checked at the start of every `render`. Packed assets can't be reloaded.
+    pub fn enable_hot_reload(&mut self) -> anyhow::Result<()> {
+        let root = self
+            .assets
+            .root()
+            .ok_or(anyhow!("assets are packed, there are no files to watch"))?;
+        self.asset_watcher = Some(AssetWatcher::new(root)?);
+
+        Ok(())
+    }
+
+    // Anything that fails to reload keeps its old version, so a typo in a shader doesn't take
+    // everything down with it.
+    fn reload_changed_assets(&mut self) {
+        let Some(watcher) = &mut self.asset_watcher else {
+            return;
+        };
+
+        let changed = match watcher.poll() {
+            Ok(changed) => changed,
+            Err(err) => {
+                eprintln!("{err:?}");
+                return;
+            }
+        };
+
+        for path in changed {
+            // Shaders might have moved, so find them with the manifest as of this change.
+            let manifest = self.assets.manifest_path();
+            if manifest.is_some_and(|manifest| is_same_file(&manifest, &path)) {
+                match self.assets.reload() {
+                    Ok(assets) => self.assets = assets,
+                    Err(err) => eprintln!("failed to reload the asset manifest: {err:?}"),
+                }
+            }
+
+            let uses = |shaders: &[&str]| {
+                shaders.iter().any(|shader| {
+                    self.assets
+                        .path(shader)
+                        .is_ok_and(|shader| is_same_file(&shader, &path))
+                })
+            };
+            let (main, shadow, post) = (
+                uses(&MAIN_SHADERS),
+                uses(&[SHADOW_VERTEX_SHADER]),
+                uses(&POST_SHADERS),
+            );
+
+            if main {
+                let pipeline = build_graphics_pipeline(
+                    self.device.clone(),
+                    &self.assets,
+                    self.depth_format,
+                    self.samples,
+                    &self.descriptor_set_layouts,
+                );
+                let result = pipeline
+                    .map(|pipeline| std::mem::replace(&mut self.graphics_pipeline, pipeline));
+                self.retire("main pipeline", result);
+            }
 
+            if shadow {
+                let result = self
+                    .shadow_map
+                    .reload_pipeline(self.device.clone(), &self.assets);
+                self.retire("shadow pipeline", result);
+            }
+
+            if post {
+                let result = self.post_process.reload_pipeline(&self.assets);
+                self.retire("post process pipeline", result);
+            }
+
+            for idx in 0..self.meshes.len() {
+                let mesh = self.assets.path(&self.mesh_names[idx]);
+                if mesh.is_ok_and(|mesh| is_same_file(&mesh, &path)) {
+                    let result = self.reload_mesh(idx);
+                    self.retire(&format!("model {}", path.display()), result);
+                }
+            }
+        }
+    }
+
+    // Only the mesh and its materials change. Lights the model brought along are part of the
+    // scene now, and entities that override the material keep the one they picked. Materials are
+    // reloaded under the same handles, so entities using them see the new ones too. Returns the
+    // old mesh and materials.
+    fn reload_mesh(&mut self, idx: usize) -> anyhow::Result<(Mesh, RetiredMaterials)> {
+        let result = self.try_reload_mesh(idx);
+        if result.is_err() {
+            // Whatever's still pending was for the version we just gave up on.
+            self.uploader.discard();
+        }
+
+        result
+    }
+
+    fn try_reload_mesh(&mut self, idx: usize) -> anyhow::Result<(Mesh, RetiredMaterials)> {
+        let asset = open_model(&self.assets, &self.mesh_names[idx])?;
+        let model = asset.access::<ArchivedModel>()?;
+
+        let handles = self.mesh_materials[idx].clone();
+        let materials = self
+            .materials
+            .reload_model(&mut self.uploader, model, &handles)?;
+        // Waits for the textures as well, so nothing samples them before they've landed.
+        let mesh = self.upload_mesh(model, &handles)?;
+
+        // Nothing's been replaced until now, so a failure anywhere above keeps the old version.
+        let retired = self.materials.replace(materials);
+
+        Ok((std::mem::replace(&mut self.meshes[idx], mesh), retired))
+    }
+
+    // Hold on to whatever was replaced until the frames that used it are done.
+    fn retire<T: 'static>(&mut self, name: &str, result: anyhow::Result<T>) {
+        match result {
+            Ok(old) => {
+                self.deletion_queue.push(self.frame_count, old);
+                println!("reloaded {name}");
+            }
+            Err(err) => eprintln!("failed to reload {name}, keeping the old one: {err:?}"),
+        }
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    pub fn scene_mut(&mut self) -> &mut Scene {
+        &mut self.scene
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.camera.set_projection(projection);
+    }
+
+    pub fn set_fixed_camera(&mut self, pose: Option<ArcballPose>) {
+        self.fixed_camera = pose;
+    }
+
+    // Move the camera with this frame's input, scaled by the time since the last update.
+    pub fn update_camera(&mut self, input: &Input) {
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_camera_update).as_secs_f32();
+        self.last_camera_update = now;
+
+        self.controller.update(input, dt, &self.camera);
+    }
+
+    // Use up to `samples` samples per pixel, or as many as the device supports. 1 turns
+    // multisampling off. Returns how many samples we ended up with.
+    pub fn set_msaa_samples(&mut self, samples: u32) -> anyhow::Result<u32> {
+        let samples = select_sample_count(self.device.physical_device(), samples);
+        if samples == self.samples {
+            return Ok(samples.as_raw());
+        }
+
+        // Frames in flight may still be using the old pipeline and attachments.
+        self.context.wait_idle()?;
+
+        self.samples = samples;
+        // Transients with the old sample count would never be used again.
+        self.transients.clear();
+
+        self.graphics_pipeline = build_graphics_pipeline(
+            self.device.clone(),
+            &self.assets,
+            self.depth_format,
+            samples,
+            &self.descriptor_set_layouts,
+        )?;
+
+        Ok(samples.as_raw())
+    }
+
+    pub fn tonemapper(&self) -> Tonemapper {
+        self.tonemapper
+    }
+
+    pub fn set_tonemapper(&mut self, tonemapper: Tonemapper) {
+        self.tonemapper = tonemapper;
+    }
+
+    // In stops, so each step up doubles the brightness.
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.exposure = exposure;
+    }
+
+    pub fn resize(&mut self, window_size: winit::dpi::PhysicalSize<u32>) {
+        if window_size == self.window_size {
+            return;
+        }
+
+        self.window_size = window_size;
+        self.swapchain_outdated = true;
+    }
+
+    fn is_minimized(&self) -> bool {
+        self.window_size.width == 0 || self.window_size.height == 0
+    }
+
+    // Returns false if the surface has no area yet, in which case we stay out of date and try
+    // again next frame.
+    fn recreate_swapchain(&mut self) -> anyhow::Result<bool> {
+        let RenderTarget::Swapchain(old_swapchain) = &self.target else {
+            // Offscreen targets never go out of date.
+            self.swapchain_outdated = false;
+            return Ok(true);
+        };
+
+        // Frames in flight may still be using the old swap images and attachments.
+        self.context.wait_idle()?;
+
+        let Some(swapchain) = self
+            .context
+            .create_swapchain(self.window_size, Some(old_swapchain))?
+        else {
+            return Ok(false);
+        };
+        self.swapchain_outdated = false;
+        self.target = RenderTarget::Swapchain(Arc::new(swapchain));
+
+        self.recreate_attachments()?;
+
+        let extent = self.target.extent();
         self.camera
-            .set_arcball(glam::vec3(0.5, 0.5, 0.5), glam::vec2(pitch, yaw), 100.0);
+            .set_screen(glam::vec2(extent.width as f32, extent.height as f32));
+
+        Ok(true)
+    }
+
+    // Match the attachments to the target's size.
+    fn recreate_attachments(&mut self) -> anyhow::Result<()> {
+        let extent = self.target.extent();
+
+        // The render graph makes new transients at the new size as it needs them.
+        self.transients.clear();
+
+        self.hdr_buffer = HdrBuffer::new(self.context.clone(), extent)?;
+        self.post_process.set_input(&self.hdr_buffer.image);
+
+        Ok(())
+    }
+
+    pub fn request_screenshot(&mut self, path: PathBuf) {
+        self.screenshot_request = Some(path);
+    }
+
+    pub fn render(&mut self) -> anyhow::Result<()> {
+        self.reload_changed_assets();
+
+        let Some(path) = self.screenshot_request.take() else {
+            self.render_frame(false)?;
+            return Ok(());
+        };
+
+        let Some(screenshot) = self.render_frame(true)? else {
+            // The frame was skipped, try again next time.
+            self.screenshot_request = Some(path);
+            return Ok(());
+        };
+
+        // A failed screenshot isn't worth stopping rendering over.
+        match screenshot.save_png(&path) {
+            Ok(()) => println!("saved screenshot to {}", path.display()),
+            Err(err) => eprintln!("failed to save screenshot to {}: {err}", path.display()),
+        }
+
+        Ok(())
+    }
+
+    // Render a frame and read it back to the host.
+    pub fn capture(&mut self) -> anyhow::Result<Screenshot> {
+        self.render_frame(true)?
+            .ok_or(anyhow!("frame was skipped and could not be captured"))
+    }
 
-        // Identity model matrix for now.
-        let mvp = self.camera.vp();
+    // Returns None if no frame was rendered, or if we weren't asked to capture it.
+    fn render_frame(&mut self, capture: bool) -> anyhow::Result<Option<Screenshot>> {
+        // Nothing to render to while minimized, so just wait until we're visible again.
+        if self.is_minimized() {
+            return Ok(None);
+        }
+
+        if self.swapchain_outdated && !self.recreate_swapchain()? {
+            return Ok(None);
+        }
+        let readback = if capture {
+            if !self.target.supports_readback() {
+                return Err(anyhow!("render target does not support readback"));
+            }
+
+            Some(Readback::new(
+                self.context.clone(),
+                self.target.color_format(),
+                self.target.extent(),
+            )?)
+        } else {
+            None
+        };
+
+        match self.fixed_camera {
+            Some(pose) => self.camera.set_arcball_pose(pose),
+            None => self.controller.apply(&mut self.camera),
+        }
+
+        let world_transforms = self.scene.world_transforms();
+        let frustum = self.camera.frustum();
+        let mut stats = FrameStats::default();
 
         let frame = self
             .frames
             .get_mut(self.frame_idx)
             .ok_or(anyhow!("invalid frame idx {}", self.frame_idx))?;
 
-        let begin_result = frame.begin(
-            self.device.clone(),
-            self.swapchain.clone(),
-            &self.depth_buffer,
-            self.window_size,
-        )?;
+        let Some(begin_result) = frame.begin(&self.target)? else {
+            self.swapchain_outdated = true;
+            return Ok(None);
+        };
+        let command_buffer = begin_result.command_buffer;
+
+        // Beginning waited for this frame's last use of its slot, and every frame before that
+        // was waited for by an earlier begin.
+        self.deletion_queue
+            .flush((self.frame_count + 1).saturating_sub(FRAMES_IN_FLIGHT as u64));
 
         // The frame has begun, our slice of the uniform buffer is clear to write to.
         let uniform_idx = self.frame_idx % FRAMES_IN_FLIGHT;
 
+        let lights = gather_lights(&self.scene, &world_transforms);
+        self.light_buffer.write(&lights, uniform_idx)?;
+
+        // Only the first directional light casts shadows.
+        let shadow_light = lights.iter().position(|light| light.is_directional());
+        let cascades =
+            shadow_light.map(|idx| compute_cascades(&self.camera, lights[idx].direction()));
+
+        // The fragment shader works in view space, so go straight from there to the light's.
+        let inverse_view = self.camera.view().inverse();
+        let mut shadow_matrices = [glam::Mat4::IDENTITY; CASCADE_COUNT];
+        let mut cascade_splits = glam::Vec4::ZERO;
+        for (idx, cascade) in cascades.iter().flatten().enumerate() {
+            shadow_matrices[idx] = cascade.view_proj * inverse_view;
+            cascade_splits[idx] = cascade.far;
+        }
+
         self.uniform_buffer.write(
             GlobalSceneData {
                 view: self.camera.view(),
                 proj: self.camera.proj(),
                 vp: self.camera.vp(),
+                light_count: lights.len() as u32,
+                shadow_light: shadow_light.map_or(-1, |idx| idx as i32),
+                _padding: [0; 2],
+                shadow_matrices,
+                cascade_splits,
             },
             uniform_idx,
         )?;
 
-        unsafe {
-            let command_buffer = begin_result.command_buffer;
-            self.device.handle().cmd_bind_pipeline(
-                command_buffer.handle(),
-                ash::vk::PipelineBindPoint::GRAPHICS,
-                self.graphics_pipeline.handle(),
-            );
+        let buffer_info = [self.uniform_buffer.descriptor_info(uniform_idx)];
 
-            self.device.handle().cmd_bind_descriptor_sets(
-                command_buffer.handle(),
-                ash::vk::PipelineBindPoint::GRAPHICS,
-                self.graphics_pipeline.layout(),
-                0,
-                &[frame.scene_descriptor.handle()],
+        let uniform_buffer_write = ash::vk::WriteDescriptorSet::default()
+            .descriptor_count(1)
+            .descriptor_type(ash::vk::DescriptorType::UNIFORM_BUFFER)
+            .dst_set(frame.scene_descriptor.handle())
+            .dst_binding(0)
+            .buffer_info(&buffer_info);
+
+        let light_buffer_info = [self.light_buffer.descriptor_info(uniform_idx)];
+
+        let light_buffer_write = ash::vk::WriteDescriptorSet::default()
+            .descriptor_count(1)
+            .descriptor_type(ash::vk::DescriptorType::STORAGE_BUFFER)
+            .dst_set(frame.scene_descriptor.handle())
+            .dst_binding(1)
+            .buffer_info(&light_buffer_info);
+
+        let shadow_map_info = [self.shadow_map.descriptor_info()];
+
+        let shadow_map_write = ash::vk::WriteDescriptorSet::default()
+            .descriptor_count(1)
+            .descriptor_type(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .dst_set(frame.scene_descriptor.handle())
+            .dst_binding(2)
+            .image_info(&shadow_map_info);
+
+        // Update before binding, sets can't change once they're in use by a command buffer.
+        unsafe {
+            self.device.handle().update_descriptor_sets(
+                &[uniform_buffer_write, light_buffer_write, shadow_map_write],
                 &[],
-            );
+            )
+        };
 
-            let buffer_info = [self.uniform_buffer.descriptor_info(uniform_idx)];
+        // Everything casts shadows, even if it's outside the camera's view.
+        let casters = self
+            .scene
+            .entities()
+            .zip(&world_transforms)
+            .filter_map(|((_, entity), model)| {
+                let mesh = self.meshes.get(entity.mesh?.0 as usize)?;
+                Some((mesh, *model))
+            })
+            .collect::<Vec<_>>();
 
-            let uniform_buffer_write = ash::vk::WriteDescriptorSet::default()
-                .descriptor_count(1)
-                .descriptor_type(ash::vk::DescriptorType::UNIFORM_BUFFER)
-                .dst_set(frame.scene_descriptor.handle())
-                .dst_binding(0)
-                .buffer_info(&buffer_info);
+        let mut graph = RenderGraph::new();
 
-            self.device
-                .handle()
-                .update_descriptor_sets(&[uniform_buffer_write], &[]);
+        // Either way the target is overwritten, but an offscreen one is the same image every
+        // frame, so the last frame's writes and readback have to finish first.
+        let target_last_access = match self.target {
+            RenderTarget::Swapchain(_) => ImageAccess::Acquired,
+            RenderTarget::Offscreen(_) => self.target_last_access,
+        };
+        let target_image = &begin_result.target_image;
+        let target = graph.import_image(
+            ResolvedImage {
+                handle: target_image.image,
+                view: target_image.view,
+                extent: self.target.extent(),
+            },
+            ash::vk::ImageAspectFlags::COLOR,
+            target_last_access,
+        );
 
-            self.mesh.bind(self.device.clone(), &command_buffer);
+        // The last frame sampled both of these.
+        let shadow_map =
+            graph.import_owned_image(self.shadow_map.image(), ImageAccess::FragmentSampled);
+        let hdr = graph.import_owned_image(&self.hdr_buffer.image, ImageAccess::FragmentSampled);
+
+        let extent = self.target.extent();
+        let depth = graph.create_transient(TransientDesc {
+            name: "depth_buffer",
+            format: self.depth_format,
+            extent,
+            samples: self.samples,
+            usage: ash::vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            aspect: ash::vk::ImageAspectFlags::DEPTH,
+        });
+
+        // Never read outside of the pass, so the driver can keep it in tile memory where that's a thing.
+        let msaa_color = (self.samples != ash::vk::SampleCountFlags::TYPE_1).then(|| {
+            graph.create_transient(TransientDesc {
+                name: "msaa_color_buffer",
+                format: HDR_FORMAT,
+                extent,
+                samples: self.samples,
+                usage: ash::vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | ash::vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                aspect: ash::vk::ImageAspectFlags::COLOR,
+            })
+        });
+
+        graph
+            .add_pass("shadow")
+            .with_image(shadow_map, ImageAccess::DepthAttachment)
+            .record(|pass| {
+                self.shadow_map.record(
+                    pass.device.clone(),
+                    pass.command_buffer,
+                    cascades
+                        .as_ref()
+                        .map_or(&[], |cascades| cascades.as_slice()),
+                    &casters,
+                )
+            });
+
+        let mut main_pass = graph
+            .add_pass("main")
+            .with_image(shadow_map, ImageAccess::FragmentSampled)
+            .with_image(hdr, ImageAccess::ColorAttachment)
+            .with_image(depth, ImageAccess::DepthAttachment);
+        if let Some(msaa_color) = msaa_color {
+            main_pass = main_pass.with_image(msaa_color, ImageAccess::ColorAttachment);
+        }
 
-            self.device.handle().cmd_push_constants(
-                command_buffer.handle(),
-                self.graphics_pipeline.layout(),
-                ash::vk::ShaderStageFlags::ALL_GRAPHICS,
-                0,
-                bytes_of(&mvp),
+        let depth_mode = self.camera.depth_mode();
+        let scene_descriptor = frame.scene_descriptor.handle();
+        main_pass.record(|pass| {
+            begin_main_pass(
+                pass,
+                pass.image(hdr),
+                msaa_color.map(|id| pass.image(id)),
+                pass.image(depth),
+                depth_mode,
             );
 
-            self.device.handle().cmd_draw_indexed(
-                command_buffer.handle(),
-                self.mesh.num_indices() as u32,
-                1,
-                0,
-                0,
-                0,
-            );
+            let device = &pass.device;
+            let command_buffer = pass.command_buffer;
+
+            unsafe {
+                device.handle().cmd_bind_pipeline(
+                    command_buffer.handle(),
+                    ash::vk::PipelineBindPoint::GRAPHICS,
+                    self.graphics_pipeline.handle(),
+                );
+
+                device.handle().cmd_bind_descriptor_sets(
+                    command_buffer.handle(),
+                    ash::vk::PipelineBindPoint::GRAPHICS,
+                    self.graphics_pipeline.layout(),
+                    0,
+                    &[scene_descriptor],
+                    &[],
+                );
+
+                for ((_, entity), model) in self.scene.entities().zip(&world_transforms) {
+                    let model = *model;
+                    let Some(mesh_handle) = entity.mesh else {
+                        continue;
+                    };
+                    let mesh = self
+                        .meshes
+                        .get(mesh_handle.0 as usize)
+                        .ok_or(anyhow!("entity {} has invalid mesh", entity.name))?;
+
+                    if !frustum.intersects(mesh.bounds(), model) {
+                        stats.objects_culled += 1;
+                        continue;
+                    }
+                    stats.objects_drawn += 1;
+
+                    mesh.bind(device.clone(), command_buffer);
+
+                    let object_data: ObjectData = [model, model.inverse().transpose()];
+                    device.handle().cmd_push_constants(
+                        command_buffer.handle(),
+                        self.graphics_pipeline.layout(),
+                        ash::vk::ShaderStageFlags::ALL_GRAPHICS,
+                        0,
+                        bytes_of(&object_data),
+                    );
+
+                    for (submesh, submesh_material) in mesh.submeshes() {
+                        if !frustum.intersects(&submesh.bounds, model) {
+                            stats.submeshes_culled += 1;
+                            continue;
+                        }
+
+                        let material = entity.material.unwrap_or(submesh_material);
+                        let descriptor = self
+                            .materials
+                            .descriptor(material)
+                            .ok_or(anyhow!("entity {} has invalid material", entity.name))?;
+
+                        device.handle().cmd_bind_descriptor_sets(
+                            command_buffer.handle(),
+                            ash::vk::PipelineBindPoint::GRAPHICS,
+                            self.graphics_pipeline.layout(),
+                            1,
+                            &[descriptor.handle()],
+                            &[],
+                        );
+
+                        device.handle().cmd_draw_indexed(
+                            command_buffer.handle(),
+                            submesh.index_count,
+                            1,
+                            submesh.first_index,
+                            submesh.vertex_offset as i32,
+                            0,
+                        );
+                    }
+                }
+
+                device.handle().cmd_end_rendering(command_buffer.handle());
+            }
+
+            Ok(())
+        });
+
+        let area = self.target.area();
+        let tonemapper = self.tonemapper;
+        let exposure = self.exposure;
+        graph
+            .add_pass("post")
+            .with_image(hdr, ImageAccess::FragmentSampled)
+            .with_image(target, ImageAccess::ColorAttachment)
+            .record(|pass| {
+                self.post_process.record(
+                    pass.command_buffer,
+                    pass.image(target).view,
+                    area,
+                    tonemapper,
+                    exposure,
+                );
+                Ok(())
+            });
+
+        if let Some(readback) = &readback {
+            let buffer = graph.import_buffer(readback.buffer());
+
+            graph
+                .add_pass("readback")
+                .with_image(target, ImageAccess::TransferSrc)
+                .with_buffer(buffer, BufferAccess::TransferWrite)
+                .record(|pass| {
+                    readback.record_copy(
+                        pass.device.clone(),
+                        pass.command_buffer,
+                        pass.image(target).handle,
+                    );
+                    Ok(())
+                });
+
+            graph.export_buffer(buffer, BufferAccess::HostRead);
+        }
+
+        if let RenderTarget::Swapchain(_) = self.target {
+            graph.export_image(target, ImageAccess::Present);
         }
 
-        frame.end(self.device.clone(), self.swapchain.clone(), begin_result)?;
+        self.target_last_access = match readback {
+            Some(_) => ImageAccess::TransferSrc,
+            None => ImageAccess::ColorAttachment,
+        };
+
+        graph.execute(self.device.clone(), command_buffer, &mut self.transients)?;
+
+        self.stats = stats;
+
+        self.swapchain_outdated = frame.end(self.device.clone(), &self.target, begin_result)?;
+
+        let screenshot = match readback {
+            Some(readback) => {
+                // Block until the copy has landed. This stalls, but screenshots are rare.
+                frame.render_fence.wait(1_000_000_000)?;
+                Some(readback.read()?)
+            }
+            None => None,
+        };
 
         self.frame_idx = (self.frame_idx + 1).rem(FRAMES_IN_FLIGHT);
+        self.frame_count += 1;
 
-        Ok(())
+        Ok(screenshot)
     }
 }
@@ -93,3 +93,99 @@ impl<T: Copy> UniformBuffer<T> {
             .range(size_of::<T>() as u64)
     }
 }
+
+// Like `UniformBuffer`, but each slot holds up to `capacity` elements of T rather than just one.
+pub struct StorageBuffer<T> {
+    buffer: Buffer,
+
+    count: usize,
+    capacity: usize,
+    stride: usize,
+    min_align: usize,
+
+    phantom: PhantomData<T>,
+}
+
+impl<T: Copy> StorageBuffer<T> {
+    pub fn new(
+        context: Arc<Context>,
+        count: usize,
+        capacity: usize,
+        sharing_mode: ash::vk::SharingMode,
+        name: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let min_align = context
+            .device()
+            .physical_device()
+            .limits()
+            .min_storage_buffer_offset_alignment;
+
+        let min_align = min_align
+            .try_into()
+            .expect("storage buffer minimum alignment should convert into u64");
+
+        let stride = get_stride::<T>(size_of::<T>() * capacity, min_align);
+
+        let size = stride * count;
+
+        let mut buffer = Buffer::new(
+            context.clone(),
+            size,
+            ash::vk::BufferUsageFlags::STORAGE_BUFFER,
+            sharing_mode,
+        )?;
+
+        buffer.allocate(AllocationCreateDesc {
+            name: name.unwrap_or("storage buffer (unnamed)"),
+            requirements: buffer.memory_requirements(),
+            // Written every frame, same as uniform buffers.
+            location: gpu_allocator::MemoryLocation::CpuToGpu,
+            linear: true,
+            allocation_scheme: gpu_allocator::vulkan::AllocationScheme::GpuAllocatorManaged,
+        })?;
+
+        Ok(Self {
+            buffer,
+            count,
+            capacity,
+            stride,
+            min_align,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn write(&mut self, data: &[T], idx: usize) -> anyhow::Result<()> {
+        assert!(idx < self.count);
+
+        if data.len() > self.capacity {
+            return Err(anyhow::anyhow!(
+                "{} elements don't fit in a storage buffer slot of {}",
+                data.len(),
+                self.capacity
+            ));
+        }
+
+        let allocation = self
+            .buffer
+            .allocation_mut()
+            .expect("buffer must be allocated before write");
+
+        let mut slab = allocation
+            .try_as_mapped_slab()
+            .expect("allocation must be valid slab");
+
+        let offset = self.stride * idx;
+
+        presser::copy_from_slice_to_offset_with_align(data, &mut slab, offset, self.min_align)?;
+
+        Ok(())
+    }
+
+    // Covers the whole slot, shaders have to be told how much of it is filled some other way.
+    pub fn descriptor_info(&self, idx: usize) -> ash::vk::DescriptorBufferInfo {
+        ash::vk::DescriptorBufferInfo::default()
+            .buffer(self.buffer.handle())
+            .offset((idx * self.stride) as u64)
+            .range((size_of::<T>() * self.capacity) as u64)
+    }
+}
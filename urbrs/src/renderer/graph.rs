@@ -0,0 +1,603 @@
+use std::sync::Arc;
+
+use anyhow::Context as anyhow_context;
+
+use crate::vulkan::{
+    command::CommandBuffer,
+    context::Context,
+    device::Device,
+    image::Image,
+    util::{self, ImageBarrierState},
+};
+
+// Any of these in an access mask means whoever comes next has to wait for it.
+const WRITE_ACCESS: ash::vk::AccessFlags2 = ash::vk::AccessFlags2::from_raw(
+    ash::vk::AccessFlags2::COLOR_ATTACHMENT_WRITE.as_raw()
+        | ash::vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+        | ash::vk::AccessFlags2::TRANSFER_WRITE.as_raw()
+        | ash::vk::AccessFlags2::SHADER_WRITE.as_raw()
+        | ash::vk::AccessFlags2::HOST_WRITE.as_raw()
+        | ash::vk::AccessFlags2::MEMORY_WRITE.as_raw(),
+);
+
+// How a pass uses an image. Each one implies the layout the image has to be in, and which
+// stages and accesses to synchronize with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageAccess {
+    // Just acquired from the swapchain. Acquisition is waited on at color attachment output,
+    // so whatever comes next has to wait there too.
+    Acquired,
+    ColorAttachment,
+    DepthAttachment,
+    // Read from fragment shaders.
+    FragmentSampled,
+    TransferSrc,
+    Present,
+}
+
+impl ImageAccess {
+    fn state(self) -> ImageBarrierState {
+        let (layout, stage, access) = match self {
+            ImageAccess::Acquired => (
+                ash::vk::ImageLayout::UNDEFINED,
+                ash::vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                ash::vk::AccessFlags2::empty(),
+            ),
+            ImageAccess::ColorAttachment => (
+                ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                ash::vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                ash::vk::AccessFlags2::COLOR_ATTACHMENT_READ
+                    | ash::vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            ),
+            ImageAccess::DepthAttachment => (
+                ash::vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+                ash::vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | ash::vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                ash::vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                    | ash::vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            ),
+            ImageAccess::FragmentSampled => (
+                ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                ash::vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                ash::vk::AccessFlags2::SHADER_SAMPLED_READ,
+            ),
+            ImageAccess::TransferSrc => (
+                ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                ash::vk::PipelineStageFlags2::COPY,
+                ash::vk::AccessFlags2::TRANSFER_READ,
+            ),
+            ImageAccess::Present => (
+                ash::vk::ImageLayout::PRESENT_SRC_KHR,
+                ash::vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+                ash::vk::AccessFlags2::empty(),
+            ),
+        };
+
+        ImageBarrierState {
+            layout,
+            stage,
+            access,
+        }
+    }
+
+    fn is_write(self) -> bool {
+        self.state().access.intersects(WRITE_ACCESS)
+    }
+}
+
+// How a pass uses a buffer. Unlike images there are no layouts, only stages and accesses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferAccess {
+    TransferWrite,
+    // Read on the host once the frame's fence is signalled.
+    HostRead,
+}
+
+impl BufferAccess {
+    fn state(self) -> BufferState {
+        let (stage, access) = match self {
+            BufferAccess::TransferWrite => (
+                ash::vk::PipelineStageFlags2::COPY,
+                ash::vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+            BufferAccess::HostRead => (
+                ash::vk::PipelineStageFlags2::HOST,
+                ash::vk::AccessFlags2::HOST_READ,
+            ),
+        };
+
+        BufferState { stage, access }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct BufferState {
+    stage: ash::vk::PipelineStageFlags2,
+    access: ash::vk::AccessFlags2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageId(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferId(usize);
+
+// An attachment the graph allocates for us. Its contents only live as long as the frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransientDesc {
+    pub name: &'static str,
+    pub format: ash::vk::Format,
+    pub extent: ash::vk::Extent2D,
+    pub samples: ash::vk::SampleCountFlags,
+    pub usage: ash::vk::ImageUsageFlags,
+    pub aspect: ash::vk::ImageAspectFlags,
+}
+
+struct TransientImage {
+    desc: TransientDesc,
+    image: Image,
+    // Where the last frame to use it left it.
+    last_state: ImageBarrierState,
+    in_use: bool,
+}
+
+// Attachments allocated by render graphs. They're kept between frames, so they don't have to
+// be reallocated every time.
+pub struct TransientImages {
+    context: Arc<Context>,
+    images: Vec<TransientImage>,
+}
+
+impl TransientImages {
+    pub fn new(context: Arc<Context>) -> Self {
+        Self {
+            context,
+            images: Vec::new(),
+        }
+    }
+
+    // Free everything. Frames in flight may still be using them, so only call this while the
+    // device is idle.
+    pub fn clear(&mut self) {
+        self.images.clear();
+    }
+
+    // Find a free image matching `desc`, or make one.
+    fn acquire(&mut self, desc: TransientDesc) -> anyhow::Result<usize> {
+        if let Some(idx) = self
+            .images
+            .iter()
+            .position(|image| !image.in_use && image.desc == desc)
+        {
+            self.images[idx].in_use = true;
+            return Ok(idx);
+        }
+
+        let image = Image::new_multisampled(
+            self.context.clone(),
+            desc.name,
+            desc.format,
+            desc.extent,
+            desc.samples,
+            desc.usage,
+            desc.aspect,
+        )?;
+
+        self.images.push(TransientImage {
+            desc,
+            image,
+            last_state: ImageBarrierState {
+                layout: ash::vk::ImageLayout::UNDEFINED,
+                stage: ash::vk::PipelineStageFlags2::NONE,
+                access: ash::vk::AccessFlags2::empty(),
+            },
+            in_use: true,
+        });
+
+        Ok(self.images.len() - 1)
+    }
+}
+
+enum ImageSource {
+    Imported(ResolvedImage),
+    Transient(TransientDesc),
+}
+
+struct GraphImage {
+    source: ImageSource,
+    aspect: ash::vk::ImageAspectFlags,
+    state: ImageBarrierState,
+    // Until something writes to it, the image's contents aren't ours to keep.
+    written: bool,
+    // Where to leave it once every pass has run, if anywhere in particular.
+    final_access: Option<ImageAccess>,
+}
+
+struct GraphBuffer {
+    handle: ash::vk::Buffer,
+    state: BufferState,
+    final_access: Option<BufferAccess>,
+}
+
+// What a pass gets to record with.
+#[derive(Clone, Copy)]
+pub struct ResolvedImage {
+    pub handle: ash::vk::Image,
+    pub view: ash::vk::ImageView,
+    pub extent: ash::vk::Extent2D,
+}
+
+pub struct PassContext<'c> {
+    pub device: Arc<Device>,
+    pub command_buffer: &'c CommandBuffer,
+    images: &'c [ResolvedImage],
+}
+
+impl PassContext<'_> {
+    pub fn image(&self, id: ImageId) -> &ResolvedImage {
+        &self.images[id.0]
+    }
+}
+
+type RecordFn<'a> = Box<dyn FnOnce(&PassContext) -> anyhow::Result<()> + 'a>;
+
+struct Pass<'a> {
+    name: &'static str,
+    images: Vec<(ImageId, ImageAccess)>,
+    buffers: Vec<(BufferId, BufferAccess)>,
+    record: RecordFn<'a>,
+}
+
+// Declares what a pass reads and writes, see `RenderGraph::add_pass`.
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    name: &'static str,
+    images: Vec<(ImageId, ImageAccess)>,
+    buffers: Vec<(BufferId, BufferAccess)>,
+}
+
+impl<'a> PassBuilder<'_, 'a> {
+    pub fn with_image(mut self, id: ImageId, access: ImageAccess) -> Self {
+        self.images.push((id, access));
+        self
+    }
+
+    pub fn with_buffer(mut self, id: BufferId, access: BufferAccess) -> Self {
+        self.buffers.push((id, access));
+        self
+    }
+
+    // Finish declaring the pass. `record` runs once everything it declared is ready.
+    pub fn record(self, record: impl FnOnce(&PassContext) -> anyhow::Result<()> + 'a) {
+        self.graph.passes.push(Pass {
+            name: self.name,
+            images: self.images,
+            buffers: self.buffers,
+            record: Box::new(record),
+        });
+    }
+}
+
+// One frame's worth of passes, recorded in the order they were added. Passes say which images
+// and buffers they use and how, and the graph puts barriers between them to match.
+//
+// The first write to an image in a frame throws away whatever it held before, so passes writing
+// to something fresh should clear it or overwrite all of it.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    images: Vec<GraphImage>,
+    buffers: Vec<GraphBuffer>,
+    passes: Vec<Pass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Use an image that lives outside the graph. `last_access` is how it was last used, e.g. by
+    // the previous frame, so the first pass to touch it can wait for that.
+    pub fn import_image(
+        &mut self,
+        image: ResolvedImage,
+        aspect: ash::vk::ImageAspectFlags,
+        last_access: ImageAccess,
+    ) -> ImageId {
+        self.images.push(GraphImage {
+            source: ImageSource::Imported(image),
+            aspect,
+            state: last_access.state(),
+            written: false,
+            final_access: None,
+        });
+
+        ImageId(self.images.len() - 1)
+    }
+
+    pub fn import_owned_image(&mut self, image: &Image, last_access: ImageAccess) -> ImageId {
+        let resolved = ResolvedImage {
+            handle: image.handle(),
+            view: image.view(),
+            extent: image.extent(),
+        };
+
+        self.import_image(resolved, image.aspect(), last_access)
+    }
+
+    // An attachment only needed for this frame, allocated from `TransientImages` when the graph runs.
+    pub fn create_transient(&mut self, desc: TransientDesc) -> ImageId {
+        self.images.push(GraphImage {
+            source: ImageSource::Transient(desc),
+            aspect: desc.aspect,
+            // Replaced with wherever the last frame left it once we know which image this is.
+            state: ImageAccess::Acquired.state(),
+            written: false,
+            final_access: None,
+        });
+
+        ImageId(self.images.len() - 1)
+    }
+
+    // Use a buffer that lives outside the graph. Host writes before submission are visible
+    // without a barrier, so there's nothing to wait for before the first pass that touches it.
+    pub fn import_buffer(&mut self, handle: ash::vk::Buffer) -> BufferId {
+        self.buffers.push(GraphBuffer {
+            handle,
+            state: BufferState {
+                stage: ash::vk::PipelineStageFlags2::NONE,
+                access: ash::vk::AccessFlags2::empty(),
+            },
+            final_access: None,
+        });
+
+        BufferId(self.buffers.len() - 1)
+    }
+
+    // Leave an image ready for `access` once every pass has run, e.g. for presenting.
+    pub fn export_image(&mut self, id: ImageId, access: ImageAccess) {
+        self.images[id.0].final_access = Some(access);
+    }
+
+    pub fn export_buffer(&mut self, id: BufferId, access: BufferAccess) {
+        self.buffers[id.0].final_access = Some(access);
+    }
+
+    pub fn add_pass<'g>(&'g mut self, name: &'static str) -> PassBuilder<'g, 'a> {
+        PassBuilder {
+            graph: self,
+            name,
+            images: Vec::new(),
+            buffers: Vec::new(),
+        }
+    }
+
+    // Record every pass into `command_buffer`, with barriers in between.
+    pub fn execute(
+        self,
+        device: Arc<Device>,
+        command_buffer: &CommandBuffer,
+        transients: &mut TransientImages,
+    ) -> anyhow::Result<()> {
+        let RenderGraph {
+            mut images,
+            mut buffers,
+            passes,
+        } = self;
+
+        let mut transient_slots: Vec<Option<usize>> = Vec::with_capacity(images.len());
+        let mut resolved: Vec<ResolvedImage> = Vec::with_capacity(images.len());
+        let result = resolve_images(&mut images, transients, &mut transient_slots, &mut resolved)
+            .and_then(|()| {
+                record_passes(
+                    &device,
+                    command_buffer,
+                    &mut images,
+                    &mut buffers,
+                    passes,
+                    &resolved,
+                )
+            });
+
+        // Transients have to be released whatever happened, or they'd never be used again.
+        for (image, slot) in images.iter().zip(transient_slots) {
+            if let Some(slot) = slot {
+                let transient = &mut transients.images[slot];
+                transient.in_use = false;
+
+                // Let the next frame know where these were left. A failed frame is never
+                // submitted, so they're still wherever the last one left them.
+                if result.is_ok() {
+                    transient.last_state = image.state;
+                }
+            }
+        }
+
+        result
+    }
+}
+
+// Find the image behind every `ImageId`. Transient images pick up wherever the last frame left
+// them, contents discarded. `transient_slots` gets an entry for each image resolved, even if a
+// later one fails.
+fn resolve_images(
+    images: &mut [GraphImage],
+    transients: &mut TransientImages,
+    transient_slots: &mut Vec<Option<usize>>,
+    resolved: &mut Vec<ResolvedImage>,
+) -> anyhow::Result<()> {
+    for image in images {
+        match image.source {
+            ImageSource::Imported(imported) => {
+                transient_slots.push(None);
+                resolved.push(imported);
+            }
+            ImageSource::Transient(desc) => {
+                let slot = transients.acquire(desc)?;
+                let transient = &transients.images[slot];
+
+                image.state = ImageBarrierState {
+                    layout: ash::vk::ImageLayout::UNDEFINED,
+                    ..transient.last_state
+                };
+
+                transient_slots.push(Some(slot));
+                resolved.push(ResolvedImage {
+                    handle: transient.image.handle(),
+                    view: transient.image.view(),
+                    extent: transient.image.extent(),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn record_passes(
+    device: &Arc<Device>,
+    command_buffer: &CommandBuffer,
+    images: &mut [GraphImage],
+    buffers: &mut [GraphBuffer],
+    passes: Vec<Pass>,
+    resolved: &[ResolvedImage],
+) -> anyhow::Result<()> {
+    for pass in passes {
+        let image_barriers: Vec<ash::vk::ImageMemoryBarrier2> = pass
+            .images
+            .iter()
+            .filter_map(|(id, access)| {
+                image_barrier(&mut images[id.0], resolved[id.0].handle, *access)
+            })
+            .collect();
+
+        let buffer_barriers: Vec<ash::vk::BufferMemoryBarrier2> = pass
+            .buffers
+            .iter()
+            .filter_map(|(id, access)| buffer_barrier(&mut buffers[id.0], *access))
+            .collect();
+
+        record_barriers(device, command_buffer, &image_barriers, &buffer_barriers);
+
+        let context = PassContext {
+            device: device.clone(),
+            command_buffer,
+            images: resolved,
+        };
+
+        (pass.record)(&context).with_context(|| format!("{} pass failed", pass.name))?;
+    }
+
+    let image_barriers: Vec<ash::vk::ImageMemoryBarrier2> = images
+        .iter_mut()
+        .zip(resolved)
+        .filter_map(|(image, resolved)| {
+            let access = image.final_access?;
+            image_barrier(image, resolved.handle, access)
+        })
+        .collect();
+
+    let buffer_barriers: Vec<ash::vk::BufferMemoryBarrier2> = buffers
+        .iter_mut()
+        .filter_map(|buffer| {
+            let access = buffer.final_access?;
+            buffer_barrier(buffer, access)
+        })
+        .collect();
+
+    record_barriers(device, command_buffer, &image_barriers, &buffer_barriers);
+
+    Ok(())
+}
+
+// Move `image` over to `access`, if it needs a barrier to get there.
+fn image_barrier(
+    image: &mut GraphImage,
+    handle: ash::vk::Image,
+    access: ImageAccess,
+) -> Option<ash::vk::ImageMemoryBarrier2<'static>> {
+    let next = access.state();
+
+    let discard = access.is_write() && !image.written;
+    let old_layout = if discard {
+        ash::vk::ImageLayout::UNDEFINED
+    } else {
+        image.state.layout
+    };
+
+    image.written |= access.is_write();
+
+    let needs_barrier = old_layout != next.layout
+        || access.is_write()
+        || image.state.access.intersects(WRITE_ACCESS);
+
+    if !needs_barrier {
+        // Reading after reading. Anything writing later has to wait for both reads, though.
+        image.state.stage |= next.stage;
+        image.state.access |= next.access;
+        return None;
+    }
+
+    let barrier = ash::vk::ImageMemoryBarrier2::default()
+        .src_stage_mask(image.state.stage)
+        .src_access_mask(image.state.access)
+        .old_layout(old_layout)
+        .dst_stage_mask(next.stage)
+        .dst_access_mask(next.access)
+        .new_layout(next.layout)
+        .subresource_range(util::get_subresource_range(image.aspect))
+        .image(handle);
+
+    image.state = next;
+
+    Some(barrier)
+}
+
+fn buffer_barrier(
+    buffer: &mut GraphBuffer,
+    access: BufferAccess,
+) -> Option<ash::vk::BufferMemoryBarrier2<'static>> {
+    let next = access.state();
+
+    // Only writes need waiting for, and there's nothing to transition.
+    if !buffer.state.access.intersects(WRITE_ACCESS) && !next.access.intersects(WRITE_ACCESS) {
+        buffer.state.stage |= next.stage;
+        buffer.state.access |= next.access;
+        return None;
+    }
+
+    let barrier = ash::vk::BufferMemoryBarrier2::default()
+        .src_stage_mask(buffer.state.stage)
+        .src_access_mask(buffer.state.access)
+        .dst_stage_mask(next.stage)
+        .dst_access_mask(next.access)
+        .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+        .buffer(buffer.handle)
+        .offset(0)
+        .size(ash::vk::WHOLE_SIZE);
+
+    buffer.state = next;
+
+    Some(barrier)
+}
+
+fn record_barriers(
+    device: &Device,
+    command_buffer: &CommandBuffer,
+    image_barriers: &[ash::vk::ImageMemoryBarrier2],
+    buffer_barriers: &[ash::vk::BufferMemoryBarrier2],
+) {
+    if image_barriers.is_empty() && buffer_barriers.is_empty() {
+        return;
+    }
+
+    let dep_info = ash::vk::DependencyInfo::default()
+        .image_memory_barriers(image_barriers)
+        .buffer_memory_barriers(buffer_barriers);
+
+    unsafe {
+        device
+            .handle()
+            .cmd_pipeline_barrier2(command_buffer.handle(), &dep_info)
+    };
+}
@@ -0,0 +1,86 @@
+use common::LightKind;
+
+use crate::scene::{Light, Scene};
+
+// Any more than this in a scene are ignored.
+pub const MAX_LIGHTS: usize = 256;
+
+const DIRECTIONAL: u32 = 0;
+const POINT: u32 = 1;
+const SPOT: u32 = 2;
+
+// Matches the std430 layout of `Light` in a.frag.
+#[derive(Clone, Copy)]
+#[repr(C)]
+#[allow(dead_code)] // We don't read most of these in Rust but we do upload them to the GPU.
+pub struct LightData {
+    // In world space.
+    position: glam::Vec3,
+    // 0 if infinite.
+    range: f32,
+    // The way the light shines, in world space.
+    direction: glam::Vec3,
+    kind: u32,
+    // Color premultiplied by intensity.
+    radiance: glam::Vec3,
+    // Spot cone falloff is `clamp(cos(angle) * scale + offset, 0, 1)`, precomputed from the angles.
+    spot_scale: f32,
+    spot_offset: f32,
+    _padding: [f32; 3],
+}
+
+impl LightData {
+    fn new(light: &Light, transform: glam::Mat4) -> Self {
+        let (kind, spot_scale, spot_offset) = match light.kind {
+            LightKind::Directional => (DIRECTIONAL, 0.0, 0.0),
+            LightKind::Point => (POINT, 0.0, 0.0),
+            LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                let cos_inner = inner_cone_angle.cos();
+                let cos_outer = outer_cone_angle.cos();
+                // Keep a hard edged cone from dividing by zero.
+                let scale = 1.0 / (cos_inner - cos_outer).max(0.001);
+
+                (SPOT, scale, -cos_outer * scale)
+            }
+        };
+
+        Self {
+            position: transform.transform_point3(glam::Vec3::ZERO),
+            range: light.range.unwrap_or(0.0),
+            direction: transform
+                .transform_vector3(glam::Vec3::NEG_Z)
+                .normalize_or_zero(),
+            kind,
+            radiance: light.color * light.intensity,
+            spot_scale,
+            spot_offset,
+            _padding: [0.0; 3],
+        }
+    }
+
+    pub fn is_directional(&self) -> bool {
+        self.kind == DIRECTIONAL
+    }
+
+    pub fn direction(&self) -> glam::Vec3 {
+        self.direction
+    }
+}
+
+// Every light in the scene, placed by the world transforms from `Scene::world_transforms`.
+pub fn gather_lights(scene: &Scene, world_transforms: &[glam::Mat4]) -> Vec<LightData> {
+    scene
+        .entities()
+        .zip(world_transforms)
+        .filter_map(|((_, entity), transform)| {
+            entity
+                .light
+                .as_ref()
+                .map(|light| LightData::new(light, *transform))
+        })
+        .take(MAX_LIGHTS)
+        .collect()
+}
@@ -0,0 +1,315 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use common::{ArchivedModel, Material, TextureFormat};
+use rkyv::rancor;
+
+use crate::{
+    renderer::{buffer::UniformBuffer, texture::Texture},
+    scene::MaterialHandle,
+    vulkan::{
+        context::Context,
+        descriptor::{DescriptorPool, DescriptorSet, DescriptorSetLayout},
+        device::Device,
+        sampler::Sampler,
+        upload::Uploader,
+    },
+};
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+#[allow(dead_code)] // We don't read these in Rust but we do upload them to the GPU.
+struct MaterialData {
+    base_color_factor: glam::Vec4,
+    // w is unused, it's just here to keep the std140 layout simple.
+    emissive_factor: glam::Vec4,
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+}
+
+impl From<&Material> for MaterialData {
+    fn from(material: &Material) -> Self {
+        Self {
+            base_color_factor: material.base_color_factor.into(),
+            emissive_factor: glam::Vec3::from(material.emissive_factor).extend(0.0),
+            metallic_factor: material.metallic_factor,
+            roughness_factor: material.roughness_factor,
+            normal_scale: material.normal_scale,
+            occlusion_strength: material.occlusion_strength,
+        }
+    }
+}
+
+const MATERIAL_DATA_BINDING: u32 = 0;
+// Base color, metallic-roughness, normal, occlusion and emissive, in that order.
+const TEXTURE_BINDINGS: [u32; 5] = [1, 2, 3, 4, 5];
+
+fn texture_or<'a>(textures: &'a [Texture], idx: Option<u32>, default: &'a Texture) -> &'a Texture {
+    idx.and_then(|idx| textures.get(idx as usize))
+        .unwrap_or(default)
+}
+
+// Everything a model's materials read from. Shared by all of them, and dropped with the last one.
+struct ModelResources {
+    _textures: Vec<Texture>,
+    _material_data: UniformBuffer<MaterialData>,
+}
+
+struct MaterialSlot {
+    descriptor: DescriptorSet,
+    _resources: Arc<ModelResources>,
+}
+
+// A model's materials, loaded again but not in their slots yet.
+pub struct ReloadedMaterials {
+    handles: Vec<MaterialHandle>,
+    slots: Vec<MaterialSlot>,
+}
+
+// Materials that were replaced, to be dropped once no frame in flight is using them.
+pub struct RetiredMaterials {
+    _slots: Vec<MaterialSlot>,
+}
+
+// Every material loaded into the renderer, with one descriptor set each.
+pub struct Materials {
+    context: Arc<Context>,
+    layout: Arc<DescriptorSetLayout>,
+
+    // Bound in place of missing textures. White leaves the factor alone.
+    white: Texture,
+    flat_normal: Texture,
+    sampler: Sampler,
+
+    // Indexed by `MaterialHandle`.
+    slots: Vec<MaterialSlot>,
+}
+
+impl Materials {
+    pub fn new_descriptor_set_layout(device: Arc<Device>) -> anyhow::Result<DescriptorSetLayout> {
+        let data_binding = ash::vk::DescriptorSetLayoutBinding::default()
+            .binding(MATERIAL_DATA_BINDING)
+            .descriptor_count(1)
+            .descriptor_type(ash::vk::DescriptorType::UNIFORM_BUFFER)
+            .stage_flags(ash::vk::ShaderStageFlags::FRAGMENT);
+
+        let bindings: Vec<ash::vk::DescriptorSetLayoutBinding> = std::iter::once(data_binding)
+            .chain(TEXTURE_BINDINGS.iter().map(|binding| {
+                ash::vk::DescriptorSetLayoutBinding::default()
+                    .binding(*binding)
+                    .descriptor_count(1)
+                    .descriptor_type(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .stage_flags(ash::vk::ShaderStageFlags::FRAGMENT)
+            }))
+            .collect();
+
+        DescriptorSetLayout::new(
+            device,
+            &bindings,
+            ash::vk::DescriptorSetLayoutCreateFlags::empty(),
+        )
+    }
+
+    pub fn new(
+        context: Arc<Context>,
+        uploader: &mut Uploader,
+        layout: Arc<DescriptorSetLayout>,
+    ) -> anyhow::Result<Self> {
+        let white = Texture::new_solid(
+            context.clone(),
+            uploader,
+            "default_white",
+            TextureFormat::Rgba8Unorm,
+            [u8::MAX; 4],
+        )?;
+        // Straight out of the surface, in tangent space.
+        let flat_normal = Texture::new_solid(
+            context.clone(),
+            uploader,
+            "default_normal",
+            TextureFormat::Rgba8Unorm,
+            [128, 128, u8::MAX, u8::MAX],
+        )?;
+
+        let sampler = Sampler::new(
+            context.device(),
+            ash::vk::Filter::LINEAR,
+            ash::vk::SamplerAddressMode::REPEAT,
+        )?;
+
+        Ok(Self {
+            context,
+            layout,
+            white,
+            flat_normal,
+            sampler,
+            slots: Vec::new(),
+        })
+    }
+
+    // Load a model's textures and materials. Returns a handle for each material, in model order.
+    pub fn add_model(
+        &mut self,
+        uploader: &mut Uploader,
+        model: &ArchivedModel,
+    ) -> anyhow::Result<Vec<MaterialHandle>> {
+        let slots = self.load_model(uploader, model)?;
+
+        let first = self.slots.len() as u32;
+        self.slots.extend(slots);
+
+        Ok((first..self.slots.len() as u32)
+            .map(MaterialHandle)
+            .collect())
+    }
+
+    // Load a model's materials again, for the slots `add_model` handed out for it. Nothing uses
+    // them until they're swapped in with `replace`.
+    pub fn reload_model(
+        &self,
+        uploader: &mut Uploader,
+        model: &ArchivedModel,
+        handles: &[MaterialHandle],
+    ) -> anyhow::Result<ReloadedMaterials> {
+        if model.materials.len() != handles.len() {
+            return Err(anyhow!(
+                "{} has {} materials now instead of {}",
+                model.name,
+                model.materials.len(),
+                handles.len()
+            ));
+        }
+
+        Ok(ReloadedMaterials {
+            handles: handles.to_vec(),
+            slots: self.load_model(uploader, model)?,
+        })
+    }
+
+    // Swap reloaded materials into their slots, so everything using those handles picks them up.
+    // Their textures have to have finished uploading first.
+    pub fn replace(&mut self, reloaded: ReloadedMaterials) -> RetiredMaterials {
+        let old = reloaded
+            .handles
+            .iter()
+            .zip(reloaded.slots)
+            .map(|(handle, slot)| std::mem::replace(&mut self.slots[handle.0 as usize], slot))
+            .collect();
+
+        RetiredMaterials { _slots: old }
+    }
+
+    fn load_model(
+        &self,
+        uploader: &mut Uploader,
+        model: &ArchivedModel,
+    ) -> anyhow::Result<Vec<MaterialSlot>> {
+        let device = self.context.device();
+
+        let num_materials = model.materials.len();
+        if num_materials == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Texture indices in the model are relative to its own textures.
+        let model_textures = model
+            .textures
+            .iter()
+            .map(|asset| Texture::new_from_archived(self.context.clone(), uploader, asset))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut material_data = UniformBuffer::new(
+            self.context.clone(),
+            num_materials,
+            ash::vk::SharingMode::EXCLUSIVE,
+            Some(&format!("{} material_data", model.name)),
+        )?;
+
+        let pool_sizes = [
+            ash::vk::DescriptorPoolSize::default()
+                .ty(ash::vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(num_materials as u32),
+            ash::vk::DescriptorPoolSize::default()
+                .ty(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count((num_materials * TEXTURE_BINDINGS.len()) as u32),
+        ];
+
+        let descriptor_pool = Arc::new(DescriptorPool::new(
+            device.clone(),
+            &pool_sizes,
+            num_materials as u32,
+        )?);
+
+        let mut descriptors = Vec::with_capacity(num_materials);
+        // Only textures are big enough to be worth reading in place.
+        let model_materials = rkyv::deserialize::<Vec<Material>, rancor::Error>(&model.materials)?;
+        for (idx, material) in model_materials.iter().enumerate() {
+            material_data.write(MaterialData::from(material), idx)?;
+
+            let descriptor =
+                DescriptorSet::alloc_from_pool(descriptor_pool.clone(), self.layout.handle())?;
+
+            let buffer_info = [material_data.descriptor_info(idx)];
+
+            let material_textures = [
+                texture_or(&model_textures, material.base_color_texture, &self.white),
+                texture_or(
+                    &model_textures,
+                    material.metallic_roughness_texture,
+                    &self.white,
+                ),
+                texture_or(&model_textures, material.normal_texture, &self.flat_normal),
+                texture_or(&model_textures, material.occlusion_texture, &self.white),
+                texture_or(&model_textures, material.emissive_texture, &self.white),
+            ];
+
+            let image_infos: Vec<[ash::vk::DescriptorImageInfo; 1]> = material_textures
+                .iter()
+                .map(|texture| [texture.descriptor_info(&self.sampler)])
+                .collect();
+
+            let mut writes = vec![ash::vk::WriteDescriptorSet::default()
+                .descriptor_count(1)
+                .descriptor_type(ash::vk::DescriptorType::UNIFORM_BUFFER)
+                .dst_set(descriptor.handle())
+                .dst_binding(MATERIAL_DATA_BINDING)
+                .buffer_info(&buffer_info)];
+
+            writes.extend(TEXTURE_BINDINGS.iter().zip(&image_infos).map(
+                |(binding, image_info)| {
+                    ash::vk::WriteDescriptorSet::default()
+                        .descriptor_count(1)
+                        .descriptor_type(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                        .dst_set(descriptor.handle())
+                        .dst_binding(*binding)
+                        .image_info(image_info)
+                },
+            ));
+
+            unsafe { device.handle().update_descriptor_sets(&writes, &[]) };
+
+            descriptors.push(descriptor);
+        }
+
+        let resources = Arc::new(ModelResources {
+            _textures: model_textures,
+            _material_data: material_data,
+        });
+
+        Ok(descriptors
+            .into_iter()
+            .map(|descriptor| MaterialSlot {
+                descriptor,
+                _resources: resources.clone(),
+            })
+            .collect())
+    }
+
+    pub fn descriptor(&self, material: MaterialHandle) -> Option<&DescriptorSet> {
+        self.slots
+            .get(material.0 as usize)
+            .map(|slot| &slot.descriptor)
+    }
+}
@@ -1,75 +1,153 @@
 use std::sync::Arc;
 
-use common::{Model, Vertex};
+use common::{ArchivedModel, ArchivedVertex, Bounds, Submesh, Vertex};
 use gpu_allocator::vulkan::AllocationCreateDesc;
+use rkyv::rancor;
 
-use crate::vulkan::{buffer::Buffer, command::CommandBuffer, context::Context, device::Device};
+use crate::{
+    scene::MaterialHandle,
+    vulkan::{
+        buffer::Buffer, command::CommandBuffer, context::Context, device::Device, upload::Uploader,
+    },
+};
 
 pub struct Mesh {
     vertex_buffer: Buffer,
     index_buffer: Buffer,
 
     _num_vertices: usize,
-    num_indices: usize,
+    _num_indices: usize,
+
+    submeshes: Vec<Submesh>,
+    // The material each submesh was loaded with, in the same order as the submeshes.
+    materials: Vec<MaterialHandle>,
+    // Covers every submesh.
+    bounds: Bounds,
+}
+
+// Archived vertices go into the vertex buffer as they are, so they have to be laid out just like
+// `Vertex`. Both are repr(C), and archives are little endian like the GPU.
+const _: () = assert!(
+    size_of::<ArchivedVertex>() == size_of::<Vertex>()
+        && align_of::<ArchivedVertex>() == align_of::<Vertex>()
+);
+
+fn union_bounds(submeshes: &[Submesh]) -> Bounds {
+    let Some(first) = submeshes.first() else {
+        return Bounds {
+            min: [0.0; 3],
+            max: [0.0; 3],
+            center: [0.0; 3],
+            radius: 0.0,
+        };
+    };
+
+    let (min, max) = submeshes.iter().fold(
+        (
+            glam::Vec3::from(first.bounds.min),
+            glam::Vec3::from(first.bounds.max),
+        ),
+        |(min, max), submesh| {
+            (
+                min.min(submesh.bounds.min.into()),
+                max.max(submesh.bounds.max.into()),
+            )
+        },
+    );
+
+    // Big enough to hold every submesh's sphere.
+    let center = (min + max) / 2.0;
+    let radius = submeshes
+        .iter()
+        .map(|submesh| center.distance(submesh.bounds.center.into()) + submesh.bounds.radius)
+        .fold(0.0, f32::max);
+
+    Bounds {
+        min: min.into(),
+        max: max.into(),
+        center: center.into(),
+        radius,
+    }
+}
+
+// A device-local buffer that can only be filled through the uploader.
+fn new_gpu_buffer(
+    context: Arc<Context>,
+    name: &str,
+    size: usize,
+    usage: ash::vk::BufferUsageFlags,
+) -> anyhow::Result<Buffer> {
+    let mut buffer = Buffer::new(
+        context,
+        size,
+        usage | ash::vk::BufferUsageFlags::TRANSFER_DST,
+        ash::vk::SharingMode::EXCLUSIVE,
+    )?;
+
+    buffer.allocate(AllocationCreateDesc {
+        name,
+        requirements: buffer.memory_requirements(),
+        location: gpu_allocator::MemoryLocation::GpuOnly,
+        linear: true,
+        allocation_scheme: gpu_allocator::vulkan::AllocationScheme::GpuAllocatorManaged,
+    })?;
+
+    Ok(buffer)
 }
 
 impl Mesh {
-    pub fn new_from_model(context: Arc<Context>, model: &Model) -> anyhow::Result<Self> {
+    // Vertices and indices are copied straight from the archive into staging memory, without
+    // deserializing the model. The buffers aren't ready to draw from until the uploader's next
+    // flush completes.
+    pub fn new_from_archived(
+        context: Arc<Context>,
+        uploader: &mut Uploader,
+        model: &ArchivedModel,
+        model_materials: &[MaterialHandle],
+    ) -> anyhow::Result<Self> {
+        let submeshes = rkyv::deserialize::<Vec<Submesh>, rancor::Error>(&model.submeshes)?;
+
+        let materials = submeshes
+            .iter()
+            .map(|submesh| {
+                model_materials
+                    .get(submesh.material as usize)
+                    .copied()
+                    .ok_or(anyhow::anyhow!(
+                        "submesh {} has invalid material {}",
+                        submesh.name,
+                        submesh.material
+                    ))
+            })
+            .collect::<anyhow::Result<Vec<MaterialHandle>>>()?;
+
         let num_vertices = model.vertices.len();
         let num_indices = model.indices.len();
 
-        let vertex_buffer_size = size_of::<Vertex>() * num_vertices;
-        let mut vertex_buffer = Buffer::new(
+        let vertex_buffer = new_gpu_buffer(
             context.clone(),
-            vertex_buffer_size,
+            &format!("{} vertex buffer", model.name),
+            size_of::<Vertex>() * num_vertices,
             ash::vk::BufferUsageFlags::VERTEX_BUFFER,
-            ash::vk::SharingMode::EXCLUSIVE,
         )?;
-        let name = format!("{} vertex buffer", model.name);
-        let vertex_alloc_desc = AllocationCreateDesc {
-            name: name.as_str(),
-            requirements: vertex_buffer.memory_requirements(),
-            location: gpu_allocator::MemoryLocation::CpuToGpu,
-            linear: true,
-            allocation_scheme: gpu_allocator::vulkan::AllocationScheme::GpuAllocatorManaged,
-        };
-        vertex_buffer.allocate(vertex_alloc_desc)?;
-        let mut slab = vertex_buffer
-            .allocation_mut()
-            .map(|a| a.try_as_mapped_slab())
-            .flatten()
-            .expect("vertex buffer should be valid mapped slab");
-        presser::copy_from_slice_to_offset(model.vertices.as_slice(), &mut slab, 0)?;
-
-        let index_buffer_size = size_of::<u32>() * num_indices;
-        let mut index_buffer = Buffer::new(
+        uploader.upload_buffer(&vertex_buffer, model.vertices.as_slice())?;
+
+        let index_buffer = new_gpu_buffer(
             context.clone(),
-            index_buffer_size,
+            &format!("{} index buffer", model.name),
+            size_of::<u32>() * num_indices,
             ash::vk::BufferUsageFlags::INDEX_BUFFER,
-            ash::vk::SharingMode::EXCLUSIVE,
         )?;
-        let name = format!("{} index buffer", model.name);
-        let index_alloc_desc = AllocationCreateDesc {
-            name: name.as_str(),
-            requirements: index_buffer.memory_requirements(),
-            location: gpu_allocator::MemoryLocation::CpuToGpu,
-            linear: true,
-            allocation_scheme: gpu_allocator::vulkan::AllocationScheme::GpuAllocatorManaged,
-        };
-        index_buffer.allocate(index_alloc_desc)?;
-        let mut slab = index_buffer
-            .allocation_mut()
-            .map(|a| a.try_as_mapped_slab())
-            .flatten()
-            .expect("index buffer should be valid mapped slab");
-
-        presser::copy_from_slice_to_offset(model.indices.as_slice(), &mut slab, 0)?;
+        uploader.upload_buffer(&index_buffer, model.indices.as_slice())?;
 
         Ok(Self {
             vertex_buffer,
             index_buffer,
             _num_vertices: num_vertices,
-            num_indices,
+            _num_indices: num_indices,
+            bounds: union_bounds(&submeshes),
+            submeshes,
+            materials,
         })
     }
 
@@ -93,7 +171,11 @@ impl Mesh {
         };
     }
 
-    pub fn num_indices(&self) -> usize {
-        self.num_indices
+    pub fn bounds(&self) -> &Bounds {
+        &self.bounds
+    }
+
+    pub fn submeshes(&self) -> impl Iterator<Item = (&Submesh, MaterialHandle)> {
+        self.submeshes.iter().zip(self.materials.iter().copied())
     }
 }
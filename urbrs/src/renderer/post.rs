@@ -0,0 +1,245 @@
+use std::sync::Arc;
+
+use anyhow::Context as anyhow_context;
+use bytemuck::bytes_of;
+
+use crate::{
+    assets::Assets,
+    vulkan::{
+        command::CommandBuffer,
+        context::Context,
+        descriptor::{DescriptorPool, DescriptorSet, DescriptorSetLayout},
+        device::Device,
+        image::Image,
+        pipeline::{Pipeline, PipelineBuilder},
+        sampler::Sampler,
+        util,
+    },
+};
+
+// How HDR color gets squeezed into the range the display can show.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tonemapper {
+    // Stephen Hill's fit of the ACES reference and output transforms.
+    #[default]
+    Aces,
+    Reinhard,
+    // Troy Sobotka's AgX, using the polynomial fit of its contrast curve.
+    AgX,
+}
+
+impl Tonemapper {
+    pub const ALL: [Tonemapper; 3] = [Tonemapper::Aces, Tonemapper::Reinhard, Tonemapper::AgX];
+
+    // The one after this in `ALL`, wrapping around.
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|t| *t == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    // Matches the TONEMAPPER_* constants in tonemap.frag.
+    fn shader_id(self) -> u32 {
+        match self {
+            Tonemapper::Aces => 0,
+            Tonemapper::Reinhard => 1,
+            Tonemapper::AgX => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+#[allow(dead_code)] // We don't read these in Rust but we do push them to the GPU.
+struct PostData {
+    // Linear scale applied before tonemapping.
+    exposure: f32,
+    tonemapper: u32,
+}
+
+// Plain old data with no padding, so any bytes are a valid `PostData` and vice versa.
+unsafe impl bytemuck::Zeroable for PostData {}
+unsafe impl bytemuck::Pod for PostData {}
+
+pub const POST_SHADERS: [&str; 2] = ["shader/fullscreen.vert", "shader/tonemap.frag"];
+
+fn build_pipeline(
+    device: Arc<Device>,
+    assets: &Assets,
+    color_format: ash::vk::Format,
+    layout: &Arc<DescriptorSetLayout>,
+) -> anyhow::Result<Pipeline> {
+    let [vertex_shader, fragment_shader] = POST_SHADERS;
+    let vertex_shader_data = assets
+        .read(vertex_shader)
+        .and_then(|asset| util::spirv_from_bytes(&asset))
+        .with_context(|| format!("failed to read vertex shader {vertex_shader}"))?;
+    let fragment_shader_data = assets
+        .read(fragment_shader)
+        .and_then(|asset| util::spirv_from_bytes(&asset))
+        .with_context(|| format!("failed to read fragment shader {fragment_shader}"))?;
+
+    // No vertex input, the vertex shader makes the triangle up from the vertex index.
+    PipelineBuilder::new()
+        .with_color_format(color_format)
+        .with_vertex_shader_data(&vertex_shader_data)
+        .with_fragment_shader_data(&fragment_shader_data)
+        .with_descriptor_set_layouts(std::slice::from_ref(layout))
+        .with_push_constants::<PostData>()
+        .build(device)
+}
+
+// Tonemaps the HDR image from the main pass into the render target with a fullscreen triangle.
+pub struct PostProcess {
+    device: Arc<Device>,
+    pipeline: Pipeline,
+    // Kept around so the pipeline can be rebuilt.
+    color_format: ash::vk::Format,
+    layout: Arc<DescriptorSetLayout>,
+    sampler: Sampler,
+    descriptor: DescriptorSet,
+}
+
+impl PostProcess {
+    pub fn new(
+        context: Arc<Context>,
+        assets: &Assets,
+        color_format: ash::vk::Format,
+    ) -> anyhow::Result<Self> {
+        let device = context.device();
+
+        let input_binding = ash::vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_count(1)
+            .descriptor_type(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .stage_flags(ash::vk::ShaderStageFlags::FRAGMENT);
+
+        let layout = Arc::new(DescriptorSetLayout::new(
+            device.clone(),
+            &[input_binding],
+            ash::vk::DescriptorSetLayoutCreateFlags::empty(),
+        )?);
+
+        let pipeline = build_pipeline(device.clone(), assets, color_format, &layout)?;
+
+        // Pixels line up one to one, so there's nothing to filter.
+        let sampler = Sampler::new(
+            device.clone(),
+            ash::vk::Filter::NEAREST,
+            ash::vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        )?;
+
+        let pool_sizes = [ash::vk::DescriptorPoolSize::default()
+            .ty(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)];
+        let pool = Arc::new(DescriptorPool::new(device.clone(), &pool_sizes, 1)?);
+        let descriptor = DescriptorSet::alloc_from_pool(pool, layout.handle())?;
+
+        Ok(Self {
+            device,
+            pipeline,
+            color_format,
+            layout,
+            sampler,
+            descriptor,
+        })
+    }
+
+    // Rebuild the pipeline from the shaders on disk. Returns the old one, since frames in flight
+    // may still be using it.
+    pub fn reload_pipeline(&mut self, assets: &Assets) -> anyhow::Result<Pipeline> {
+        let pipeline =
+            build_pipeline(self.device.clone(), assets, self.color_format, &self.layout)?;
+
+        Ok(std::mem::replace(&mut self.pipeline, pipeline))
+    }
+
+    // Read from `input` from now on. Nothing in flight can be using the descriptor, so only call
+    // this while the device is idle.
+    pub fn set_input(&self, input: &Image) {
+        let image_info = [ash::vk::DescriptorImageInfo::default()
+            .sampler(self.sampler.handle())
+            .image_view(input.view())
+            .image_layout(ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+
+        let write = ash::vk::WriteDescriptorSet::default()
+            .descriptor_count(1)
+            .descriptor_type(ash::vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .dst_set(self.descriptor.handle())
+            .dst_binding(0)
+            .image_info(&image_info);
+
+        unsafe { self.device.handle().update_descriptor_sets(&[write], &[]) };
+    }
+
+    // The input has to be ready to sample, and the target ready to render into.
+    // `exposure` is in stops.
+    pub fn record(
+        &self,
+        command_buffer: &CommandBuffer,
+        target_view: ash::vk::ImageView,
+        area: ash::vk::Rect2D,
+        tonemapper: Tonemapper,
+        exposure: f32,
+    ) {
+        // Every pixel gets overwritten, so there's no need to clear.
+        let color_attachment_info = ash::vk::RenderingAttachmentInfo::default()
+            .image_view(target_view)
+            .image_layout(ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(ash::vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(ash::vk::AttachmentStoreOp::STORE);
+
+        let color_attachments = &[color_attachment_info];
+
+        let rendering_info = ash::vk::RenderingInfo::default()
+            .color_attachments(color_attachments)
+            .layer_count(1)
+            .render_area(area);
+
+        // Not flipped, the main pass already flipped what we're reading.
+        let viewport = ash::vk::Viewport::default()
+            .width(area.extent.width as f32)
+            .height(area.extent.height as f32)
+            .max_depth(1.0);
+
+        let post_data = PostData {
+            exposure: exposure.exp2(),
+            tonemapper: tonemapper.shader_id(),
+        };
+
+        unsafe {
+            let cmd = command_buffer.handle();
+            let device = self.device.handle();
+
+            device.cmd_begin_rendering(cmd, &rendering_info);
+
+            device.cmd_bind_pipeline(
+                cmd,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.handle(),
+            );
+            device.cmd_set_viewport(cmd, 0, &[viewport]);
+            device.cmd_set_scissor(cmd, 0, &[area]);
+
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                ash::vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.layout(),
+                0,
+                &[self.descriptor.handle()],
+                &[],
+            );
+
+            device.cmd_push_constants(
+                cmd,
+                self.pipeline.layout(),
+                ash::vk::ShaderStageFlags::ALL_GRAPHICS,
+                0,
+                bytes_of(&post_data),
+            );
+
+            device.cmd_draw(cmd, 3, 1, 0, 0);
+
+            device.cmd_end_rendering(cmd);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+    sync::Arc,
+};
+
+use gpu_allocator::vulkan::AllocationCreateDesc;
+
+use crate::vulkan::{buffer::Buffer, command::CommandBuffer, context::Context, device::Device};
+
+// A frame copied back to the host, as tightly packed RGBA8 rows.
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Screenshot {
+    pub fn save_png(&self, path: &Path) -> anyhow::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+
+        Ok(())
+    }
+
+    // Load an 8-bit RGB or RGBA PNG, like the ones `save_png` writes.
+    pub fn load_png(path: &Path) -> anyhow::Result<Self> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![
+            0;
+            reader
+                .output_buffer_size()
+                .ok_or(anyhow::anyhow!("PNG is too large to decode"))?
+        ];
+        let info = reader.next_frame(&mut buf)?;
+        let data = &buf[..info.buffer_size()];
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => data.to_vec(),
+            png::ColorType::Rgb => data
+                .chunks_exact(3)
+                .flat_map(|px| [px[0], px[1], px[2], u8::MAX])
+                .collect(),
+            color_type => return Err(anyhow::anyhow!("unsupported PNG color type {color_type:?}")),
+        };
+
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+}
+
+// Describes how to get RGBA8 out of the bytes of a color attachment format.
+enum ChannelOrder {
+    Rgba,
+    Bgra,
+}
+
+fn channel_order(format: ash::vk::Format) -> Option<ChannelOrder> {
+    // We don't care about sRGB vs. UNORM here. Either way the bytes are what ends up
+    // on screen, and that's what PNG expects.
+    match format {
+        ash::vk::Format::R8G8B8A8_SRGB | ash::vk::Format::R8G8B8A8_UNORM => {
+            Some(ChannelOrder::Rgba)
+        }
+        ash::vk::Format::B8G8R8A8_SRGB | ash::vk::Format::B8G8R8A8_UNORM => {
+            Some(ChannelOrder::Bgra)
+        }
+        _ => None,
+    }
+}
+
+// A host-visible buffer sized to hold one frame of a color attachment.
+pub struct Readback {
+    buffer: Buffer,
+    format: ash::vk::Format,
+    extent: ash::vk::Extent2D,
+}
+
+impl Readback {
+    const BYTES_PER_PIXEL: usize = 4;
+
+    pub fn new(
+        context: Arc<Context>,
+        format: ash::vk::Format,
+        extent: ash::vk::Extent2D,
+    ) -> anyhow::Result<Self> {
+        if channel_order(format).is_none() {
+            return Err(anyhow::anyhow!(
+                "readback of color format {format:?} is not supported"
+            ));
+        }
+
+        let size = extent.width as usize * extent.height as usize * Self::BYTES_PER_PIXEL;
+
+        let mut buffer = Buffer::new(
+            context.clone(),
+            size,
+            ash::vk::BufferUsageFlags::TRANSFER_DST,
+            ash::vk::SharingMode::EXCLUSIVE,
+        )?;
+
+        buffer.allocate(AllocationCreateDesc {
+            name: "readback buffer",
+            requirements: buffer.memory_requirements(),
+            location: gpu_allocator::MemoryLocation::GpuToCpu,
+            linear: true,
+            allocation_scheme: gpu_allocator::vulkan::AllocationScheme::GpuAllocatorManaged,
+        })?;
+
+        Ok(Self {
+            buffer,
+            format,
+            extent,
+        })
+    }
+
+    pub fn buffer(&self) -> ash::vk::Buffer {
+        self.buffer.handle()
+    }
+
+    // Record a copy of `image` into our buffer. `image` must be in TRANSFER_SRC_OPTIMAL, and the
+    // buffer needs a barrier before the host can read it.
+    pub fn record_copy(
+        &self,
+        device: Arc<Device>,
+        command_buffer: &CommandBuffer,
+        image: ash::vk::Image,
+    ) {
+        let subresource = ash::vk::ImageSubresourceLayers::default()
+            .aspect_mask(ash::vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1);
+
+        // Zero row length and image height mean the buffer is tightly packed.
+        let region = ash::vk::BufferImageCopy::default()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(subresource)
+            .image_extent(self.extent.into());
+
+        unsafe {
+            device.handle().cmd_copy_image_to_buffer(
+                command_buffer.handle(),
+                image,
+                ash::vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.buffer.handle(),
+                &[region],
+            );
+        }
+    }
+
+    // Read the copied frame. Only valid once the submission recording the copy has completed.
+    pub fn read(&self) -> anyhow::Result<Screenshot> {
+        let data = self
+            .buffer
+            .allocation()
+            .and_then(|a| a.mapped_slice())
+            .expect("readback buffer should be mapped");
+
+        let order = channel_order(self.format).expect("format was checked on creation");
+
+        let size = self.extent.width as usize * self.extent.height as usize * Self::BYTES_PER_PIXEL;
+
+        let pixels = data[..size]
+            .chunks_exact(Self::BYTES_PER_PIXEL)
+            .flat_map(|px| {
+                let (r, g, b) = match order {
+                    ChannelOrder::Rgba => (px[0], px[1], px[2]),
+                    ChannelOrder::Bgra => (px[2], px[1], px[0]),
+                };
+
+                // We present opaque, so whatever alpha we cleared to shouldn't leak into the image.
+                [r, g, b, u8::MAX]
+            })
+            .collect();
+
+        Ok(Screenshot {
+            width: self.extent.width,
+            height: self.extent.height,
+            pixels,
+        })
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    any::Any,
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as anyhow_context;
+use notify::{RecursiveMode, Watcher};
+
+// rsrc writes its outputs in several steps, so wait for things to go quiet before loading any of
+// them. Otherwise we'd pick up half written files.
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+// Watches a directory for files that get rewritten, e.g. by `cargo make process`.
+pub struct AssetWatcher {
+    // Stops watching when dropped.
+    _watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+
+    // Changed since the last batch went out, canonicalized.
+    pending: HashSet<PathBuf>,
+    last_event: Instant,
+}
+
+impl AssetWatcher {
+    pub fn new(root: &Path) -> anyhow::Result<Self> {
+        let (sender, events) = mpsc::channel();
+
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .with_context(|| format!("failed to watch {}", root.display()))?;
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            pending: HashSet::new(),
+            last_event: Instant::now(),
+        })
+    }
+
+    // Files that were created or modified, once nothing has changed for `SETTLE_TIME`. Empty
+    // otherwise. Never blocks. If the watcher reported an error, the first one is returned and
+    // whatever changed is held on to for the next call.
+    pub fn poll(&mut self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut error = None;
+        for event in self.events.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    error.get_or_insert(err);
+                    continue;
+                }
+            };
+
+            if !(event.kind.is_create() || event.kind.is_modify()) {
+                continue;
+            }
+
+            // Canonicalize so callers can compare against paths they spelled differently. This
+            // also drops anything that's already gone again, like temporary files.
+            self.pending.extend(
+                event
+                    .paths
+                    .iter()
+                    .filter_map(|path| path.canonicalize().ok()),
+            );
+            self.last_event = Instant::now();
+        }
+
+        if let Some(error) = error {
+            return Err(error).context("error watching assets");
+        }
+
+        if self.pending.is_empty() || self.last_event.elapsed() < SETTLE_TIME {
+            return Ok(Vec::new());
+        }
+
+        Ok(self.pending.drain().collect())
+    }
+}
+
+// Whether `path` is the same file as `other`, which came from `AssetWatcher::poll`.
+pub fn is_same_file(path: &Path, other: &Path) -> bool {
+    path.canonicalize().is_ok_and(|path| path == other)
+}
+
+// Holds on to GPU objects that were replaced while frames in flight might still be using them,
+// and drops them once those frames are done.
+#[derive(Default)]
+pub struct DeletionQueue {
+    // Along with the frame count at the time they were replaced.
+    objects: Vec<(u64, Box<dyn Any>)>,
+}
+
+impl DeletionQueue {
+    pub fn push(&mut self, frame_count: u64, object: impl Any) {
+        self.objects.push((frame_count, Box::new(object)));
+    }
+
+    // Drop everything that was replaced before `finished`, the count of frames that are known to
+    // be done on the GPU.
+    pub fn flush(&mut self, finished: u64) {
+        self.objects
+            .retain(|(frame_count, _)| *frame_count > finished);
+    }
+}
@@ -0,0 +1,276 @@
+use std::sync::Arc;
+
+use anyhow::Context as anyhow_context;
+use bytemuck::bytes_of;
+use common::Vertex;
+
+use crate::{
+    assets::Assets,
+    camera::{Camera, Frustum},
+    renderer::mesh::Mesh,
+    vulkan::{
+        command::CommandBuffer,
+        context::Context,
+        device::Device,
+        image::Image,
+        mesh::MeshVertex,
+        pipeline::{DepthMode, Pipeline, PipelineBuilder},
+        sampler::Sampler,
+        util,
+    },
+};
+
+pub const CASCADE_COUNT: usize = 4;
+
+pub const SHADOW_VERTEX_SHADER: &str = "shader/shadow.vert";
+
+const SHADOW_MAP_SIZE: u32 = 2048;
+const SHADOW_MAP_FORMAT: ash::vk::Format = ash::vk::Format::D32_SFLOAT;
+
+// Nothing further than this from the camera gets shadowed.
+const SHADOW_DISTANCE: f32 = 400.0;
+// How far to lean from evenly spaced cascades (0) to logarithmically spaced ones (1).
+const SPLIT_LAMBDA: f32 = 0.8;
+// Casters outside a cascade's bounds can still shadow it, so leave room for them towards the light.
+const CASTER_MARGIN: f32 = 500.0;
+
+// Rasterization depth bias, to keep surfaces from shadowing themselves.
+const DEPTH_BIAS_CONSTANT: f32 = 1.25;
+const DEPTH_BIAS_SLOPE: f32 = 1.75;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Cascade {
+    // World space to the light's clip space.
+    pub view_proj: glam::Mat4,
+    // Distance from the camera where this cascade ends.
+    pub far: f32,
+}
+
+// Split the camera's view into cascades, each covered by an orthographic projection along `light_dir`.
+pub fn compute_cascades(camera: &Camera, light_dir: glam::Vec3) -> [Cascade; CASCADE_COUNT] {
+    let (near, far) = camera.projection().depth_range();
+    let far = far.min(SHADOW_DISTANCE).max(near);
+
+    let split = |idx: usize| {
+        let t = idx as f32 / CASCADE_COUNT as f32;
+        let uniform = near + (far - near) * t;
+        let log = near * (far / near).powf(t);
+
+        uniform + (log - uniform) * SPLIT_LAMBDA
+    };
+
+    // Looking straight up or down leaves Y no good as an up vector.
+    let up = if light_dir.y.abs() > 0.99 {
+        glam::Vec3::Z
+    } else {
+        glam::Vec3::Y
+    };
+
+    std::array::from_fn(|idx| {
+        let cascade_near = split(idx);
+        let cascade_far = split(idx + 1);
+
+        let corners = camera.frustum_corners(cascade_near, cascade_far);
+        let center = corners.iter().sum::<glam::Vec3>() / corners.len() as f32;
+
+        // Bound the slice with a sphere rather than a box, so the projection's size doesn't
+        // change as the camera turns. Round it up a little so it doesn't flicker either.
+        let radius = corners
+            .iter()
+            .map(|corner| corner.distance(center))
+            .fold(0.0, f32::max);
+        let radius = (radius * 16.0).ceil() / 16.0;
+
+        let eye = center - light_dir * (radius + CASTER_MARGIN);
+        let view = glam::Mat4::look_at_rh(eye, center, up);
+        let mut proj = glam::Mat4::orthographic_rh(
+            -radius,
+            radius,
+            -radius,
+            radius,
+            0.0,
+            2.0 * radius + CASTER_MARGIN,
+        );
+
+        // Snap to whole texels, so shadow edges don't crawl as the camera moves.
+        let half_size = SHADOW_MAP_SIZE as f32 / 2.0;
+        let origin = (proj * view).project_point3(glam::Vec3::ZERO).truncate() * half_size;
+        let offset = (origin.round() - origin) / half_size;
+        proj.w_axis.x += offset.x;
+        proj.w_axis.y += offset.y;
+
+        Cascade {
+            view_proj: proj * view,
+            far: cascade_far,
+        }
+    })
+}
+
+fn build_pipeline(device: Arc<Device>, assets: &Assets) -> anyhow::Result<Pipeline> {
+    let vertex_shader_data = assets
+        .read(SHADOW_VERTEX_SHADER)
+        .and_then(|asset| util::spirv_from_bytes(&asset))
+        .with_context(|| format!("failed to read vertex shader {SHADOW_VERTEX_SHADER}"))?;
+
+    PipelineBuilder::new()
+        .with_depth_format(SHADOW_MAP_FORMAT)
+        .with_vertex_shader_data(&vertex_shader_data)
+        .with_vertex_layout_info(Vertex::layout())
+        .with_push_constants::<glam::Mat4>()
+        .with_depth_bias(DEPTH_BIAS_CONSTANT, DEPTH_BIAS_SLOPE)
+        .build(device)
+}
+
+// Depth from the primary directional light, one layer per cascade.
+pub struct ShadowMap {
+    image: Image,
+    sampler: Sampler,
+    pipeline: Pipeline,
+}
+
+impl ShadowMap {
+    pub fn new(context: Arc<Context>, assets: &Assets) -> anyhow::Result<Self> {
+        let device = context.device();
+
+        let extent = ash::vk::Extent2D::default()
+            .width(SHADOW_MAP_SIZE)
+            .height(SHADOW_MAP_SIZE);
+
+        let image = Image::new_array(
+            context.clone(),
+            "shadow_map",
+            SHADOW_MAP_FORMAT,
+            extent,
+            CASCADE_COUNT as u32,
+            ash::vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | ash::vk::ImageUsageFlags::SAMPLED,
+            ash::vk::ImageAspectFlags::DEPTH,
+        )?;
+
+        let sampler = Sampler::new_shadow(device.clone())?;
+        let pipeline = build_pipeline(device, assets)?;
+
+        Ok(Self {
+            image,
+            sampler,
+            pipeline,
+        })
+    }
+
+    // Rebuild the pipeline from the shader on disk. Returns the old one, since frames in flight
+    // may still be using it.
+    pub fn reload_pipeline(
+        &mut self,
+        device: Arc<Device>,
+        assets: &Assets,
+    ) -> anyhow::Result<Pipeline> {
+        let pipeline = build_pipeline(device, assets)?;
+
+        Ok(std::mem::replace(&mut self.pipeline, pipeline))
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    pub fn descriptor_info(&self) -> ash::vk::DescriptorImageInfo {
+        ash::vk::DescriptorImageInfo::default()
+            .sampler(self.sampler.handle())
+            .image_view(self.image.view())
+            .image_layout(ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    // Render each cascade. The map has to be ready to render into. Every cascade is at least
+    // cleared, so it's always safe to sample even with nothing to draw.
+    pub fn record(
+        &self,
+        device: Arc<Device>,
+        command_buffer: &CommandBuffer,
+        cascades: &[Cascade],
+        casters: &[(&Mesh, glam::Mat4)],
+    ) -> anyhow::Result<()> {
+        let mut depth_clear = ash::vk::ClearValue::default();
+        depth_clear.depth_stencil =
+            ash::vk::ClearDepthStencilValue::default().depth(DepthMode::Standard.clear_depth());
+
+        let area = ash::vk::Rect2D::default().extent(self.image.extent());
+
+        // Unlike the main pass, Y isn't flipped, so NDC maps straight onto texture coordinates.
+        let viewport = ash::vk::Viewport::default()
+            .width(SHADOW_MAP_SIZE as f32)
+            .height(SHADOW_MAP_SIZE as f32)
+            .max_depth(1.0);
+
+        for layer in 0..CASCADE_COUNT as u32 {
+            let view = self
+                .image
+                .layer_view(layer)
+                .ok_or(anyhow::anyhow!("shadow map has no layer {layer}"))?;
+
+            let depth_attachment_info = ash::vk::RenderingAttachmentInfo::default()
+                .image_view(view)
+                .image_layout(ash::vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL)
+                .load_op(ash::vk::AttachmentLoadOp::CLEAR)
+                .clear_value(depth_clear)
+                .store_op(ash::vk::AttachmentStoreOp::STORE);
+
+            let rendering_info = ash::vk::RenderingInfo::default()
+                .layer_count(1)
+                .depth_attachment(&depth_attachment_info)
+                .render_area(area);
+
+            let cascade = cascades.get(layer as usize);
+
+            unsafe {
+                let cmd = command_buffer.handle();
+                device.handle().cmd_begin_rendering(cmd, &rendering_info);
+
+                if let Some(cascade) = cascade {
+                    device.handle().cmd_bind_pipeline(
+                        cmd,
+                        ash::vk::PipelineBindPoint::GRAPHICS,
+                        self.pipeline.handle(),
+                    );
+                    device.handle().cmd_set_viewport(cmd, 0, &[viewport]);
+                    device.handle().cmd_set_scissor(cmd, 0, &[area]);
+                    device
+                        .handle()
+                        .cmd_set_depth_compare_op(cmd, DepthMode::Standard.compare_op());
+
+                    let frustum = Frustum::from_matrix(cascade.view_proj);
+
+                    for (mesh, model) in casters {
+                        if !frustum.intersects(mesh.bounds(), *model) {
+                            continue;
+                        }
+
+                        mesh.bind(device.clone(), command_buffer);
+
+                        let mvp = cascade.view_proj * *model;
+                        device.handle().cmd_push_constants(
+                            cmd,
+                            self.pipeline.layout(),
+                            ash::vk::ShaderStageFlags::ALL_GRAPHICS,
+                            0,
+                            bytes_of(&mvp),
+                        );
+
+                        for (submesh, _) in mesh.submeshes() {
+                            device.handle().cmd_draw_indexed(
+                                cmd,
+                                submesh.index_count,
+                                1,
+                                submesh.first_index,
+                                submesh.vertex_offset as i32,
+                                0,
+                            );
+                        }
+                    }
+                }
+
+                device.handle().cmd_end_rendering(cmd);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use crate::vulkan::{context::Context, image::Image, swapchain::Swapchain, sync::Semaphore};
+
+pub struct OffscreenTarget {
+    image: Image,
+}
+
+impl OffscreenTarget {
+    // Match the swapchain's sRGB encoding so offscreen frames look the same as windowed ones.
+    pub const COLOR_FORMAT: ash::vk::Format = ash::vk::Format::R8G8B8A8_SRGB;
+
+    pub fn new(context: Arc<Context>, width: u32, height: u32) -> anyhow::Result<Self> {
+        let format = Self::COLOR_FORMAT;
+
+        let props = context
+            .device()
+            .physical_device()
+            .get_format_properties(format);
+        if !props
+            .optimal_tiling_features
+            .contains(ash::vk::FormatFeatureFlags::COLOR_ATTACHMENT)
+        {
+            return Err(anyhow::anyhow!(
+                "offscreen color format {format:?} is not supported as a color attachment"
+            ));
+        }
+
+        let image = Image::new(
+            context,
+            "offscreen_color_target",
+            format,
+            ash::vk::Extent2D::default().width(width).height(height),
+            1,
+            ash::vk::ImageUsageFlags::COLOR_ATTACHMENT | ash::vk::ImageUsageFlags::TRANSFER_SRC,
+            ash::vk::ImageAspectFlags::COLOR,
+        )?;
+
+        Ok(Self { image })
+    }
+}
+
+// The image we're rendering into this frame.
+pub struct TargetImage {
+    pub image: ash::vk::Image,
+    pub view: ash::vk::ImageView,
+    // Only set when rendering to the swapchain, so we know what to present.
+    pub swap_idx: Option<u32>,
+}
+
+pub enum RenderTarget {
+    Swapchain(Arc<Swapchain>),
+    Offscreen(OffscreenTarget),
+}
+
+impl RenderTarget {
+    pub fn extent(&self) -> ash::vk::Extent2D {
+        match self {
+            RenderTarget::Swapchain(swapchain) => swapchain.extent(),
+            RenderTarget::Offscreen(offscreen) => offscreen.image.extent(),
+        }
+    }
+
+    pub fn area(&self) -> ash::vk::Rect2D {
+        self.extent().into()
+    }
+
+    pub fn color_format(&self) -> ash::vk::Format {
+        match self {
+            RenderTarget::Swapchain(swapchain) => swapchain.surface_color_format(),
+            RenderTarget::Offscreen(offscreen) => offscreen.image.format(),
+        }
+    }
+
+    pub fn supports_readback(&self) -> bool {
+        match self {
+            RenderTarget::Swapchain(swapchain) => swapchain.supports_readback(),
+            // Offscreen targets are always created with TRANSFER_SRC.
+            RenderTarget::Offscreen(_) => true,
+        }
+    }
+
+    // Get the image to render into. For the swapchain, `acquired` is signalled once the image is ready.
+    // Returns None if the swapchain is out of date and has to be recreated first.
+    pub fn acquire(&self, acquired: &Semaphore) -> anyhow::Result<Option<TargetImage>> {
+        match self {
+            RenderTarget::Swapchain(swapchain) => {
+                let swap_image = swapchain.acquire_image(acquired)?;
+
+                Ok(swap_image.map(|swap_image| TargetImage {
+                    image: swap_image.image,
+                    view: swap_image.view,
+                    swap_idx: Some(swap_image.idx),
+                }))
+            }
+            RenderTarget::Offscreen(offscreen) => Ok(Some(TargetImage {
+                image: offscreen.image.handle(),
+                view: offscreen.image.view(),
+                swap_idx: None,
+            })),
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::sync::Arc;
+
+use common::{ArchivedTexture, TextureFormat, TextureMip};
+use rkyv::rancor;
+
+use crate::vulkan::{context::Context, image::Image, sampler::Sampler, upload::Uploader};
+
+// A sampled image uploaded from a processed texture asset.
+pub struct Texture {
+    image: Image,
+}
+
+fn vk_format(format: TextureFormat) -> ash::vk::Format {
+    match format {
+        TextureFormat::Rgba8Srgb => ash::vk::Format::R8G8B8A8_SRGB,
+        TextureFormat::Rgba8Unorm => ash::vk::Format::R8G8B8A8_UNORM,
+    }
+}
+
+fn bytes_per_pixel(format: TextureFormat) -> u64 {
+    match format {
+        TextureFormat::Rgba8Srgb | TextureFormat::Rgba8Unorm => 4,
+    }
+}
+
+// Make sure every mip is where the copy regions will say it is, since they're read straight out of
+// the asset and the device won't check them for us.
+fn check_mips(
+    name: &str,
+    format: TextureFormat,
+    mips: &[TextureMip],
+    data: &[u8],
+) -> anyhow::Result<()> {
+    let base = mips
+        .first()
+        .ok_or(anyhow::anyhow!("texture {name} has no mips"))?;
+    if base.width == 0 || base.height == 0 {
+        return Err(anyhow::anyhow!("texture {name} is empty"));
+    }
+
+    let max_mips = u32::BITS - base.width.max(base.height).leading_zeros();
+    if mips.len() > max_mips as usize {
+        return Err(anyhow::anyhow!(
+            "texture {name} has {} mips, but a {}x{} image can only have {max_mips}",
+            mips.len(),
+            base.width,
+            base.height
+        ));
+    }
+
+    for (level, mip) in mips.iter().enumerate() {
+        // Each level is half the size of the one before it, rounding down, until it hits 1.
+        let width = (base.width >> level).max(1);
+        let height = (base.height >> level).max(1);
+        if (mip.width, mip.height) != (width, height) {
+            return Err(anyhow::anyhow!(
+                "mip {level} of texture {name} is {}x{}, but should be {width}x{height}",
+                mip.width,
+                mip.height
+            ));
+        }
+
+        let size = width as u64 * height as u64 * bytes_per_pixel(format);
+        if mip.size as u64 != size {
+            return Err(anyhow::anyhow!(
+                "mip {level} of texture {name} is {} bytes, but should be {size}",
+                mip.size
+            ));
+        }
+
+        if mip.offset as u64 + mip.size as u64 > data.len() as u64 {
+            return Err(anyhow::anyhow!(
+                "mip {level} of texture {name} runs past the end of its data"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+impl Texture {
+    // The texture isn't ready to sample until the uploader's next flush completes.
+    pub fn new_from_asset(
+        context: Arc<Context>,
+        uploader: &mut Uploader,
+        asset: &common::Texture,
+    ) -> anyhow::Result<Self> {
+        Self::new_with_data(
+            context,
+            uploader,
+            &asset.name,
+            asset.format,
+            &asset.mips,
+            &asset.data,
+        )
+    }
+
+    // Like `new_from_asset`, but the pixels are copied straight out of the archive.
+    pub fn new_from_archived(
+        context: Arc<Context>,
+        uploader: &mut Uploader,
+        asset: &ArchivedTexture,
+    ) -> anyhow::Result<Self> {
+        let format = rkyv::deserialize::<TextureFormat, rancor::Error>(&asset.format)?;
+        let mips = rkyv::deserialize::<Vec<TextureMip>, rancor::Error>(&asset.mips)?;
+
+        Self::new_with_data(context, uploader, &asset.name, format, &mips, &asset.data)
+    }
+
+    fn new_with_data(
+        context: Arc<Context>,
+        uploader: &mut Uploader,
+        name: &str,
+        format: TextureFormat,
+        mips: &[TextureMip],
+        data: &[u8],
+    ) -> anyhow::Result<Self> {
+        check_mips(name, format, mips, data)?;
+        let base = &mips[0];
+
+        let extent = ash::vk::Extent2D::default()
+            .width(base.width)
+            .height(base.height);
+
+        let image = Image::new(
+            context,
+            name,
+            vk_format(format),
+            extent,
+            mips.len() as u32,
+            ash::vk::ImageUsageFlags::SAMPLED | ash::vk::ImageUsageFlags::TRANSFER_DST,
+            ash::vk::ImageAspectFlags::COLOR,
+        )?;
+
+        let regions: Vec<ash::vk::BufferImageCopy> = mips
+            .iter()
+            .enumerate()
+            .map(|(level, mip)| mip_copy_region(level as u32, mip))
+            .collect();
+
+        uploader.upload_image(&image, data, &regions)?;
+
+        Ok(Self { image })
+    }
+
+    // A 1x1 texture of a single color, for when there's nothing else to bind.
+    pub fn new_solid(
+        context: Arc<Context>,
+        uploader: &mut Uploader,
+        name: &str,
+        format: TextureFormat,
+        color: [u8; 4],
+    ) -> anyhow::Result<Self> {
+        let asset = common::Texture {
+            name: name.to_string(),
+            format,
+            mips: vec![TextureMip {
+                width: 1,
+                height: 1,
+                offset: 0,
+                size: color.len() as u32,
+            }],
+            data: color.to_vec(),
+        };
+
+        Self::new_from_asset(context, uploader, &asset)
+    }
+
+    pub fn descriptor_info(&self, sampler: &Sampler) -> ash::vk::DescriptorImageInfo {
+        ash::vk::DescriptorImageInfo::default()
+            .sampler(sampler.handle())
+            .image_view(self.image.view())
+            .image_layout(ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+}
+
+fn mip_copy_region(level: u32, mip: &TextureMip) -> ash::vk::BufferImageCopy {
+    let subresource = ash::vk::ImageSubresourceLayers::default()
+        .aspect_mask(ash::vk::ImageAspectFlags::COLOR)
+        .mip_level(level)
+        .base_array_layer(0)
+        .layer_count(1);
+
+    // Mips are tightly packed, so zero row length and image height.
+    ash::vk::BufferImageCopy::default()
+        .buffer_offset(mip.offset as u64)
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(subresource)
+        .image_extent(
+            ash::vk::Extent3D::default()
+                .width(mip.width)
+                .height(mip.height)
+                .depth(1),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A tightly packed chain for a `width`x`height` RGBA8 texture, and enough data to hold it.
+    fn mip_chain(width: u32, height: u32) -> (Vec<TextureMip>, Vec<u8>) {
+        let mut mips = Vec::new();
+        let (mut width, mut height, mut offset) = (width, height, 0);
+        loop {
+            let size = width * height * 4;
+            mips.push(TextureMip {
+                width,
+                height,
+                offset,
+                size,
+            });
+            offset += size;
+
+            if width == 1 && height == 1 {
+                break;
+            }
+            width = (width / 2).max(1);
+            height = (height / 2).max(1);
+        }
+
+        (mips, vec![0; offset as usize])
+    }
+
+    fn check(mips: &[TextureMip], data: &[u8]) -> anyhow::Result<()> {
+        check_mips("test", TextureFormat::Rgba8Unorm, mips, data)
+    }
+
+    #[test]
+    fn valid_mips() {
+        let (mips, data) = mip_chain(8, 2);
+        assert_eq!(mips.len(), 4);
+        check(&mips, &data).unwrap();
+        // Stopping early is fine.
+        check(&mips[..2], &data).unwrap();
+    }
+
+    #[test]
+    fn mips_out_of_bounds() {
+        let (mut mips, data) = mip_chain(8, 2);
+        check(&mips, &data[..data.len() - 1]).unwrap_err();
+
+        mips[1].offset = u32::MAX;
+        check(&mips, &data).unwrap_err();
+    }
+
+    #[test]
+    fn inconsistent_mips() {
+        let (mips, data) = mip_chain(8, 2);
+
+        let mut wrong_extent = mips.clone();
+        wrong_extent[2].width = 3;
+        check(&wrong_extent, &data).unwrap_err();
+
+        let mut wrong_size = mips.clone();
+        wrong_size[0].size -= 4;
+        check(&wrong_size, &data).unwrap_err();
+
+        let mut too_many = mips.clone();
+        too_many.push(mips[3].clone());
+        check(&too_many, &data).unwrap_err();
+
+        check(&[], &data).unwrap_err();
+    }
+}
@@ -0,0 +1,232 @@
+// A mesh loaded into the renderer with `Renderer::load_model`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MeshHandle(pub(crate) u32);
+
+// A material loaded into the renderer with `Renderer::load_model`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialHandle(pub(crate) u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EntityId(usize);
+
+pub use common::LightKind;
+
+// A light attached to an entity. It sits at the entity's origin and shines down its -Z.
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    // Linear RGB.
+    pub color: glam::Vec3,
+    pub intensity: f32,
+    // Distance where the light fades out completely. Infinite if unset.
+    pub range: Option<f32>,
+}
+
+impl From<&common::Light> for Light {
+    fn from(light: &common::Light) -> Self {
+        Self {
+            kind: light.kind,
+            color: light.color.into(),
+            intensity: light.intensity,
+            range: light.range,
+        }
+    }
+}
+
+// Scale, then rotate, then translate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: glam::Vec3,
+    pub rotation: glam::Quat,
+    pub scale: glam::Vec3,
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: glam::Vec3::ZERO,
+        rotation: glam::Quat::IDENTITY,
+        scale: glam::Vec3::ONE,
+    };
+
+    pub fn from_translation(translation: glam::Vec3) -> Self {
+        Self {
+            translation,
+            ..Self::IDENTITY
+        }
+    }
+
+    // Placed at `position`, with -Z pointing along `direction`.
+    pub fn looking_along(position: glam::Vec3, direction: glam::Vec3) -> Self {
+        Self {
+            translation: position,
+            rotation: glam::Quat::from_rotation_arc(
+                glam::Vec3::NEG_Z,
+                direction.normalize_or(glam::Vec3::NEG_Z),
+            ),
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn matrix(&self) -> glam::Mat4 {
+        glam::Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+pub struct Entity {
+    pub name: String,
+    // Relative to the parent, if there is one.
+    pub transform: Transform,
+    pub mesh: Option<MeshHandle>,
+    // Replaces the materials the mesh was loaded with, if set.
+    pub material: Option<MaterialHandle>,
+    pub light: Option<Light>,
+
+    // Only set on creation, so parents always come before their children.
+    parent: Option<EntityId>,
+}
+
+impl Entity {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            transform: Transform::IDENTITY,
+            mesh: None,
+            material: None,
+            light: None,
+            parent: None,
+        }
+    }
+
+    pub fn with_transform(self, transform: Transform) -> Self {
+        Self { transform, ..self }
+    }
+
+    pub fn with_mesh(self, mesh: MeshHandle) -> Self {
+        Self {
+            mesh: Some(mesh),
+            ..self
+        }
+    }
+
+    pub fn with_material(self, material: MaterialHandle) -> Self {
+        Self {
+            material: Some(material),
+            ..self
+        }
+    }
+
+    pub fn with_light(self, light: Light) -> Self {
+        Self {
+            light: Some(light),
+            ..self
+        }
+    }
+
+    pub fn with_parent(self, parent: EntityId) -> Self {
+        Self {
+            parent: Some(parent),
+            ..self
+        }
+    }
+
+    pub fn parent(&self) -> Option<EntityId> {
+        self.parent
+    }
+}
+
+#[derive(Default)]
+pub struct Scene {
+    entities: Vec<Entity>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, entity: Entity) -> anyhow::Result<EntityId> {
+        if let Some(parent) = entity.parent {
+            if parent.0 >= self.entities.len() {
+                return Err(anyhow::anyhow!(
+                    "parent {parent:?} of {} does not exist",
+                    entity.name
+                ));
+            }
+        }
+
+        self.entities.push(entity);
+
+        Ok(EntityId(self.entities.len() - 1))
+    }
+
+    // Add a model's lights as children of `parent`, so they move along with the model.
+    pub fn add_model_lights(
+        &mut self,
+        parent: EntityId,
+        lights: &[common::Light],
+    ) -> anyhow::Result<Vec<EntityId>> {
+        lights
+            .iter()
+            .map(|light| {
+                let transform =
+                    Transform::looking_along(light.position.into(), light.direction.into());
+
+                self.add(
+                    Entity::new(&light.name)
+                        .with_transform(transform)
+                        .with_light(light.into())
+                        .with_parent(parent),
+                )
+            })
+            .collect()
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&Entity> {
+        self.entities.get(id.0)
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut Entity> {
+        self.entities.get_mut(id.0)
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = (EntityId, &Entity)> {
+        self.entities
+            .iter()
+            .enumerate()
+            .map(|(idx, entity)| (EntityId(idx), entity))
+    }
+
+    // Every entity's transform relative to the world, in the same order as `entities`.
+    pub fn world_transforms(&self) -> Vec<glam::Mat4> {
+        let mut world: Vec<glam::Mat4> = Vec::with_capacity(self.entities.len());
+
+        // Parents come first, so their world transform is always ready by the time we reach a child.
+        for entity in &self.entities {
+            let parent = entity
+                .parent
+                .map_or(glam::Mat4::IDENTITY, |parent| world[parent.0]);
+
+            world.push(parent * entity.transform.matrix());
+        }
+
+        world
+    }
+
+    pub fn world_transform(&self, id: EntityId) -> Option<glam::Mat4> {
+        let mut entity = self.get(id)?;
+        let mut transform = entity.transform.matrix();
+
+        while let Some(parent) = entity.parent {
+            entity = &self.entities[parent.0];
+            transform = entity.transform.matrix() * transform;
+        }
+
+        Some(transform)
+    }
+}
@@ -9,20 +9,18 @@ use super::surface::Surface;
 use super::swapchain::Swapchain;
 
 pub struct Context {
-    _instance: Arc<Instance>,
-    _surface: Arc<Surface>,
+    instance: Arc<Instance>,
+    surface: Option<Arc<Surface>>,
     device: Arc<Device>,
-    swapchain: Arc<Swapchain>,
     allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
 }
 
 impl Context {
     pub fn new(
-        window: &winit::window::Window,
         display_handle: RawDisplayHandle,
         window_handle: RawWindowHandle,
     ) -> anyhow::Result<Self> {
-        let instance = Arc::new(Instance::new(display_handle)?);
+        let instance = Arc::new(Instance::new(Some(display_handle))?);
 
         let surface = Arc::new(Surface::new(
             instance.clone(),
@@ -30,18 +28,44 @@ impl Context {
             display_handle,
         )?);
 
-        let phys_device = PhysicalDevice::select_device(instance.clone(), &surface)?
+        let phys_device = PhysicalDevice::select_device(instance.clone(), Some(&surface))?
             .ok_or(anyhow::anyhow!("no valid physical device found"))?;
 
         let device = Arc::new(Device::new(instance.clone(), phys_device)?);
 
-        let swapchain = Arc::new(Swapchain::new(
-            instance.clone(),
-            device.clone(),
-            surface.clone(),
-            window,
-        )?);
+        let allocator = Self::new_allocator(&instance, &device)?;
+
+        Ok(Self {
+            instance,
+            surface: Some(surface),
+            device,
+            allocator,
+        })
+    }
+
+    // A context with no window, surface or swapchain. Rendering has to go to an offscreen target.
+    pub fn new_headless() -> anyhow::Result<Self> {
+        let instance = Arc::new(Instance::new(None)?);
+
+        let phys_device = PhysicalDevice::select_device(instance.clone(), None)?
+            .ok_or(anyhow::anyhow!("no valid physical device found"))?;
+
+        let device = Arc::new(Device::new(instance.clone(), phys_device)?);
 
+        let allocator = Self::new_allocator(&instance, &device)?;
+
+        Ok(Self {
+            instance,
+            surface: None,
+            device,
+            allocator,
+        })
+    }
+
+    fn new_allocator(
+        instance: &Instance,
+        device: &Device,
+    ) -> anyhow::Result<Arc<Mutex<gpu_allocator::vulkan::Allocator>>> {
         let alloc_create_desc = gpu_allocator::vulkan::AllocatorCreateDesc {
             instance: instance.handle().clone(),
             device: device.handle().clone(),
@@ -52,25 +76,33 @@ impl Context {
             allocation_sizes: gpu_allocator::AllocationSizes::default(),
         };
 
-        let allocator = Arc::new(Mutex::new(gpu_allocator::vulkan::Allocator::new(
+        Ok(Arc::new(Mutex::new(gpu_allocator::vulkan::Allocator::new(
             &alloc_create_desc,
-        )?));
-
-        Ok(Self {
-            _instance: instance,
-            _surface: surface,
-            device,
-            swapchain,
-            allocator,
-        })
+        )?)))
     }
 
     pub fn device(&self) -> Arc<Device> {
         self.device.clone()
     }
 
-    pub fn swapchain(&self) -> Arc<Swapchain> {
-        self.swapchain.clone()
+    // Create a swapchain for our surface. Pass the current swapchain as `old_swapchain` when recreating,
+    // and only drop it once the new one exists. Returns None while the surface has no area.
+    pub fn create_swapchain(
+        &self,
+        win_size: winit::dpi::PhysicalSize<u32>,
+        old_swapchain: Option<&Swapchain>,
+    ) -> anyhow::Result<Option<Swapchain>> {
+        let surface = self.surface.clone().ok_or(anyhow::anyhow!(
+            "headless contexts have no surface to present to"
+        ))?;
+
+        Swapchain::new(
+            self.instance.clone(),
+            self.device.clone(),
+            surface,
+            win_size,
+            old_swapchain,
+        )
     }
 
     pub fn alloc_gpu_mem(
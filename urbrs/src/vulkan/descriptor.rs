@@ -10,17 +10,12 @@ pub struct DescriptorPool {
 impl DescriptorPool {
     pub fn new(
         device: Arc<Device>,
-        ty: ash::vk::DescriptorType,
-        size: u32,
+        pool_sizes: &[ash::vk::DescriptorPoolSize],
+        max_sets: u32,
     ) -> anyhow::Result<Self> {
-        // For now, we only support pools devoted to one type of descriptor.
-        let pool_sizes: [ash::vk::DescriptorPoolSize; 1] = [ash::vk::DescriptorPoolSize::default()
-            .ty(ty)
-            .descriptor_count(size)];
-
         let info = ash::vk::DescriptorPoolCreateInfo::default()
-            .pool_sizes(&pool_sizes)
-            .max_sets(size);
+            .pool_sizes(pool_sizes)
+            .max_sets(max_sets);
 
         let pool = unsafe { device.handle().create_descriptor_pool(&info, None) }?;
 
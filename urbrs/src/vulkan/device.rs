@@ -15,8 +15,9 @@ pub struct Device {
     physical_device: PhysicalDevice,
 
     graphics_queue: DeviceQueue,
-    _transfer_queue: DeviceQueue,
-    present_queue: DeviceQueue,
+    transfer_queue: DeviceQueue,
+    // Headless devices have nothing to present to.
+    present_queue: Option<DeviceQueue>,
 }
 
 fn new_queue_create_info<'a>(
@@ -54,10 +55,11 @@ impl Device {
             ash::vk::PhysicalDeviceBufferDeviceAddressFeatures::default()
                 .buffer_device_address(true);
 
-        let required_extensions: Vec<*const i8> = PhysicalDevice::REQUIRED_EXTENSIONS
-            .iter()
-            .map(|s| s.as_ptr())
-            .collect();
+        let required_extensions: Vec<*const i8> =
+            PhysicalDevice::enabled_extensions(physical_device.has_surface_support())
+                .iter()
+                .map(|s| s.as_ptr())
+                .collect();
 
         let mut queue_infos: Vec<ash::vk::DeviceQueueCreateInfo> = Vec::new();
         let priorities = [1.0];
@@ -71,8 +73,10 @@ impl Device {
             queue_infos.push(new_queue_create_info(transfer_family, &priorities));
         }
 
-        if present_family != graphics_family && present_family != transfer_family {
-            queue_infos.push(new_queue_create_info(present_family, &priorities));
+        if let Some(present_family) = present_family {
+            if present_family != graphics_family && present_family != transfer_family {
+                queue_infos.push(new_queue_create_info(present_family, &priorities));
+            }
         }
 
         let create_info = ash::vk::DeviceCreateInfo::default()
@@ -91,14 +95,14 @@ impl Device {
 
         let graphics_queue = get_device_queue(&device, graphics_family);
         let transfer_queue = get_device_queue(&device, transfer_family);
-        let present_queue = get_device_queue(&device, present_family);
+        let present_queue = present_family.map(|family| get_device_queue(&device, family));
 
         Ok(Self {
             _instance: instance,
             handle: device,
             physical_device,
             graphics_queue,
-            _transfer_queue: transfer_queue,
+            transfer_queue,
             present_queue,
         })
     }
@@ -115,12 +119,13 @@ impl Device {
         &self.graphics_queue
     }
 
-    pub fn _transfer_queue(&self) -> &DeviceQueue {
-        &self._transfer_queue
+    // The same queue as graphics if the device has no separate transfer family.
+    pub fn transfer_queue(&self) -> &DeviceQueue {
+        &self.transfer_queue
     }
 
-    pub fn present_queue(&self) -> &DeviceQueue {
-        &self.present_queue
+    pub fn present_queue(&self) -> Option<&DeviceQueue> {
+        self.present_queue.as_ref()
     }
 }
 
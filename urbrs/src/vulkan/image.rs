@@ -0,0 +1,298 @@
+use std::sync::Arc;
+
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme};
+
+use super::context::Context;
+
+// A 2D device-local image with a view covering all of its mips.
+// Array images have a view over every layer too, as well as one for each layer on its own.
+pub struct Image {
+    context: Arc<Context>,
+    handle: ash::vk::Image,
+    allocation: Allocation,
+    view: ash::vk::ImageView,
+    // Only for array images.
+    layer_views: Vec<ash::vk::ImageView>,
+
+    format: ash::vk::Format,
+    extent: ash::vk::Extent2D,
+    mip_levels: u32,
+    aspect: ash::vk::ImageAspectFlags,
+}
+
+fn create_view(
+    context: &Context,
+    image: ash::vk::Image,
+    view_type: ash::vk::ImageViewType,
+    format: ash::vk::Format,
+    range: ash::vk::ImageSubresourceRange,
+) -> anyhow::Result<ash::vk::ImageView> {
+    let image_view_info = ash::vk::ImageViewCreateInfo::default()
+        .image(image)
+        .format(format)
+        .view_type(view_type)
+        .components(ash::vk::ComponentMapping::default())
+        .subresource_range(range);
+
+    let view = unsafe {
+        context
+            .device()
+            .handle()
+            .create_image_view(&image_view_info, None)?
+    };
+
+    Ok(view)
+}
+
+// Allocate dedicated memory for `image` and bind it. The memory is freed again if binding fails.
+fn allocate_image_memory(
+    context: &Context,
+    name: &str,
+    image: ash::vk::Image,
+) -> anyhow::Result<Allocation> {
+    let requirements = unsafe {
+        context
+            .device()
+            .handle()
+            .get_image_memory_requirements(image)
+    };
+
+    let allocation = context.alloc_gpu_mem(&AllocationCreateDesc {
+        name,
+        requirements,
+        location: gpu_allocator::MemoryLocation::GpuOnly,
+        linear: false,
+        allocation_scheme: AllocationScheme::DedicatedImage(image),
+    })?;
+
+    let bound = unsafe {
+        context
+            .device()
+            .handle()
+            .bind_image_memory(image, allocation.memory(), allocation.offset())
+    };
+    if let Err(err) = bound {
+        let _ = context.free_gpu_mem(allocation);
+        return Err(err.into());
+    }
+
+    Ok(allocation)
+}
+
+impl Image {
+    pub fn new(
+        context: Arc<Context>,
+        name: &str,
+        format: ash::vk::Format,
+        extent: ash::vk::Extent2D,
+        mip_levels: u32,
+        usage: ash::vk::ImageUsageFlags,
+        aspect: ash::vk::ImageAspectFlags,
+    ) -> anyhow::Result<Self> {
+        Self::new_with_layers(
+            context,
+            name,
+            format,
+            extent,
+            mip_levels,
+            1,
+            ash::vk::ImageViewType::TYPE_2D,
+            ash::vk::SampleCountFlags::TYPE_1,
+            usage,
+            aspect,
+        )
+    }
+
+    // A render target with `samples` samples per pixel, and no mips.
+    pub fn new_multisampled(
+        context: Arc<Context>,
+        name: &str,
+        format: ash::vk::Format,
+        extent: ash::vk::Extent2D,
+        samples: ash::vk::SampleCountFlags,
+        usage: ash::vk::ImageUsageFlags,
+        aspect: ash::vk::ImageAspectFlags,
+    ) -> anyhow::Result<Self> {
+        Self::new_with_layers(
+            context,
+            name,
+            format,
+            extent,
+            1,
+            1,
+            ash::vk::ImageViewType::TYPE_2D,
+            samples,
+            usage,
+            aspect,
+        )
+    }
+
+    // An array of `layers` images without mips, e.g. for shadow cascades.
+    pub fn new_array(
+        context: Arc<Context>,
+        name: &str,
+        format: ash::vk::Format,
+        extent: ash::vk::Extent2D,
+        layers: u32,
+        usage: ash::vk::ImageUsageFlags,
+        aspect: ash::vk::ImageAspectFlags,
+    ) -> anyhow::Result<Self> {
+        let mut image = Self::new_with_layers(
+            context,
+            name,
+            format,
+            extent,
+            1,
+            layers,
+            ash::vk::ImageViewType::TYPE_2D_ARRAY,
+            ash::vk::SampleCountFlags::TYPE_1,
+            usage,
+            aspect,
+        )?;
+
+        for layer in 0..layers {
+            let range = ash::vk::ImageSubresourceRange::default()
+                .aspect_mask(aspect)
+                .base_mip_level(0)
+                .level_count(1)
+                .base_array_layer(layer)
+                .layer_count(1);
+
+            let view = create_view(
+                &image.context,
+                image.handle,
+                ash::vk::ImageViewType::TYPE_2D,
+                format,
+                range,
+            )?;
+            image.layer_views.push(view);
+        }
+
+        Ok(image)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn new_with_layers(
+        context: Arc<Context>,
+        name: &str,
+        format: ash::vk::Format,
+        extent: ash::vk::Extent2D,
+        mip_levels: u32,
+        layers: u32,
+        view_type: ash::vk::ImageViewType,
+        samples: ash::vk::SampleCountFlags,
+        usage: ash::vk::ImageUsageFlags,
+        aspect: ash::vk::ImageAspectFlags,
+    ) -> anyhow::Result<Self> {
+        let image_create_info = ash::vk::ImageCreateInfo::default()
+            .image_type(ash::vk::ImageType::TYPE_2D)
+            .extent(extent.into())
+            .mip_levels(mip_levels)
+            .array_layers(layers)
+            .format(format)
+            .tiling(ash::vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(ash::vk::SharingMode::EXCLUSIVE)
+            .samples(samples);
+
+        let handle = unsafe {
+            context
+                .device()
+                .handle()
+                .create_image(&image_create_info, None)?
+        };
+
+        // Nothing owns the image until we return, so clean up by hand if anything fails first.
+        let allocation = match allocate_image_memory(&context, name, handle) {
+            Ok(allocation) => allocation,
+            Err(err) => {
+                unsafe { context.device().handle().destroy_image(handle, None) };
+                return Err(err);
+            }
+        };
+
+        let range = ash::vk::ImageSubresourceRange::default()
+            .aspect_mask(aspect)
+            .base_mip_level(0)
+            .level_count(mip_levels)
+            .base_array_layer(0)
+            .layer_count(layers);
+
+        let view = match create_view(&context, handle, view_type, format, range) {
+            Ok(view) => view,
+            Err(err) => {
+                // Already failing, the original error is the one worth reporting.
+                let _ = context.free_gpu_mem(allocation);
+                unsafe { context.device().handle().destroy_image(handle, None) };
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            context,
+            handle,
+            allocation,
+            view,
+            layer_views: Vec::new(),
+            format,
+            extent,
+            mip_levels,
+            aspect,
+        })
+    }
+
+    pub fn handle(&self) -> ash::vk::Image {
+        self.handle
+    }
+
+    pub fn view(&self) -> ash::vk::ImageView {
+        self.view
+    }
+
+    // A view of just one layer of an array image.
+    pub fn layer_view(&self, layer: u32) -> Option<ash::vk::ImageView> {
+        self.layer_views.get(layer as usize).copied()
+    }
+
+    pub fn format(&self) -> ash::vk::Format {
+        self.format
+    }
+
+    pub fn extent(&self) -> ash::vk::Extent2D {
+        self.extent
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn aspect(&self) -> ash::vk::ImageAspectFlags {
+        self.aspect
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        let allocation = std::mem::take(&mut self.allocation);
+        self.context.free_gpu_mem(allocation).unwrap();
+
+        unsafe {
+            for view in &self.layer_views {
+                self.context
+                    .device()
+                    .handle()
+                    .destroy_image_view(*view, None);
+            }
+
+            self.context
+                .device()
+                .handle()
+                .destroy_image_view(self.view, None);
+
+            self.context
+                .device()
+                .handle()
+                .destroy_image(self.handle, None);
+        };
+    }
+}
@@ -43,21 +43,24 @@ impl Instance {
         &[ash::vk::EXT_DEBUG_UTILS_NAME];
 
     fn get_required_instance_extensions(
-        display_handle: RawDisplayHandle,
+        display_handle: Option<RawDisplayHandle>,
     ) -> anyhow::Result<Vec<&'static CStr>> {
         let mut base: Vec<&'static CStr> = Self::REQUIRED_INSTANCE_EXTENSIONS_BASE
             .iter()
             .copied()
             .collect();
 
-        let mut surface_exts: Vec<&'static CStr> =
-            ash_window::enumerate_required_extensions(display_handle)?
-                .iter()
-                // Safety: ash_window should always give us a pointer that's safe to use here.
-                .map(|ext| unsafe { CStr::from_ptr(*ext) })
-                .collect();
+        // Headless instances have no display to present to, so skip the surface extensions.
+        if let Some(display_handle) = display_handle {
+            let mut surface_exts: Vec<&'static CStr> =
+                ash_window::enumerate_required_extensions(display_handle)?
+                    .iter()
+                    // Safety: ash_window should always give us a pointer that's safe to use here.
+                    .map(|ext| unsafe { CStr::from_ptr(*ext) })
+                    .collect();
 
-        base.append(&mut surface_exts);
+            base.append(&mut surface_exts);
+        }
 
         Ok(base)
     }
@@ -104,7 +107,7 @@ impl Instance {
             .collect()
     }
 
-    pub fn new(display_handle: RawDisplayHandle) -> anyhow::Result<Self> {
+    pub fn new(display_handle: Option<RawDisplayHandle>) -> anyhow::Result<Self> {
         let entry = ash::Entry::linked();
 
         let app_info = ash::vk::ApplicationInfo::default()
@@ -28,6 +28,21 @@ impl MeshVertex for Vertex {
                 .location(1)
                 .format(ash::vk::Format::R32G32B32_SFLOAT)
                 .offset(offset_of!(Vertex, normal) as u32),
+            ash::vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(2)
+                .format(ash::vk::Format::R32G32_SFLOAT)
+                .offset(offset_of!(Vertex, tex_coord) as u32),
+            ash::vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(3)
+                .format(ash::vk::Format::R32G32B32A32_SFLOAT)
+                .offset(offset_of!(Vertex, tangent) as u32),
+            ash::vk::VertexInputAttributeDescription::default()
+                .binding(0)
+                .location(4)
+                .format(ash::vk::Format::R32G32B32A32_SFLOAT)
+                .offset(offset_of!(Vertex, color) as u32),
         ];
 
         VertexLayoutInfo { descs, bindings }
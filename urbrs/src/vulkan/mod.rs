@@ -3,11 +3,14 @@ pub mod command;
 pub mod context;
 pub mod descriptor;
 pub mod device;
+pub mod image;
 pub mod instance;
 pub mod mesh;
 pub mod phys_device;
 pub mod pipeline;
+pub mod sampler;
 pub mod surface;
 pub mod swapchain;
 pub mod sync;
+pub mod upload;
 pub mod util;
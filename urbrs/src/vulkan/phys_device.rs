@@ -2,6 +2,14 @@ use std::{ffi::CStr, sync::Arc};
 
 use super::{instance::Instance, surface::Surface};
 
+// Everything we need to know about a physical device to present to a surface.
+// Surface capabilities change with the window, so query those from `Surface` when needed.
+pub struct SurfaceSupport {
+    pub format: ash::vk::SurfaceFormatKHR,
+    pub present_mode: ash::vk::PresentModeKHR,
+    pub present_family: u32,
+}
+
 pub struct PhysicalDevice {
     instance: Arc<Instance>,
     handle: ash::vk::PhysicalDevice,
@@ -10,19 +18,17 @@ pub struct PhysicalDevice {
     _extensions: Vec<ash::vk::ExtensionProperties>,
     _queue_families: Vec<ash::vk::QueueFamilyProperties>,
 
-    surface_caps: ash::vk::SurfaceCapabilitiesKHR,
-    surface_format: ash::vk::SurfaceFormatKHR,
-    present_mode: ash::vk::PresentModeKHR,
+    // None for headless devices, which never present.
+    surface_support: Option<SurfaceSupport>,
 
     graphics_family: u32,
     transfer_family: u32,
-    present_family: u32,
 }
 
 impl PhysicalDevice {
     pub fn select_device(
         instance: Arc<Instance>,
-        surface: &Surface,
+        surface: Option<&Surface>,
     ) -> anyhow::Result<Option<Self>> {
         let device_handles = unsafe { instance.handle().enumerate_physical_devices() }?;
 
@@ -135,15 +141,53 @@ impl PhysicalDevice {
         desired.or(first).copied()
     }
 
-    pub const REQUIRED_EXTENSIONS: &'static [&'static CStr; 3] = &[
-        ash::vk::KHR_SWAPCHAIN_NAME,
+    pub const REQUIRED_EXTENSIONS: &'static [&'static CStr; 2] = &[
         ash::vk::KHR_DYNAMIC_RENDERING_NAME,
         ash::vk::KHR_SYNCHRONIZATION2_NAME,
     ];
 
+    // Only required if we're going to present to a surface.
+    pub const SURFACE_EXTENSIONS: &'static [&'static CStr; 1] = &[ash::vk::KHR_SWAPCHAIN_NAME];
+
+    unsafe fn query_surface_support(
+        handle: ash::vk::PhysicalDevice,
+        surface: &Surface,
+        queue_families: &Vec<ash::vk::QueueFamilyProperties>,
+        graphics_family: u32,
+    ) -> anyhow::Result<SurfaceSupport> {
+        let present_family = Self::select_present_family(
+            handle,
+            *surface.handle(),
+            surface.surface_instance(),
+            queue_families,
+            graphics_family,
+        )?
+        .ok_or(anyhow::anyhow!("no present family found"))?;
+
+        let surface_formats = surface
+            .surface_instance()
+            .get_physical_device_surface_formats(handle, *surface.handle())?;
+
+        let format = Self::select_surface_format(&surface_formats)
+            .ok_or(anyhow::anyhow!("no surface format available"))?;
+
+        let present_modes = surface
+            .surface_instance()
+            .get_physical_device_surface_present_modes(handle, *surface.handle())?;
+
+        let present_mode = Self::select_present_mode(&present_modes)
+            .ok_or(anyhow::anyhow!("no valid present mode available"))?;
+
+        Ok(SurfaceSupport {
+            format,
+            present_mode,
+            present_family,
+        })
+    }
+
     unsafe fn new(
         instance: Arc<Instance>,
-        surface: &Surface,
+        surface: Option<&Surface>,
         handle: ash::vk::PhysicalDevice,
     ) -> anyhow::Result<Self> {
         let mut properties = ash::vk::PhysicalDeviceProperties2::default();
@@ -171,9 +215,8 @@ impl PhysicalDevice {
             .handle()
             .enumerate_device_extension_properties(handle)?;
 
-        let unsupported_extensions: Vec<&CStr> = Self::REQUIRED_EXTENSIONS
-            .iter()
-            .copied()
+        let unsupported_extensions: Vec<&CStr> = Self::enabled_extensions(surface.is_some())
+            .into_iter()
             .filter(|required| !Self::is_extension_supported(&extensions, &required))
             .collect();
 
@@ -205,32 +248,15 @@ impl PhysicalDevice {
 
         let transfer_family = Self::select_transfer_family(&queue_families, graphics_family);
 
-        let present_family = Self::select_present_family(
-            handle,
-            *surface.handle(),
-            surface.surface_instance(),
-            &queue_families,
-            graphics_family,
-        )?
-        .ok_or(anyhow::anyhow!("no present family found"))?;
-
-        let surface_caps = surface
-            .surface_instance()
-            .get_physical_device_surface_capabilities(handle, *surface.handle())?;
-
-        let surface_formats = surface
-            .surface_instance()
-            .get_physical_device_surface_formats(handle, *surface.handle())?;
-
-        let surface_format = Self::select_surface_format(&surface_formats)
-            .ok_or(anyhow::anyhow!("no surface format available"))?;
-
-        let present_modes = surface
-            .surface_instance()
-            .get_physical_device_surface_present_modes(handle, *surface.handle())?;
-
-        let present_mode = Self::select_present_mode(&present_modes)
-            .ok_or(anyhow::anyhow!("no valid present mode available"))?;
+        let surface_support = match surface {
+            Some(surface) => Some(Self::query_surface_support(
+                handle,
+                surface,
+                &queue_families,
+                graphics_family,
+            )?),
+            None => None,
+        };
 
         let phys_device = Self {
             instance,
@@ -239,12 +265,9 @@ impl PhysicalDevice {
             _features: features,
             _extensions: extensions,
             _queue_families: queue_families,
-            surface_caps,
+            surface_support,
             graphics_family,
             transfer_family,
-            present_family,
-            surface_format,
-            present_mode,
         };
 
         Ok(phys_device)
@@ -270,23 +293,35 @@ impl PhysicalDevice {
         self.transfer_family
     }
 
-    pub fn present_family(&self) -> u32 {
-        self.present_family
+    pub fn present_family(&self) -> Option<u32> {
+        self.surface_support.as_ref().map(|s| s.present_family)
     }
 
-    pub fn surface_caps(&self) -> &ash::vk::SurfaceCapabilitiesKHR {
-        &self.surface_caps
+    pub fn surface_support(&self) -> Option<&SurfaceSupport> {
+        self.surface_support.as_ref()
     }
 
-    pub fn surface_format(&self) -> ash::vk::SurfaceFormatKHR {
-        self.surface_format
+    pub fn enabled_extensions(with_surface: bool) -> Vec<&'static CStr> {
+        let mut extensions = Self::REQUIRED_EXTENSIONS.to_vec();
+
+        if with_surface {
+            extensions.extend_from_slice(Self::SURFACE_EXTENSIONS);
+        }
+
+        extensions
     }
 
-    pub fn present_mode(&self) -> ash::vk::PresentModeKHR {
-        self.present_mode
+    pub fn has_surface_support(&self) -> bool {
+        self.surface_support.is_some()
     }
 
     pub fn limits(&self) -> &ash::vk::PhysicalDeviceLimits {
         &self.properties.limits
     }
+
+    // Sample counts we can use for rendering into both color and depth attachments.
+    pub fn supported_sample_counts(&self) -> ash::vk::SampleCountFlags {
+        self.limits().framebuffer_color_sample_counts
+            & self.limits().framebuffer_depth_sample_counts
+    }
 }
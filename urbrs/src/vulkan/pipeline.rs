@@ -44,6 +44,32 @@ impl Drop for ShaderModule {
     }
 }
 
+// Which way depth increases. Pipelines leave the compare op dynamic, so this can be set per frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthMode {
+    // 0 at the near plane, 1 at the far plane.
+    Standard,
+    // 1 at the near plane, 0 at the far plane.
+    Reversed,
+}
+
+impl DepthMode {
+    pub fn compare_op(self) -> ash::vk::CompareOp {
+        match self {
+            DepthMode::Standard => ash::vk::CompareOp::LESS,
+            DepthMode::Reversed => ash::vk::CompareOp::GREATER,
+        }
+    }
+
+    // The depth of nothing at all, i.e. the far plane.
+    pub fn clear_depth(self) -> f32 {
+        match self {
+            DepthMode::Standard => 1.0,
+            DepthMode::Reversed => 0.0,
+        }
+    }
+}
+
 pub struct Pipeline {
     device: Arc<Device>,
     handle: ash::vk::Pipeline,
@@ -80,11 +106,20 @@ pub struct PipelineBuilder<'s> {
     depth_format: Option<ash::vk::Format>,
 
     push_constant_range: Option<ash::vk::PushConstantRange>,
+    // Constant and slope factors.
+    depth_bias: Option<(f32, f32)>,
+    samples: ash::vk::SampleCountFlags,
 
     vertex_layout_info: Option<VertexLayoutInfo>,
     descriptor_set_layouts: Vec<Arc<DescriptorSetLayout>>,
 }
 
+impl Default for PipelineBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'s> PipelineBuilder<'s> {
     pub fn new() -> Self {
         Self {
@@ -94,6 +129,8 @@ impl<'s> PipelineBuilder<'s> {
             depth_format: None,
             vertex_layout_info: None,
             push_constant_range: None,
+            depth_bias: None,
+            samples: ash::vk::SampleCountFlags::TYPE_1,
             descriptor_set_layouts: Vec::new(),
         }
     }
@@ -147,6 +184,19 @@ impl<'s> PipelineBuilder<'s> {
         }
     }
 
+    // Push depth away from the light, so surfaces don't shadow themselves in a shadow map.
+    pub fn with_depth_bias(self, constant_factor: f32, slope_factor: f32) -> Self {
+        Self {
+            depth_bias: Some((constant_factor, slope_factor)),
+            ..self
+        }
+    }
+
+    // Has to match the sample count of the attachments we render into.
+    pub fn with_samples(self, samples: ash::vk::SampleCountFlags) -> Self {
+        Self { samples, ..self }
+    }
+
     pub fn with_descriptor_set_layouts(self, layout: &[Arc<DescriptorSetLayout>]) -> Self {
         Self {
             descriptor_set_layouts: Vec::from(layout),
@@ -154,14 +204,18 @@ impl<'s> PipelineBuilder<'s> {
         }
     }
 
+    // Leave out the fragment shader and color format for a depth-only pipeline, or the depth
+    // format for one without depth testing, e.g. for post-processing.
     pub fn build(self, device: Arc<Device>) -> anyhow::Result<Pipeline> {
         let vertex_shader_data = self
             .vertex_shader_data
             .ok_or(anyhow::anyhow!("no vertex shader specified"))?;
 
-        let fragment_shader_data = self
-            .fragment_shader_data
-            .ok_or(anyhow::anyhow!("no fragment shader specified"))?;
+        if self.fragment_shader_data.is_some() != self.color_format.is_some() {
+            return Err(anyhow::anyhow!(
+                "fragment shader and color format must be specified together"
+            ));
+        }
 
         let vertex_shader = ShaderModule::new(
             device.clone(),
@@ -169,11 +223,12 @@ impl<'s> PipelineBuilder<'s> {
             ash::vk::ShaderStageFlags::VERTEX,
         )?;
 
-        let fragment_shader = ShaderModule::new(
-            device.clone(),
-            &fragment_shader_data,
-            ash::vk::ShaderStageFlags::FRAGMENT,
-        )?;
+        let fragment_shader = self
+            .fragment_shader_data
+            .map(|data| {
+                ShaderModule::new(device.clone(), data, ash::vk::ShaderStageFlags::FRAGMENT)
+            })
+            .transpose()?;
 
         // Don't initialize this - we'll leave it as dynamic state.
         let viewport_info = ash::vk::PipelineViewportStateCreateInfo::default()
@@ -192,7 +247,12 @@ impl<'s> PipelineBuilder<'s> {
             .color_write_mask(ash::vk::ColorComponentFlags::RGBA)
             .blend_enable(false);
 
-        let attachments = &[color_attachment];
+        let attachments: &[ash::vk::PipelineColorBlendAttachmentState] =
+            if self.color_format.is_some() {
+                &[color_attachment]
+            } else {
+                &[]
+            };
         let color_blend_info = ash::vk::PipelineColorBlendStateCreateInfo::default()
             .logic_op_enable(false)
             .logic_op(ash::vk::LogicOp::COPY)
@@ -202,23 +262,28 @@ impl<'s> PipelineBuilder<'s> {
             .primitive_restart_enable(false)
             .topology(ash::vk::PrimitiveTopology::TRIANGLE_LIST);
 
+        let (depth_bias_constant, depth_bias_slope) = self.depth_bias.unwrap_or((0.0, 0.0));
         let raster_info = ash::vk::PipelineRasterizationStateCreateInfo::default()
             .cull_mode(ash::vk::CullModeFlags::NONE)
             .front_face(ash::vk::FrontFace::CLOCKWISE)
             .polygon_mode(ash::vk::PolygonMode::FILL)
+            .depth_bias_enable(self.depth_bias.is_some())
+            .depth_bias_constant_factor(depth_bias_constant)
+            .depth_bias_slope_factor(depth_bias_slope)
             .line_width(1.0f32);
 
         let multisample_info = ash::vk::PipelineMultisampleStateCreateInfo::default()
             .sample_shading_enable(false)
-            .rasterization_samples(ash::vk::SampleCountFlags::TYPE_1)
+            .rasterization_samples(self.samples)
             .min_sample_shading(1.0f32)
             .alpha_to_coverage_enable(false)
             .alpha_to_one_enable(false);
 
         let depth_info = ash::vk::PipelineDepthStencilStateCreateInfo::default()
-            .depth_test_enable(true)
-            .depth_write_enable(true)
-            .depth_compare_op(ash::vk::CompareOp::LESS)
+            .depth_test_enable(self.depth_format.is_some())
+            .depth_write_enable(self.depth_format.is_some())
+            // Dynamic, this is just a placeholder.
+            .depth_compare_op(DepthMode::Standard.compare_op())
             .depth_bounds_test_enable(false)
             .stencil_test_enable(false)
             .front(ash::vk::StencilOpState::default())
@@ -226,22 +291,28 @@ impl<'s> PipelineBuilder<'s> {
             .min_depth_bounds(0.0f32)
             .max_depth_bounds(1.0f32);
 
-        let color_format = self
-            .color_format
-            .ok_or(anyhow::anyhow!("no color format specified!"))?;
-        let depth_format = self
-            .depth_format
-            .ok_or(anyhow::anyhow!("no depth format specified"))?;
+        if self.depth_format.is_none() && self.color_format.is_none() {
+            return Err(anyhow::anyhow!(
+                "at least one of a color or depth format must be specified"
+            ));
+        }
 
-        let color_formats = &[color_format];
+        let color_formats: Vec<ash::vk::Format> = self.color_format.into_iter().collect();
         let mut rendering_info = ash::vk::PipelineRenderingCreateInfo::default()
-            .color_attachment_formats(color_formats)
-            .depth_attachment_format(depth_format);
+            .color_attachment_formats(&color_formats)
+            .depth_attachment_format(self.depth_format.unwrap_or(ash::vk::Format::UNDEFINED));
 
-        let dynamic_info = ash::vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&[
+        let mut dynamic_states = vec![
             ash::vk::DynamicState::VIEWPORT,
             ash::vk::DynamicState::SCISSOR,
-        ]);
+        ];
+        if self.depth_format.is_some() {
+            // Follows the camera's projection, see `DepthMode`.
+            dynamic_states.push(ash::vk::DynamicState::DEPTH_COMPARE_OP);
+        }
+
+        let dynamic_info =
+            ash::vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
 
         let mut push_constant_ranges: Vec<ash::vk::PushConstantRange> = Vec::new();
         if let Some(range) = self.push_constant_range {
@@ -260,12 +331,13 @@ impl<'s> PipelineBuilder<'s> {
 
         let layout = unsafe { device.handle().create_pipeline_layout(&layout_info, None)? };
 
-        let vertex_shader_info = vertex_shader.shader_stage_create_info();
-        let fragment_shader_info = fragment_shader.shader_stage_create_info();
-        let stages = &[vertex_shader_info, fragment_shader_info];
+        let stages: Vec<ash::vk::PipelineShaderStageCreateInfo> = std::iter::once(&vertex_shader)
+            .chain(fragment_shader.as_ref())
+            .map(|shader| shader.shader_stage_create_info())
+            .collect();
 
         let info = ash::vk::GraphicsPipelineCreateInfo::default()
-            .stages(stages)
+            .stages(&stages)
             .viewport_state(&viewport_info)
             .vertex_input_state(&vertex_input_info)
             .color_blend_state(&color_blend_info)
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use super::device::Device;
+
+pub struct Sampler {
+    device: Arc<Device>,
+    handle: ash::vk::Sampler,
+}
+
+impl Sampler {
+    // Samples every mip level, blending between them.
+    pub fn new(
+        device: Arc<Device>,
+        filter: ash::vk::Filter,
+        address_mode: ash::vk::SamplerAddressMode,
+    ) -> anyhow::Result<Self> {
+        let info = ash::vk::SamplerCreateInfo::default()
+            .mag_filter(filter)
+            .min_filter(filter)
+            .mipmap_mode(ash::vk::SamplerMipmapMode::LINEAR)
+            .address_mode_u(address_mode)
+            .address_mode_v(address_mode)
+            .address_mode_w(address_mode)
+            .min_lod(0.0)
+            .max_lod(ash::vk::LOD_CLAMP_NONE);
+
+        let handle = unsafe { device.handle().create_sampler(&info, None)? };
+
+        Ok(Self { device, handle })
+    }
+
+    // For depth comparisons, e.g. shadow maps. Linear filtering blends the results of the
+    // four nearest comparisons, so even a single lookup is smoothed a little.
+    pub fn new_shadow(device: Arc<Device>) -> anyhow::Result<Self> {
+        let info = ash::vk::SamplerCreateInfo::default()
+            .mag_filter(ash::vk::Filter::LINEAR)
+            .min_filter(ash::vk::Filter::LINEAR)
+            .mipmap_mode(ash::vk::SamplerMipmapMode::NEAREST)
+            // Anything outside the map is lit.
+            .address_mode_u(ash::vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_v(ash::vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_w(ash::vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .border_color(ash::vk::BorderColor::FLOAT_OPAQUE_WHITE)
+            .compare_enable(true)
+            .compare_op(ash::vk::CompareOp::LESS_OR_EQUAL)
+            .min_lod(0.0)
+            .max_lod(0.0);
+
+        let handle = unsafe { device.handle().create_sampler(&info, None)? };
+
+        Ok(Self { device, handle })
+    }
+
+    pub fn handle(&self) -> ash::vk::Sampler {
+        self.handle
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        unsafe { self.device.handle().destroy_sampler(self.handle, None) };
+    }
+}
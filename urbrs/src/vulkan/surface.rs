@@ -37,6 +37,18 @@ impl Surface {
     pub fn surface_instance(&self) -> &ash::khr::surface::Instance {
         &self.surface_instance
     }
+
+    pub fn capabilities(
+        &self,
+        physical_device: ash::vk::PhysicalDevice,
+    ) -> anyhow::Result<ash::vk::SurfaceCapabilitiesKHR> {
+        let caps = unsafe {
+            self.surface_instance
+                .get_physical_device_surface_capabilities(physical_device, self.handle)?
+        };
+
+        Ok(caps)
+    }
 }
 
 impl Drop for Surface {
@@ -3,7 +3,6 @@ use std::sync::Arc;
 use super::{
     device::{Device, DeviceQueue},
     instance::Instance,
-    phys_device::PhysicalDevice,
     surface::Surface,
     sync::Semaphore,
 };
@@ -23,19 +22,20 @@ pub struct Swapchain {
 
     images: Vec<SwapchainImage>,
     swap_area: ash::vk::Rect2D,
+    // Whether swap images can be copied out of, e.g. for screenshots.
+    supports_readback: bool,
 }
 
 impl Swapchain {
     fn select_swap_extent(
-        physical_device: &PhysicalDevice,
-        window: &winit::window::Window,
+        caps: &ash::vk::SurfaceCapabilitiesKHR,
+        win_size: winit::dpi::PhysicalSize<u32>,
     ) -> ash::vk::Extent2D {
-        let current_extent = physical_device.surface_caps().current_extent;
-        let min_extent = physical_device.surface_caps().min_image_extent;
-        let max_extent = physical_device.surface_caps().max_image_extent;
+        let current_extent = caps.current_extent;
+        let min_extent = caps.min_image_extent;
+        let max_extent = caps.max_image_extent;
 
         if current_extent.height == u32::MAX {
-            let win_size = window.inner_size();
             ash::vk::Extent2D::default()
                 .width(win_size.width.clamp(min_extent.width, max_extent.width))
                 .height(win_size.height.clamp(min_extent.height, max_extent.height))
@@ -44,15 +44,11 @@ impl Swapchain {
         }
     }
 
-    pub fn swap_area(&self) -> ash::vk::Rect2D {
-        self.swap_area
-    }
-
-    fn select_image_count(physical_device: &PhysicalDevice) -> u32 {
+    fn select_image_count(caps: &ash::vk::SurfaceCapabilitiesKHR) -> u32 {
         // Add 1 so we don't wait on the driver.
-        let desired = physical_device.surface_caps().min_image_count + 1;
+        let desired = caps.min_image_count + 1;
 
-        let max_count = physical_device.surface_caps().max_image_count;
+        let max_count = caps.max_image_count;
 
         // A max of zero indicates no limit.
         if max_count == 0 {
@@ -66,14 +62,23 @@ impl Swapchain {
         self.surface_format.format
     }
 
-    pub fn acquire_image(&self, completion: &Semaphore) -> anyhow::Result<&SwapchainImage> {
-        let (idx, _) = unsafe {
+    // Returns None if the swapchain is out of date and has to be recreated before rendering.
+    pub fn acquire_image(&self, completion: &Semaphore) -> anyhow::Result<Option<&SwapchainImage>> {
+        let result = unsafe {
             self.swapchain_device.acquire_next_image(
                 self.handle,
                 1_000_000_000,
                 completion.handle(),
                 ash::vk::Fence::null(),
-            )?
+            )
+        };
+
+        // A suboptimal image can still be rendered to and presented. We'll find out
+        // again from present and recreate afterwards.
+        let idx = match result {
+            Ok((idx, _suboptimal)) => idx,
+            Err(ash::vk::Result::ERROR_OUT_OF_DATE_KHR) => return Ok(None),
+            Err(err) => return Err(err.into()),
         };
 
         let image = self
@@ -81,7 +86,7 @@ impl Swapchain {
             .get(idx as usize)
             .expect("acquired image idx should be correct");
 
-        Ok(image)
+        Ok(Some(image))
     }
 
     pub fn get_image(&self, idx: u32) -> Option<&SwapchainImage> {
@@ -116,12 +121,13 @@ impl Swapchain {
         Ok(device.create_image_view(&info, None)?)
     }
 
+    // Returns true if the swapchain is suboptimal or out of date and should be recreated.
     pub fn present(
         &self,
         idx: u32,
         queue: &DeviceQueue,
         completion: &Semaphore,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<bool> {
         let swapchains = &[self.handle];
         let semaphores = &[completion.handle()];
         let indices = &[idx];
@@ -131,41 +137,75 @@ impl Swapchain {
             .wait_semaphores(semaphores)
             .image_indices(indices);
 
-        unsafe {
+        let result = unsafe {
             self.swapchain_device
                 .queue_present(queue.queue, &present_info)
-        }?;
+        };
 
-        Ok(())
+        match result {
+            Ok(suboptimal) => Ok(suboptimal),
+            Err(ash::vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(true),
+            Err(err) => Err(err.into()),
+        }
     }
 
     pub fn new(
         instance: Arc<Instance>,
         device: Arc<Device>,
         surface: Arc<Surface>,
-        window: &winit::window::Window,
-    ) -> anyhow::Result<Self> {
+        win_size: winit::dpi::PhysicalSize<u32>,
+        old_swapchain: Option<&Swapchain>,
+    ) -> anyhow::Result<Option<Self>> {
         let swapchain_device = ash::khr::swapchain::Device::new(instance.handle(), device.handle());
 
-        let surface_format = device.physical_device().surface_format();
-        let image_count = Self::select_image_count(device.physical_device());
+        let surface_support = device
+            .physical_device()
+            .surface_support()
+            .ok_or(anyhow::anyhow!(
+                "physical device cannot present to a surface"
+            ))?;
+        let present_queue = device
+            .present_queue()
+            .ok_or(anyhow::anyhow!("device has no present queue"))?;
+
+        // Capabilities like the current extent change as the window does, so don't trust
+        // the ones we got when selecting the device.
+        let caps = surface.capabilities(device.physical_device().handle())?;
+
+        let surface_format = surface_support.format;
+        let image_count = Self::select_image_count(&caps);
+
+        let extent = Self::select_swap_extent(&caps, win_size);
+        // A minimized window has no area to present to. There's nothing to create until it's
+        // visible again.
+        if extent.width == 0 || extent.height == 0 {
+            return Ok(None);
+        }
+
+        let supports_readback = caps
+            .supported_usage_flags
+            .contains(ash::vk::ImageUsageFlags::TRANSFER_SRC);
 
-        let extent = Self::select_swap_extent(device.physical_device(), window);
+        let mut image_usage = ash::vk::ImageUsageFlags::COLOR_ATTACHMENT;
+        if supports_readback {
+            image_usage |= ash::vk::ImageUsageFlags::TRANSFER_SRC;
+        }
 
         let mut info = ash::vk::SwapchainCreateInfoKHR::default()
             .surface(*surface.handle())
             .image_format(surface_format.format)
             .image_color_space(surface_format.color_space)
-            .present_mode(device.physical_device().present_mode())
+            .present_mode(surface_support.present_mode)
             .image_extent(extent)
             .min_image_count(image_count)
             .image_array_layers(1)
-            .image_usage(ash::vk::ImageUsageFlags::COLOR_ATTACHMENT)
-            .pre_transform(device.physical_device().surface_caps().current_transform)
-            .composite_alpha(ash::vk::CompositeAlphaFlagsKHR::OPAQUE);
+            .image_usage(image_usage)
+            .pre_transform(caps.current_transform)
+            .composite_alpha(ash::vk::CompositeAlphaFlagsKHR::OPAQUE)
+            .old_swapchain(old_swapchain.map_or(ash::vk::SwapchainKHR::null(), |s| s.handle));
 
-        let image_sharing_required = device.graphics_queue().idx != device.present_queue().idx;
-        let indices = [device.graphics_queue().idx, device.present_queue().idx];
+        let image_sharing_required = device.graphics_queue().idx != present_queue.idx;
+        let indices = [device.graphics_queue().idx, present_queue.idx];
 
         if image_sharing_required {
             info = info
@@ -193,7 +233,7 @@ impl Swapchain {
             });
         }
 
-        Ok(Self {
+        Ok(Some(Self {
             device,
             _surface: surface,
             surface_format,
@@ -201,12 +241,17 @@ impl Swapchain {
             swapchain_device,
             images,
             swap_area: extent.into(),
-        })
+            supports_readback,
+        }))
     }
 
     pub fn extent(&self) -> ash::vk::Extent2D {
         self.swap_area.extent
     }
+
+    pub fn supports_readback(&self) -> bool {
+        self.supports_readback
+    }
 }
 
 impl Drop for Swapchain {
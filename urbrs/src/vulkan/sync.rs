@@ -64,6 +64,10 @@ impl Fence {
         Ok(())
     }
 
+    pub fn is_signaled(&self) -> anyhow::Result<bool> {
+        Ok(unsafe { self.device.handle().get_fence_status(self.handle)? })
+    }
+
     pub fn reset(&self) -> anyhow::Result<()> {
         unsafe { self.device.handle().reset_fences(&[self.handle])? };
 
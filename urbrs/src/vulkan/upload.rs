@@ -0,0 +1,561 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use gpu_allocator::vulkan::AllocationCreateDesc;
+
+use super::{
+    buffer::Buffer,
+    command::{CommandBuffer, CommandPool},
+    context::Context,
+    device::Device,
+    image::Image,
+    sync::{Fence, Semaphore},
+    util,
+};
+
+// Identifies a flushed batch of uploads, so we can check on it or wait for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UploadTicket(u64);
+
+// Command buffers and sync objects for one batch. Reused once the batch completes.
+struct BatchResources {
+    transfer_commands: CommandBuffer,
+    // Only used when the transfer and graphics families differ, to acquire ownership on the graphics queue.
+    graphics_commands: CommandBuffer,
+    released: Semaphore,
+    fence: Fence,
+}
+
+struct PendingBatch {
+    resources: BatchResources,
+    // Recorded on the graphics queue at flush time, after the transfer queue releases everything.
+    acquire_buffer_barriers: Vec<ash::vk::BufferMemoryBarrier2<'static>>,
+    acquire_image_barriers: Vec<ash::vk::ImageMemoryBarrier2<'static>>,
+    dedicated_staging: Vec<Buffer>,
+}
+
+struct InFlightBatch {
+    ticket: UploadTicket,
+    resources: BatchResources,
+    // The ring's tail moves up to here once the batch completes.
+    ring_end: u64,
+    _dedicated_staging: Vec<Buffer>,
+}
+
+// Copies data into GPU-only buffers and images through a staging ring, on the transfer queue.
+//
+// Uploads are recorded into a pending batch until `flush`, which submits them and hands back a ticket.
+// Nothing uploaded is safe to use on the graphics queue until its ticket completes. Whoever owns the
+// uploader should flush or `discard` uploads before dropping it. Anything left over is thrown away,
+// since what it was copying into might already be gone.
+pub struct Uploader {
+    context: Arc<Context>,
+    device: Arc<Device>,
+
+    staging: Buffer,
+    capacity: u64,
+    alignment: u64,
+    // Byte positions that only ever grow. Ring offsets are these modulo capacity.
+    head: u64,
+    tail: u64,
+
+    transfer_pool: CommandPool,
+    graphics_pool: CommandPool,
+
+    pending: Option<PendingBatch>,
+    in_flight: VecDeque<InFlightBatch>,
+    free_resources: Vec<BatchResources>,
+
+    next_ticket: u64,
+    // Every ticket up to and including this one has completed.
+    completed: u64,
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+impl Uploader {
+    pub fn new(context: Arc<Context>, capacity: usize) -> anyhow::Result<Self> {
+        let device = context.device();
+
+        // Offsets also have to be a multiple of the texel size for image copies, which is at most 16 bytes.
+        let alignment = device
+            .physical_device()
+            .limits()
+            .optimal_buffer_copy_offset_alignment
+            .max(16);
+        let capacity = align_up(capacity as u64, alignment);
+
+        let mut staging = Buffer::new(
+            context.clone(),
+            capacity as usize,
+            ash::vk::BufferUsageFlags::TRANSFER_SRC,
+            ash::vk::SharingMode::EXCLUSIVE,
+        )?;
+        staging.allocate(AllocationCreateDesc {
+            name: "upload staging ring",
+            requirements: staging.memory_requirements(),
+            location: gpu_allocator::MemoryLocation::CpuToGpu,
+            linear: true,
+            allocation_scheme: gpu_allocator::vulkan::AllocationScheme::GpuAllocatorManaged,
+        })?;
+
+        let transfer_pool = CommandPool::new(
+            device.clone(),
+            device.transfer_queue(),
+            ash::vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+        )?;
+        let graphics_pool = CommandPool::new(
+            device.clone(),
+            device.graphics_queue(),
+            ash::vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+        )?;
+
+        Ok(Self {
+            context,
+            device,
+            staging,
+            capacity,
+            alignment,
+            head: 0,
+            tail: 0,
+            transfer_pool,
+            graphics_pool,
+            pending: None,
+            in_flight: VecDeque::new(),
+            free_resources: Vec::new(),
+            next_ticket: 1,
+            completed: 0,
+        })
+    }
+
+    fn needs_ownership_transfer(&self) -> bool {
+        self.device.transfer_queue().idx != self.device.graphics_queue().idx
+    }
+
+    fn new_batch_resources(&self) -> anyhow::Result<BatchResources> {
+        Ok(BatchResources {
+            transfer_commands: CommandBuffer::new(self.device.clone(), &self.transfer_pool)?,
+            graphics_commands: CommandBuffer::new(self.device.clone(), &self.graphics_pool)?,
+            released: Semaphore::new(self.device.clone(), ash::vk::SemaphoreCreateFlags::empty())?,
+            fence: Fence::new(self.device.clone(), ash::vk::FenceCreateFlags::empty())?,
+        })
+    }
+
+    fn pending(&mut self) -> anyhow::Result<&mut PendingBatch> {
+        if self.pending.is_none() {
+            let resources = match self.free_resources.pop() {
+                Some(resources) => resources,
+                None => self.new_batch_resources()?,
+            };
+
+            resources
+                .transfer_commands
+                .begin(ash::vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+
+            self.pending = Some(PendingBatch {
+                resources,
+                acquire_buffer_barriers: Vec::new(),
+                acquire_image_barriers: Vec::new(),
+                dedicated_staging: Vec::new(),
+            });
+        }
+
+        Ok(self
+            .pending
+            .as_mut()
+            .expect("pending batch was just created"))
+    }
+
+    // Reserve `size` bytes of the ring, returning the offset. None if there's no room right now.
+    fn ring_alloc(&mut self, size: u64) -> Option<u64> {
+        // Nothing in use, so start from the beginning and give ourselves the most contiguous room.
+        // Batches still in flight remember where the head was when they were submitted, even if
+        // they never used the ring, so wait until they're all retired.
+        if self.head == self.tail && self.in_flight.is_empty() {
+            self.head = 0;
+            self.tail = 0;
+        }
+
+        let offset = self.head % self.capacity;
+        let mut start = align_up(offset, self.alignment);
+
+        // Don't split an allocation across the end of the ring, skip to the start instead.
+        if start + size > self.capacity {
+            start = self.capacity;
+        }
+
+        let new_head = self.head + (start - offset) + size;
+        if new_head - self.tail > self.capacity {
+            return None;
+        }
+
+        self.head = new_head;
+
+        Some(start % self.capacity)
+    }
+
+    // Copy `data` somewhere the transfer queue can read it from. Returns the buffer and offset it landed at.
+    fn stage<T: Copy>(&mut self, data: &[T]) -> anyhow::Result<(ash::vk::Buffer, u64)> {
+        let size = size_of_val(data) as u64;
+
+        if size > self.capacity {
+            // Too big for the ring no matter what, so give it a buffer of its own.
+            let mut buffer = Buffer::new(
+                self.context.clone(),
+                size as usize,
+                ash::vk::BufferUsageFlags::TRANSFER_SRC,
+                ash::vk::SharingMode::EXCLUSIVE,
+            )?;
+            buffer.allocate(AllocationCreateDesc {
+                name: "upload dedicated staging",
+                requirements: buffer.memory_requirements(),
+                location: gpu_allocator::MemoryLocation::CpuToGpu,
+                linear: true,
+                allocation_scheme: gpu_allocator::vulkan::AllocationScheme::GpuAllocatorManaged,
+            })?;
+
+            let mut slab = buffer
+                .allocation_mut()
+                .and_then(|a| a.try_as_mapped_slab())
+                .expect("staging buffer should be valid mapped slab");
+            presser::copy_from_slice_to_offset(data, &mut slab, 0)?;
+
+            let handle = buffer.handle();
+            self.pending()?.dedicated_staging.push(buffer);
+
+            return Ok((handle, 0));
+        }
+
+        let offset = loop {
+            if let Some(offset) = self.ring_alloc(size) {
+                break offset;
+            }
+
+            // The ring is full. Submit what we have and wait for the oldest batch to free up some room.
+            if self.pending.is_some() {
+                self.flush()?;
+            }
+
+            let oldest = self
+                .in_flight
+                .front()
+                .expect("a full ring should have batches in flight");
+            oldest.resources.fence.wait(u64::MAX)?;
+            self.poll()?;
+        };
+
+        let mut slab = self
+            .staging
+            .allocation_mut()
+            .and_then(|a| a.try_as_mapped_slab())
+            .expect("staging ring should be valid mapped slab");
+        presser::copy_from_slice_to_offset(data, &mut slab, offset as usize)?;
+
+        Ok((self.staging.handle(), offset))
+    }
+
+    // Upload `data` to the start of `dst`, which must have been created with TRANSFER_DST usage.
+    pub fn upload_buffer<T: Copy>(&mut self, dst: &Buffer, data: &[T]) -> anyhow::Result<()> {
+        let size = size_of_val(data) as u64;
+        if size == 0 {
+            return Ok(());
+        }
+
+        let (src, src_offset) = self.stage(data)?;
+
+        let transfer_family = self.device.transfer_queue().idx;
+        let graphics_family = self.device.graphics_queue().idx;
+        let ownership_transfer = self.needs_ownership_transfer();
+        let device = self.device.clone();
+        let pending = self.pending()?;
+        let commands = &pending.resources.transfer_commands;
+
+        let region = ash::vk::BufferCopy::default()
+            .src_offset(src_offset)
+            .dst_offset(0)
+            .size(size);
+
+        unsafe {
+            device
+                .handle()
+                .cmd_copy_buffer(commands.handle(), src, dst.handle(), &[region])
+        };
+
+        // We don't know what the buffer is for, so make it visible to anything that reads it.
+        let barrier = ash::vk::BufferMemoryBarrier2::default()
+            .src_stage_mask(ash::vk::PipelineStageFlags2::COPY)
+            .src_access_mask(ash::vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(ash::vk::PipelineStageFlags2::ALL_COMMANDS)
+            .dst_access_mask(ash::vk::AccessFlags2::MEMORY_READ)
+            .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+            .buffer(dst.handle())
+            .offset(0)
+            .size(ash::vk::WHOLE_SIZE);
+
+        if ownership_transfer {
+            let release = barrier
+                .dst_stage_mask(ash::vk::PipelineStageFlags2::NONE)
+                .dst_access_mask(ash::vk::AccessFlags2::NONE)
+                .src_queue_family_index(transfer_family)
+                .dst_queue_family_index(graphics_family);
+
+            let acquire = barrier
+                .src_stage_mask(ash::vk::PipelineStageFlags2::NONE)
+                .src_access_mask(ash::vk::AccessFlags2::NONE)
+                .src_queue_family_index(transfer_family)
+                .dst_queue_family_index(graphics_family);
+
+            record_barriers(&device, commands, &[release], &[]);
+            pending.acquire_buffer_barriers.push(acquire);
+        } else {
+            record_barriers(&device, commands, &[barrier], &[]);
+        }
+
+        Ok(())
+    }
+
+    // Upload every region of a color image. Region buffer offsets are relative to the start of `data`.
+    // The image ends up in SHADER_READ_ONLY_OPTIMAL, ready to sample from fragment shaders.
+    pub fn upload_image(
+        &mut self,
+        dst: &Image,
+        data: &[u8],
+        regions: &[ash::vk::BufferImageCopy],
+    ) -> anyhow::Result<()> {
+        let (src, src_offset) = self.stage(data)?;
+
+        let transfer_family = self.device.transfer_queue().idx;
+        let graphics_family = self.device.graphics_queue().idx;
+        let ownership_transfer = self.needs_ownership_transfer();
+        let device = self.device.clone();
+        let pending = self.pending()?;
+        let commands = &pending.resources.transfer_commands;
+
+        let regions: Vec<ash::vk::BufferImageCopy> = regions
+            .iter()
+            .map(|region| region.buffer_offset(region.buffer_offset + src_offset))
+            .collect();
+
+        util::upload_transition(device.clone(), commands, dst.handle());
+
+        unsafe {
+            device.handle().cmd_copy_buffer_to_image(
+                commands.handle(),
+                src,
+                dst.handle(),
+                ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &regions,
+            );
+        }
+
+        let range = ash::vk::ImageSubresourceRange::default()
+            .aspect_mask(ash::vk::ImageAspectFlags::COLOR)
+            .base_mip_level(0)
+            .level_count(ash::vk::REMAINING_MIP_LEVELS)
+            .base_array_layer(0)
+            .layer_count(ash::vk::REMAINING_ARRAY_LAYERS);
+
+        let barrier = ash::vk::ImageMemoryBarrier2::default()
+            .src_stage_mask(ash::vk::PipelineStageFlags2::COPY)
+            .src_access_mask(ash::vk::AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(ash::vk::PipelineStageFlags2::FRAGMENT_SHADER)
+            .dst_access_mask(ash::vk::AccessFlags2::SHADER_SAMPLED_READ)
+            .old_layout(ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(ash::vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .src_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(ash::vk::QUEUE_FAMILY_IGNORED)
+            .image(dst.handle())
+            .subresource_range(range);
+
+        if ownership_transfer {
+            // Both halves of the transfer have to do the same layout transition.
+            let release = barrier
+                .dst_stage_mask(ash::vk::PipelineStageFlags2::NONE)
+                .dst_access_mask(ash::vk::AccessFlags2::NONE)
+                .src_queue_family_index(transfer_family)
+                .dst_queue_family_index(graphics_family);
+
+            let acquire = barrier
+                .src_stage_mask(ash::vk::PipelineStageFlags2::NONE)
+                .src_access_mask(ash::vk::AccessFlags2::NONE)
+                .src_queue_family_index(transfer_family)
+                .dst_queue_family_index(graphics_family);
+
+            record_barriers(&device, commands, &[], &[release]);
+            pending.acquire_image_barriers.push(acquire);
+        } else {
+            record_barriers(&device, commands, &[], &[barrier]);
+        }
+
+        Ok(())
+    }
+
+    // Submit everything uploaded since the last flush. The returned ticket completes once it's all landed.
+    pub fn flush(&mut self) -> anyhow::Result<UploadTicket> {
+        let Some(pending) = self.pending.take() else {
+            // Nothing new, so this is as done as the last batch.
+            return Ok(UploadTicket(self.next_ticket - 1));
+        };
+
+        let resources = &pending.resources;
+        resources.transfer_commands.end()?;
+        resources.fence.reset()?;
+
+        let transfer_buffers = &[resources.transfer_commands.submit_info()];
+
+        if self.needs_ownership_transfer() {
+            let signals = &[resources
+                .released
+                .submit_info(ash::vk::PipelineStageFlags2::ALL_COMMANDS)];
+
+            let transfer_submit = ash::vk::SubmitInfo2::default()
+                .command_buffer_infos(transfer_buffers)
+                .signal_semaphore_infos(signals);
+
+            unsafe {
+                self.device.handle().queue_submit2(
+                    self.device.transfer_queue().queue,
+                    &[transfer_submit],
+                    ash::vk::Fence::null(),
+                )?
+            };
+
+            resources
+                .graphics_commands
+                .begin(ash::vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+            record_barriers(
+                &self.device,
+                &resources.graphics_commands,
+                &pending.acquire_buffer_barriers,
+                &pending.acquire_image_barriers,
+            );
+            resources.graphics_commands.end()?;
+
+            let waits = &[resources
+                .released
+                .submit_info(ash::vk::PipelineStageFlags2::ALL_COMMANDS)];
+            let graphics_buffers = &[resources.graphics_commands.submit_info()];
+
+            let graphics_submit = ash::vk::SubmitInfo2::default()
+                .command_buffer_infos(graphics_buffers)
+                .wait_semaphore_infos(waits);
+
+            unsafe {
+                self.device.handle().queue_submit2(
+                    self.device.graphics_queue().queue,
+                    &[graphics_submit],
+                    resources.fence.handle(),
+                )?
+            };
+        } else {
+            let transfer_submit =
+                ash::vk::SubmitInfo2::default().command_buffer_infos(transfer_buffers);
+
+            unsafe {
+                self.device.handle().queue_submit2(
+                    self.device.transfer_queue().queue,
+                    &[transfer_submit],
+                    resources.fence.handle(),
+                )?
+            };
+        }
+
+        let ticket = UploadTicket(self.next_ticket);
+        self.next_ticket += 1;
+
+        self.in_flight.push_back(InFlightBatch {
+            ticket,
+            resources: pending.resources,
+            ring_end: self.head,
+            _dedicated_staging: pending.dedicated_staging,
+        });
+
+        Ok(ticket)
+    }
+
+    // Throw away everything uploaded since the last flush, e.g. because what it was copying into
+    // failed to load and has already been dropped.
+    pub fn discard(&mut self) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+
+        // The pending batch's staging space is everything past where the last flush left the head.
+        self.head = self
+            .in_flight
+            .back()
+            .map_or(self.tail, |batch| batch.ring_end);
+
+        // Its command buffer can only be begun again once it's been ended.
+        if pending.resources.transfer_commands.end().is_ok() {
+            self.free_resources.push(pending.resources);
+        }
+    }
+
+    // Retire finished batches, freeing their staging space.
+    pub fn poll(&mut self) -> anyhow::Result<()> {
+        while let Some(batch) = self.in_flight.front() {
+            if !batch.resources.fence.is_signaled()? {
+                break;
+            }
+
+            let batch = self
+                .in_flight
+                .pop_front()
+                .expect("front batch was just checked");
+
+            self.tail = batch.ring_end;
+            self.completed = batch.ticket.0;
+            self.free_resources.push(batch.resources);
+        }
+
+        Ok(())
+    }
+
+    pub fn is_complete(&mut self, ticket: UploadTicket) -> anyhow::Result<bool> {
+        self.poll()?;
+
+        Ok(ticket.0 <= self.completed)
+    }
+
+    // Block until everything in `ticket`'s batch has landed.
+    pub fn wait(&mut self, ticket: UploadTicket) -> anyhow::Result<()> {
+        while !self.is_complete(ticket)? {
+            let oldest = self
+                .in_flight
+                .front()
+                .expect("incomplete tickets should have batches in flight");
+            oldest.resources.fence.wait(u64::MAX)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Uploader {
+    fn drop(&mut self) {
+        // The staging memory and command buffers have to outlive any copies still running.
+        for batch in &self.in_flight {
+            batch.resources.fence.wait(u64::MAX).unwrap();
+        }
+    }
+}
+
+fn record_barriers(
+    device: &Device,
+    command_buffer: &CommandBuffer,
+    buffer_barriers: &[ash::vk::BufferMemoryBarrier2],
+    image_barriers: &[ash::vk::ImageMemoryBarrier2],
+) {
+    let dep_info = ash::vk::DependencyInfo::default()
+        .buffer_memory_barriers(buffer_barriers)
+        .image_memory_barriers(image_barriers);
+
+    unsafe {
+        device
+            .handle()
+            .cmd_pipeline_barrier2(command_buffer.handle(), &dep_info)
+    };
+}
@@ -1,55 +1,25 @@
-use std::{
-    fs,
-    io::{self, BufReader, Read, Seek},
-    ops::{Div, Rem},
-    path::Path,
-    sync::Arc,
-};
+use std::{ops::Rem, sync::Arc};
 
 use super::{command::CommandBuffer, device::Device};
 
-pub fn read_spirv(path: &Path) -> anyhow::Result<Vec<u32>> {
-    let mut file = fs::File::open(path)?;
-
-    // Get the size of file - need two seek ops for this.
-    let size = file.seek(io::SeekFrom::End(0))?;
-    file.rewind()?;
-
-    let data_len = size.div(4);
-
-    // We expect a file of 4 byte words for SPIR-V.
-    let remainder = size.rem(4);
-    if remainder != 0 {
-        return Err(anyhow::anyhow!("SPIR-V file size was not a multiple of 4"));
-    }
-
-    let mut reader = BufReader::new(file);
-
-    let mut data: Vec<u32> = Vec::with_capacity(data_len as usize);
-    let mut bytes: [u8; 4] = [0, 0, 0, 0];
-
-    loop {
-        match reader.read_exact(&mut bytes) {
-            Ok(_) => {
-                data.push(u32::from_le_bytes(bytes));
-            }
-            Err(err) => {
-                if err.kind() == io::ErrorKind::UnexpectedEof {
-                    break;
-                } else {
-                    return Err(err.into());
-                }
-            }
-        }
+// SPIR-V is a stream of little endian 4 byte words.
+pub fn spirv_from_bytes(bytes: &[u8]) -> anyhow::Result<Vec<u32>> {
+    if bytes.len().rem(4) != 0 {
+        return Err(anyhow::anyhow!("SPIR-V size was not a multiple of 4"));
     }
 
-    return Ok(data);
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect())
 }
 
+// Where an image is at, or needs to be, on one side of a barrier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct ImageBarrierState {
-    layout: ash::vk::ImageLayout,
-    stage: ash::vk::PipelineStageFlags2,
-    access: ash::vk::AccessFlags2,
+    pub layout: ash::vk::ImageLayout,
+    pub stage: ash::vk::PipelineStageFlags2,
+    pub access: ash::vk::AccessFlags2,
 }
 
 fn transition_image(
@@ -81,7 +51,7 @@ fn transition_image(
     };
 }
 
-fn get_subresource_range(aspect: ash::vk::ImageAspectFlags) -> ash::vk::ImageSubresourceRange {
+pub fn get_subresource_range(aspect: ash::vk::ImageAspectFlags) -> ash::vk::ImageSubresourceRange {
     ash::vk::ImageSubresourceRange::default()
         .base_array_layer(0)
         .base_mip_level(0)
@@ -90,43 +60,22 @@ fn get_subresource_range(aspect: ash::vk::ImageAspectFlags) -> ash::vk::ImageSub
         .aspect_mask(aspect)
 }
 
-pub fn swap_acquire_transition(
+// Get a freshly created image ready to be copied into.
+pub fn upload_transition(
     device: Arc<Device>,
     command_buffer: &CommandBuffer,
     image: ash::vk::Image,
 ) {
     let src_state = ImageBarrierState {
         layout: ash::vk::ImageLayout::UNDEFINED,
-        stage: ash::vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        stage: ash::vk::PipelineStageFlags2::NONE,
         access: ash::vk::AccessFlags2::empty(),
     };
 
     let dst_state = ImageBarrierState {
-        layout: ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-        stage: ash::vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
-        access: ash::vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
-    };
-
-    let range = get_subresource_range(ash::vk::ImageAspectFlags::COLOR);
-
-    transition_image(device, command_buffer, image, range, src_state, dst_state);
-}
-
-pub fn swap_present_transition(
-    device: Arc<Device>,
-    command_buffer: &CommandBuffer,
-    image: ash::vk::Image,
-) {
-    let src_state = ImageBarrierState {
-        layout: ash::vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-        stage: ash::vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
-        access: ash::vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
-    };
-
-    let dst_state = ImageBarrierState {
-        layout: ash::vk::ImageLayout::PRESENT_SRC_KHR,
-        stage: ash::vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
-        access: ash::vk::AccessFlags2::empty(),
+        layout: ash::vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        stage: ash::vk::PipelineStageFlags2::COPY,
+        access: ash::vk::AccessFlags2::TRANSFER_WRITE,
     };
 
     let range = get_subresource_range(ash::vk::ImageAspectFlags::COLOR);
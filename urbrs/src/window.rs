@@ -1,21 +1,30 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
 use winit::{
     dpi::PhysicalSize,
+    event::{DeviceEvent, WindowEvent},
     event_loop::ActiveEventLoop,
+    keyboard::KeyCode,
     raw_window_handle::{HasDisplayHandle, HasWindowHandle},
 };
 
-use crate::{renderer::Renderer, vulkan::context::Context};
+use crate::{
+    assets::Assets,
+    input::Input,
+    renderer::{post::Tonemapper, target::RenderTarget, Renderer},
+    vulkan::context::Context,
+};
 
 pub struct Window {
     handle: winit::window::Window,
     context: Arc<Context>,
     renderer: Renderer,
+    input: Input,
+    title: String,
 }
 
 impl Window {
-    pub fn new(event_loop: &ActiveEventLoop) -> anyhow::Result<Self> {
+    pub fn new(event_loop: &ActiveEventLoop, assets: Assets) -> anyhow::Result<Self> {
         let winit_window = event_loop.create_window(
             winit::window::WindowAttributes::default()
                 .with_title("urbrs")
@@ -26,32 +35,94 @@ impl Window {
 
         let raw_window_handle = winit_window.window_handle()?.as_raw();
 
-        let context = Arc::new(Context::new(
-            &winit_window,
-            display_handle,
-            raw_window_handle,
-        )?);
+        let context = Arc::new(Context::new(display_handle, raw_window_handle)?);
+
+        let swapchain = context
+            .create_swapchain(winit_window.inner_size(), None)?
+            .ok_or(anyhow::anyhow!("window has no area to render to"))?;
 
-        let renderer = Renderer::new(
+        let mut renderer = Renderer::new(
             context.clone(),
-            context.swapchain(),
-            winit_window.inner_size(),
+            RenderTarget::Swapchain(Arc::new(swapchain)),
+            assets,
         )?;
 
+        // Pick up rebuilt shaders and models without restarting. Not being able to is no reason
+        // not to open the window.
+        if let Err(err) = renderer.enable_hot_reload() {
+            eprintln!("hot reloading is off: {err:?}");
+        }
+
         Ok(Self {
             handle: winit_window,
             context,
             renderer,
+            input: Input::new(),
+            title: String::from("urbrs"),
         })
     }
 
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        self.input.handle_window_event(event);
+    }
+
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        self.input.handle_device_event(event);
+    }
+
     pub fn render(&mut self) -> anyhow::Result<()> {
+        self.renderer.update_camera(&self.input);
+
+        // T flips through the tonemappers, to compare them.
+        if self.input.was_key_pressed(KeyCode::KeyT) {
+            let tonemapper = self.renderer.tonemapper().next();
+            self.renderer.set_tonemapper(tonemapper);
+        }
+
+        self.input.end_frame();
+
         self.renderer.render()?;
+
+        let stats = self.renderer.stats();
+        let title = format!(
+            "urbrs - {} drawn, {} culled, {:?}",
+            stats.objects_drawn,
+            stats.objects_culled,
+            self.renderer.tonemapper()
+        );
+        // Setting the title isn't free on every platform, so only do it when it changes.
+        if title != self.title {
+            self.handle.set_title(&title);
+            self.title = title;
+        }
+
         self.handle.request_redraw();
 
         Ok(())
     }
 
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        self.renderer.resize(size);
+    }
+
+    // Returns the sample count we actually got, see `Renderer::set_msaa_samples`.
+    pub fn set_msaa_samples(&mut self, samples: u32) -> anyhow::Result<u32> {
+        self.renderer.set_msaa_samples(samples)
+    }
+
+    pub fn set_tonemapper(&mut self, tonemapper: Tonemapper) {
+        self.renderer.set_tonemapper(tonemapper);
+    }
+
+    // In stops.
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.renderer.set_exposure(exposure);
+    }
+
+    pub fn request_screenshot(&mut self, path: PathBuf) {
+        self.renderer.request_screenshot(path);
+    }
+
     pub fn exit(&self) -> anyhow::Result<()> {
         self.context.wait_idle()?;
 
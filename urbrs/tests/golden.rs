@@ -0,0 +1,243 @@
+// Golden image tests: render fixed scenes headlessly and compare against checked-in references.
+//
+// These need a Vulkan device (lavapipe works) and processed assets in `data/`, so they're
+// ignored by default. Run them with `cargo make golden`, and `cargo make bless` to (re)write
+// the reference images from the current renderer output.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Once,
+};
+
+use urbrs::{
+    assets::{Assets, DATA_DIR},
+    camera::{ArcballPose, Projection},
+    headless::Headless,
+    renderer::readback::Screenshot,
+};
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 256;
+
+// Maximum per-channel difference before a pixel counts as mismatched.
+const CHANNEL_TOLERANCE: u8 = 3;
+// Fraction of pixels allowed to mismatch, to absorb rasterization differences between drivers.
+const MAX_MISMATCHED_RATIO: f64 = 0.001;
+
+fn reference_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden")
+}
+
+fn enter_workspace_root() {
+    static ONCE: Once = Once::new();
+
+    // The renderer loads assets relative to the working directory, same as `cargo make start`.
+    ONCE.call_once(|| {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .expect("urbrs should live inside the workspace");
+        std::env::set_current_dir(root).expect("workspace root should be accessible");
+    });
+}
+
+fn render(pose: ArcballPose) -> Screenshot {
+    render_with_projection(pose, None)
+}
+
+// Like `render`, but with a projection other than the default.
+fn render_with_projection(pose: ArcballPose, projection: Option<Projection>) -> Screenshot {
+    enter_workspace_root();
+
+    let assets = Assets::open(Path::new(DATA_DIR)).expect("assets should be processed");
+    let mut headless =
+        Headless::new(WIDTH, HEIGHT, assets).expect("headless renderer should initialize");
+    headless.set_fixed_camera(Some(pose));
+    if let Some(projection) = projection {
+        headless.set_projection(projection);
+    }
+
+    let screenshot = headless.capture().expect("frame capture should succeed");
+    headless.exit().expect("device should go idle");
+
+    screenshot
+}
+
+struct Comparison {
+    mismatched: usize,
+    max_diff: u8,
+    diff_image: Screenshot,
+}
+
+fn compare(actual: &Screenshot, reference: &Screenshot) -> Comparison {
+    let mut mismatched = 0;
+    let mut max_diff = 0;
+    let mut diff_pixels = Vec::with_capacity(actual.pixels.len());
+
+    for (a, r) in actual
+        .pixels
+        .chunks_exact(4)
+        .zip(reference.pixels.chunks_exact(4))
+    {
+        let diff = a
+            .iter()
+            .zip(r)
+            .map(|(a, r)| a.abs_diff(*r))
+            .max()
+            .unwrap_or(0);
+        max_diff = max_diff.max(diff);
+
+        if diff > CHANNEL_TOLERANCE {
+            mismatched += 1;
+            diff_pixels.extend_from_slice(&[u8::MAX, 0, 0, u8::MAX]);
+        } else {
+            // Dim matching pixels so the mismatches stand out.
+            diff_pixels.extend_from_slice(&[a[0] / 4, a[1] / 4, a[2] / 4, u8::MAX]);
+        }
+    }
+
+    Comparison {
+        mismatched,
+        max_diff,
+        diff_image: Screenshot {
+            width: actual.width,
+            height: actual.height,
+            pixels: diff_pixels,
+        },
+    }
+}
+
+fn check_golden(name: &str, actual: Screenshot) {
+    let reference_path = reference_dir().join(format!("{name}.png"));
+
+    if std::env::var_os("URBRS_BLESS").is_some() {
+        std::fs::create_dir_all(reference_dir()).unwrap();
+        actual.save_png(&reference_path).unwrap();
+        println!("blessed {}", reference_path.display());
+        return;
+    }
+
+    let out_dir = output_dir();
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let actual_path = out_dir.join(format!("{name}.actual.png"));
+    actual.save_png(&actual_path).unwrap();
+
+    assert!(
+        reference_path.exists(),
+        "{name}: there's no reference image at {}.\n\
+         Check this run's output at {} and, if it looks right, bless it with `cargo make bless` \
+         (or set URBRS_BLESS=1) and commit the reference.",
+        reference_path.display(),
+        actual_path.display(),
+    );
+    let reference = Screenshot::load_png(&reference_path).unwrap_or_else(|err| {
+        panic!(
+            "{name}: could not load reference {}: {err}. Bless it again with `cargo make bless`.",
+            reference_path.display()
+        )
+    });
+
+    assert_eq!(
+        (actual.width, actual.height),
+        (reference.width, reference.height),
+        "{name}: size differs from reference"
+    );
+
+    let comparison = compare(&actual, &reference);
+
+    let total = (actual.width * actual.height) as usize;
+    let ratio = comparison.mismatched as f64 / total as f64;
+
+    if ratio > MAX_MISMATCHED_RATIO {
+        let diff_path = out_dir.join(format!("{name}.diff.png"));
+        comparison.diff_image.save_png(&diff_path).unwrap();
+
+        panic!(
+            "{name}: {} of {total} pixels differ from the reference (max channel diff {}).\n\
+             actual: {}\n\
+             diff: {}",
+            comparison.mismatched,
+            comparison.max_diff,
+            actual_path.display(),
+            diff_path.display(),
+        );
+    }
+}
+
+#[test]
+#[ignore = "requires a Vulkan device and processed assets"]
+fn jerma_front() {
+    let pose = ArcballPose {
+        target: glam::vec3(0.5, 0.5, 0.5),
+        angles: glam::vec2(f32::to_radians(-15.0), 0.0),
+        dist: 100.0,
+    };
+
+    check_golden("jerma_front", render(pose));
+}
+
+#[test]
+#[ignore = "requires a Vulkan device and processed assets"]
+fn jerma_side() {
+    let pose = ArcballPose {
+        target: glam::vec3(0.5, 0.5, 0.5),
+        angles: glam::vec2(f32::to_radians(-15.0), f32::to_radians(90.0)),
+        dist: 100.0,
+    };
+
+    check_golden("jerma_side", render(pose));
+}
+
+#[test]
+#[ignore = "requires a Vulkan device and processed assets"]
+fn jerma_above() {
+    let pose = ArcballPose {
+        target: glam::vec3(0.5, 0.5, 0.5),
+        angles: glam::vec2(f32::to_radians(-75.0), f32::to_radians(45.0)),
+        dist: 100.0,
+    };
+
+    check_golden("jerma_above", render(pose));
+}
+
+#[test]
+#[ignore = "requires a Vulkan device and processed assets"]
+fn jerma_front_reverse_z() {
+    let pose = ArcballPose {
+        target: glam::vec3(0.5, 0.5, 0.5),
+        angles: glam::vec2(f32::to_radians(-15.0), 0.0),
+        dist: 100.0,
+    };
+    let projection = Projection::ReverseZ {
+        fov: f32::to_radians(45.0),
+        near: 0.01,
+    };
+
+    check_golden(
+        "jerma_front_reverse_z",
+        render_with_projection(pose, Some(projection)),
+    );
+}
+
+#[test]
+#[ignore = "requires a Vulkan device and processed assets"]
+fn jerma_front_ortho() {
+    let pose = ArcballPose {
+        target: glam::vec3(0.5, 0.5, 0.5),
+        angles: glam::vec2(f32::to_radians(-15.0), 0.0),
+        dist: 100.0,
+    };
+    let projection = Projection::Orthographic {
+        height: 80.0,
+        near: 0.01,
+        far: 10000.0,
+    };
+
+    check_golden(
+        "jerma_front_ortho",
+        render_with_projection(pose, Some(projection)),
+    );
+}